@@ -0,0 +1,5 @@
+ALTER TABLE token
+    ADD COLUMN IF NOT EXISTS sync_state VARCHAR(16) NOT NULL DEFAULT 'backfilling',
+    ADD COLUMN IF NOT EXISTS head_block BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS backfill_rate DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS sync_error TEXT;
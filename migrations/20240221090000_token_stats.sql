@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS token_stats (
+    token_id INT PRIMARY KEY,
+    last_checked_block BIGINT NOT NULL,
+    holder_count BIGINT NOT NULL,
+    total_supply NUMERIC(78, 0) NOT NULL,
+    top10_share DOUBLE PRECISION NOT NULL,
+    top100_share DOUBLE PRECISION NOT NULL,
+    gini DOUBLE PRECISION NOT NULL,
+    herfindahl DOUBLE PRECISION NOT NULL,
+    nakamoto BIGINT NOT NULL,
+    CONSTRAINT fk_token
+      FOREIGN KEY(token_id)
+      REFERENCES token(token_id)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS token_history (
+    token_id INT NOT NULL,
+    block_number BIGINT NOT NULL,
+    holder_count BIGINT NOT NULL,
+    total_supply NUMERIC(78, 0) NOT NULL,
+    PRIMARY KEY (token_id, block_number),
+    CONSTRAINT fk_token
+      FOREIGN KEY(token_id)
+      REFERENCES token(token_id)
+);
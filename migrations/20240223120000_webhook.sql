@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS webhook (
+    webhook_id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    event VARCHAR(16) NOT NULL,
+    token_id INT,
+    holder_addr BYTEA,
+    min_amount NUMERIC(78, 0),
+    CONSTRAINT fk_token
+      FOREIGN KEY(token_id)
+      REFERENCES token(token_id)
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    delivery_id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    webhook_id INT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    status_code INT,
+    delivered BOOLEAN NOT NULL DEFAULT FALSE,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT fk_webhook
+      FOREIGN KEY(webhook_id)
+      REFERENCES webhook(webhook_id)
+      ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_id ON webhook_delivery (webhook_id);
@@ -0,0 +1,2 @@
+-- Keyset pagination of token balances by amount
+CREATE INDEX IF NOT EXISTS idx_token_amount ON balance (token_id, amount, holder_id);
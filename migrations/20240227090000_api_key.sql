@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS api_key (
+    api_key_id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    key_hash BYTEA NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    rate_limit INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
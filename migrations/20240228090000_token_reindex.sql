@@ -0,0 +1,2 @@
+ALTER TABLE token
+    ADD COLUMN IF NOT EXISTS reindex_block BIGINT;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS job (
+    job_id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    contract_addr BYTEA NOT NULL,
+    start_block BIGINT,
+    state VARCHAR(16) NOT NULL DEFAULT 'pending',
+    token_id INT,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_pending ON job (job_id) WHERE state = 'pending';
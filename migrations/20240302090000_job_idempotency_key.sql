@@ -0,0 +1,3 @@
+ALTER TABLE job ADD COLUMN IF NOT EXISTS idempotency_key TEXT UNIQUE;
+
+CREATE INDEX IF NOT EXISTS idx_job_contract_addr ON job (contract_addr);
@@ -0,0 +1 @@
+ALTER TABLE token_stats ADD COLUMN IF NOT EXISTS calculated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,5 @@
+-- Running job is claimed again by another indexer after its lease expires
+ALTER TABLE job ADD COLUMN IF NOT EXISTS claimed_by TEXT;
+ALTER TABLE job ADD COLUMN IF NOT EXISTS lease_expires_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_job_running ON job (lease_expires_at) WHERE state = 'running';
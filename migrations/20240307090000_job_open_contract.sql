@@ -0,0 +1,12 @@
+-- Only one pending or running job per contract, later duplicates are failed first
+UPDATE job SET state = 'failed', error = 'Duplicate of earlier job', updated_at = NOW()
+    WHERE state IN ('pending', 'running')
+        AND EXISTS (
+            SELECT 1 FROM job AS earlier
+            WHERE earlier.contract_addr = job.contract_addr
+                AND earlier.state IN ('pending', 'running')
+                AND earlier.job_id < job.job_id
+        );
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_job_open_contract ON job (contract_addr)
+    WHERE state IN ('pending', 'running');
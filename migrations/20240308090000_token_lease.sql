@@ -0,0 +1,3 @@
+-- Token is indexed by one indexer at a time, another one takes it over after lease expires
+ALTER TABLE token ADD COLUMN IF NOT EXISTS owner TEXT;
+ALTER TABLE token ADD COLUMN IF NOT EXISTS lease_expires_at TIMESTAMPTZ;
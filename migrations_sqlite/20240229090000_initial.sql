@@ -0,0 +1,30 @@
+-- Addresses are lowercase hex without 0x prefix.
+-- Amounts are zero padded to 78 digits to compare them as text,
+-- negative amounts of mint and burn address start with '-'
+
+CREATE TABLE IF NOT EXISTS token (
+    token_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    contract_addr TEXT UNIQUE NOT NULL,
+    last_checked_block INTEGER NOT NULL,
+    symbol TEXT NOT NULL,
+    decimals INTEGER NOT NULL,
+    sync_state TEXT NOT NULL DEFAULT 'backfilling',
+    head_block INTEGER NOT NULL DEFAULT 0,
+    backfill_rate REAL NOT NULL DEFAULT 0,
+    sync_error TEXT,
+    reindex_block INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS holder (
+    holder_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    holder_addr TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS balance (
+    holder_id INTEGER NOT NULL REFERENCES holder (holder_id),
+    token_id INTEGER NOT NULL REFERENCES token (token_id),
+    amount TEXT NOT NULL,
+    PRIMARY KEY (holder_id, token_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_amount ON balance (token_id, amount, holder_id);
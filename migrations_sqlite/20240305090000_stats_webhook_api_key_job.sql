@@ -0,0 +1,71 @@
+-- Times compared by storage are julian days of julianday('now'),
+-- times returned by api are text of datetime('now')
+
+CREATE TABLE IF NOT EXISTS token_stats (
+    token_id INTEGER PRIMARY KEY REFERENCES token (token_id),
+    last_checked_block INTEGER NOT NULL,
+    holder_count INTEGER NOT NULL,
+    total_supply TEXT NOT NULL,
+    top10_share REAL NOT NULL,
+    top100_share REAL NOT NULL,
+    gini REAL NOT NULL,
+    herfindahl REAL NOT NULL,
+    nakamoto INTEGER NOT NULL,
+    calculated_at REAL NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS token_history (
+    token_id INTEGER NOT NULL REFERENCES token (token_id),
+    block_number INTEGER NOT NULL,
+    holder_count INTEGER NOT NULL,
+    total_supply TEXT NOT NULL,
+    PRIMARY KEY (token_id, block_number)
+);
+
+CREATE TABLE IF NOT EXISTS webhook (
+    webhook_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    event TEXT NOT NULL,
+    token_id INTEGER REFERENCES token (token_id),
+    holder_addr TEXT,
+    min_amount TEXT
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    delivery_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhook (webhook_id) ON DELETE CASCADE,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    status_code INTEGER,
+    delivered INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    next_attempt_at REAL NOT NULL DEFAULT (julianday('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_id ON webhook_delivery (webhook_id);
+
+-- Scopes are joined by comma
+CREATE TABLE IF NOT EXISTS api_key (
+    api_key_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    key_hash TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    rate_limit INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE IF NOT EXISTS job (
+    job_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    contract_addr TEXT NOT NULL,
+    start_block INTEGER,
+    state TEXT NOT NULL DEFAULT 'pending',
+    token_id INTEGER,
+    error TEXT,
+    idempotency_key TEXT UNIQUE,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_contract_addr ON job (contract_addr);
@@ -0,0 +1,4 @@
+-- Running job is claimed again by another indexer after its lease expires,
+-- expiry is julian day
+ALTER TABLE job ADD COLUMN claimed_by TEXT;
+ALTER TABLE job ADD COLUMN lease_expires_at REAL;
@@ -0,0 +1,4 @@
+-- Token is indexed by one indexer at a time, another one takes it over after lease expires,
+-- expiry is julian day
+ALTER TABLE token ADD COLUMN owner TEXT;
+ALTER TABLE token ADD COLUMN lease_expires_at REAL;
@@ -0,0 +1,154 @@
+//! Signed token amounts with magnitude of full uint256 range
+
+use anyhow::{anyhow, Error, Result};
+use ethers::types::U256;
+use std::{cmp::Ordering, fmt, ops::Neg, str::FromStr};
+
+/// Balance or balance change of holder. Balances of holders are at most
+/// `U256::MAX`, mint address goes negative by the supply
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Amount {
+    /// Never set for zero
+    negative: bool,
+    magnitude: U256,
+}
+
+impl Amount {
+    pub fn new(negative: bool, magnitude: U256) -> Self {
+        Self {
+            negative: negative && !magnitude.is_zero(),
+            magnitude,
+        }
+    }
+
+    pub fn magnitude(&self) -> U256 {
+        self.magnitude
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_positive(&self) -> bool {
+        !self.is_negative() && !self.is_zero()
+    }
+
+    /// Sum of amounts, None if its magnitude is over `U256::MAX`
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        if self.negative == other.negative {
+            Some(Self::new(
+                self.negative,
+                self.magnitude.checked_add(other.magnitude)?,
+            ))
+        } else if self.magnitude >= other.magnitude {
+            Some(Self::new(self.negative, self.magnitude - other.magnitude))
+        } else {
+            Some(Self::new(other.negative, other.magnitude - self.magnitude))
+        }
+    }
+}
+
+impl From<U256> for Amount {
+    fn from(magnitude: U256) -> Self {
+        Self::new(false, magnitude)
+    }
+}
+
+impl Neg for Amount {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(!self.negative, self.magnitude)
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.magnitude.cmp(&other.magnitude),
+            (true, true) => other.magnitude.cmp(&self.magnitude),
+            (negative, _) => other.negative.cmp(&negative),
+        }
+    }
+}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", self.magnitude)
+    }
+}
+
+/// Decimal digits with optional `-` sign
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(amount: &str) -> Result<Self> {
+        let (negative, digits) = match amount.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, amount),
+        };
+
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(anyhow!("Invalid amount '{amount}'"));
+        }
+
+        let magnitude =
+            U256::from_dec_str(digits).map_err(|_| anyhow!("Amount '{amount}' is out of range"))?;
+
+        Ok(Self::new(negative, magnitude))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn amounts_are_added_with_sign() {
+        assert_eq!(amount("5").checked_add(amount("-7")), Some(amount("-2")));
+        assert_eq!(amount("-5").checked_add(amount("7")), Some(amount("2")));
+        assert!(!amount("-5").checked_add(amount("5")).unwrap().is_negative());
+        assert_eq!(amount("-5").checked_add(amount("5")), Some(amount("0")));
+    }
+
+    #[test]
+    fn full_uint256_range_is_kept_and_overflow_rejected() {
+        let max = Amount::from(U256::MAX);
+
+        assert_eq!(amount(&format!("-{}", U256::MAX)), -max);
+        assert_eq!((-max).checked_add(max), Some(Amount::default()));
+        assert_eq!(max.checked_add(Amount::from(U256::one())), None);
+        assert_eq!((-max).checked_add(-Amount::from(U256::one())), None);
+        assert!(format!("{}0", U256::MAX).parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn negative_amounts_are_ordered_before_positive() {
+        let mut amounts = vec![amount("3"), amount("-1"), amount("0"), amount("-10")];
+        amounts.sort();
+
+        assert_eq!(
+            amounts,
+            [amount("-10"), amount("-1"), amount("0"), amount("3")]
+        );
+        assert_eq!(amount("-0"), Amount::default());
+        assert!("1e5".parse::<Amount>().is_err() && "".parse::<Amount>().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    app_err_response,
+    error::{AppError, AppErrorResponse},
+    model::ApiKey,
+    storage::SharedStorage,
+};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ethers::{core::rand, utils::hex};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Bucket is full again after a minute without requests, so buckets
+/// of idle or deleted keys are dropped after it
+const BUCKET_IDLE: Duration = Duration::from_secs(60);
+
+/// Access level of api key, admin key has every scope
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Read,
+    AddToken,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::AddToken => "add-token",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn from_param(param: &str) -> Option<Self> {
+        match param {
+            "read" => Some(Scope::Read),
+            "add-token" => Some(Scope::AddToken),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of key, only hashes are stored in db
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Random hex encoded 32 bytes key
+pub fn generate_key() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<i32, Bucket>,
+    pruned: Option<Instant>,
+}
+
+/// Token bucket per api key, refilled with rate limit of key per minute
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Take token from bucket of key, returns seconds until next token if bucket is empty
+    fn take(&self, api_key: &ApiKey, now: Instant) -> Result<(), u64> {
+        let capacity = api_key.rate_limit.max(1) as f64;
+        let per_second = capacity / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets
+            .pruned
+            .is_none_or(|pruned| now.duration_since(pruned) >= BUCKET_IDLE)
+        {
+            buckets
+                .by_key
+                .retain(|_, bucket| now.duration_since(bucket.updated) < BUCKET_IDLE);
+            buckets.pruned = Some(now);
+        }
+
+        let bucket = buckets.by_key.entry(api_key.id).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / per_second).ceil() as u64)
+        }
+    }
+}
+
+/// State of auth middleware for routes of one scope
+#[derive(Clone)]
+pub struct Auth {
+    storage: SharedStorage,
+    limiter: Arc<RateLimiter>,
+    scope: Scope,
+}
+
+impl Auth {
+    pub fn new(storage: SharedStorage, limiter: Arc<RateLimiter>, scope: Scope) -> Self {
+        Self {
+            storage,
+            limiter,
+            scope,
+        }
+    }
+}
+
+/// Api key from `X-Api-Key` or `Authorization: Bearer` header
+fn key_from_headers(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("x-api-key")
+        .or_else(|| headers.get(header::AUTHORIZATION))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value))
+}
+
+/// Check api key, scope and rate limit of key before request
+pub async fn authorize(State(auth): State<Auth>, request: Request, next: Next) -> Response {
+    let Some(key) = key_from_headers(request.headers()) else {
+        return app_err_response!(StatusCode::UNAUTHORIZED, "Missing API key").into_response();
+    };
+
+    let api_key = match auth.storage.api_key_by_hash(&hash_key(key)).await {
+        Ok(Some(api_key)) => api_key,
+        Ok(None) => {
+            return app_err_response!(StatusCode::UNAUTHORIZED, "Invalid API key").into_response()
+        }
+        Err(err) => return AppErrorResponse::from(err).into_response(),
+    };
+
+    let allowed = api_key
+        .scopes
+        .iter()
+        .any(|scope| scope == auth.scope.as_str() || scope == Scope::Admin.as_str());
+
+    if !allowed {
+        let message = format!("API key has no '{}' scope", auth.scope.as_str());
+        return app_err_response!(StatusCode::FORBIDDEN, message).into_response();
+    }
+
+    if let Err(retry_after) = auth.limiter.take(&api_key, Instant::now()) {
+        let mut response =
+            app_err_response!(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    /// Route of add-token scope with keys `reader`, `writer` limited
+    /// to one request per minute and `admin`
+    async fn router() -> Router {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+
+        for (key, scope, rate_limit) in [
+            ("reader", "read", 60),
+            ("writer", "add-token", 1),
+            ("admin", "admin", 60),
+        ] {
+            storage
+                .add_api_key(&hash_key(key), key, &[scope.to_string()], &rate_limit)
+                .await
+                .unwrap();
+        }
+
+        let auth = Auth::new(storage, Arc::default(), Scope::AddToken);
+
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(auth, authorize))
+    }
+
+    async fn request(router: &Router, key: Option<&str>) -> Response {
+        let mut request = Request::get("/");
+
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {key}"));
+        }
+
+        router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn api_key(id: i32, rate_limit: i32) -> ApiKey {
+        ApiKey {
+            id,
+            name: format!("key{id}"),
+            scopes: vec![],
+            rate_limit,
+            created_at: String::new(),
+            key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_or_unknown_key_is_unauthorized() {
+        let router = router().await;
+
+        assert_eq!(
+            request(&router, None).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            request(&router, Some("unknown")).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn key_needs_scope_of_route_or_admin() {
+        let router = router().await;
+
+        assert_eq!(
+            request(&router, Some("reader")).await.status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            request(&router, Some("writer")).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            request(&router, Some("admin")).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn limited_key_gets_retry_after() {
+        let router = router().await;
+
+        assert_eq!(
+            request(&router, Some("writer")).await.status(),
+            StatusCode::OK
+        );
+
+        let response = request(&router, Some("writer")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+
+        // Limit of one key doesn't affect others
+        assert_eq!(
+            request(&router, Some("admin")).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn buckets_of_idle_keys_are_dropped() {
+        let limiter = RateLimiter::default();
+        let (idle, active) = (api_key(1, 1), api_key(2, 1));
+        let now = Instant::now();
+
+        assert_eq!(limiter.take(&idle, now), Ok(()));
+        assert_eq!(limiter.take(&active, now + BUCKET_IDLE / 2), Ok(()));
+        assert_eq!(limiter.take(&active, now + BUCKET_IDLE), Err(30));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_key.keys().collect::<Vec<_>>(), [&2]);
+    }
+}
@@ -0,0 +1,237 @@
+use super::{
+    ChainClient, ChainError, DecimalsCall, DecimalsReturn, LogStream, SymbolCall, SymbolReturn,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use ethers::{
+    abi::AbiEncode,
+    contract::EthCall,
+    types::{Address, Bytes, Filter, Log, ValueOrArray, H256, U256, U64},
+    utils::keccak256,
+};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+struct Contract {
+    symbol: String,
+    decimals: u8,
+    /// Block of contract deployment, code is returned from it
+    deployed_block: u64,
+}
+
+/// Chain replaying scripted heads and transfers without network, for tests of indexer
+pub struct MockChain {
+    /// Heads returned by successive block number requests, last one is repeated
+    block_numbers: Mutex<Vec<u64>>,
+    /// Logs requests over more blocks fail with `-32005`
+    max_range: Option<u64>,
+    contracts: HashMap<Address, Contract>,
+    /// Transfers returned by logs requests
+    logs: Vec<Log>,
+    /// Transfers sent to subscriptions, stream ends after them
+    live: Vec<Log>,
+    /// Logs requests over these blocks fail
+    failing: Vec<u64>,
+    /// Logs requests over these blocks are answered after delay
+    delays: Vec<(u64, Duration)>,
+    /// Block ranges of logs requests, failed ones too
+    requests: Mutex<Vec<(u64, u64)>>,
+}
+
+impl MockChain {
+    /// Chain with heads returned in order
+    pub fn new(block_numbers: &[u64]) -> Self {
+        assert!(!block_numbers.is_empty(), "Mock chain has no block numbers");
+
+        Self {
+            block_numbers: Mutex::new(block_numbers.iter().rev().copied().collect()),
+            max_range: None,
+            contracts: HashMap::new(),
+            logs: Vec::new(),
+            live: Vec::new(),
+            failing: Vec::new(),
+            delays: Vec::new(),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn max_range(mut self, max_range: u64) -> Self {
+        self.max_range = Some(max_range);
+        self
+    }
+
+    pub fn contract(
+        mut self,
+        address: Address,
+        symbol: &str,
+        decimals: u8,
+        deployed_block: u64,
+    ) -> Self {
+        self.contracts.insert(
+            address,
+            Contract {
+                symbol: symbol.to_string(),
+                decimals,
+                deployed_block,
+            },
+        );
+        self
+    }
+
+    /// Logs requests over block fail with error other than `-32005`
+    pub fn fail_at(mut self, block: u64) -> Self {
+        self.failing.push(block);
+        self
+    }
+
+    /// Logs requests over block are answered after delay
+    pub fn delay_at(mut self, block: u64, delay: Duration) -> Self {
+        self.delays.push((block, delay));
+        self
+    }
+
+    /// Transfer returned by logs requests
+    pub fn log(
+        mut self,
+        address: Address,
+        block: u64,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Self {
+        let log = transfer_log(address, block, from, to, amount, self.log_count());
+        self.logs.push(log);
+        self
+    }
+
+    /// Transfer sent to subscriptions
+    pub fn live(
+        mut self,
+        address: Address,
+        block: u64,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Self {
+        let log = transfer_log(address, block, from, to, amount, self.log_count());
+        self.live.push(log);
+        self
+    }
+
+    /// Block ranges of logs requests in order
+    pub fn requests(&self) -> Vec<(u64, u64)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn log_count(&self) -> usize {
+        self.logs.len() + self.live.len()
+    }
+}
+
+/// Transfer log with unique transaction hash and log index
+fn transfer_log(
+    address: Address,
+    block: u64,
+    from: Address,
+    to: Address,
+    amount: U256,
+    index: usize,
+) -> Log {
+    let topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let mut data = [0u8; 32];
+    amount.to_big_endian(&mut data);
+    let index = index as u64;
+
+    Log {
+        address,
+        topics: vec![topic, from.into(), to.into()],
+        data: data.to_vec().into(),
+        block_number: Some(U64::from(block)),
+        transaction_hash: Some(H256::from_low_u64_be(index + 1)),
+        log_index: Some(U256::from(index)),
+        ..Default::default()
+    }
+}
+
+/// Logs of filter address from its first block
+fn matching<'a>(logs: &'a [Log], filter: &'a Filter) -> impl Iterator<Item = &'a Log> {
+    let from = filter.get_from_block().unwrap_or_default();
+    let to = filter.get_to_block().unwrap_or(U64::MAX);
+
+    logs.iter().filter(move |log| {
+        let address = match &filter.address {
+            Some(ValueOrArray::Value(address)) => *address == log.address,
+            Some(ValueOrArray::Array(addresses)) => addresses.contains(&log.address),
+            None => true,
+        };
+        let block = log.block_number.unwrap_or_default();
+
+        address && from <= block && block <= to
+    })
+}
+
+#[async_trait]
+impl ChainClient for MockChain {
+    async fn block_number(&self) -> Result<u64, ChainError> {
+        let mut block_numbers = self.block_numbers.lock().unwrap();
+
+        if block_numbers.len() > 1 {
+            Ok(block_numbers.pop().unwrap())
+        } else {
+            Ok(block_numbers[0])
+        }
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError> {
+        let from = filter.get_from_block().unwrap_or_default().as_u64();
+        let to = filter.get_to_block().map(|to| to.as_u64()).unwrap_or(from);
+
+        self.requests.lock().unwrap().push((from, to));
+
+        if let Some(max_range) = self.max_range {
+            if to.saturating_sub(from) + 1 > max_range {
+                return Err(ChainError::LimitExceeded);
+            }
+        }
+
+        let covers = |block: &u64| from <= *block && *block <= to;
+
+        for (_, delay) in self.delays.iter().filter(|(block, _)| covers(block)) {
+            tokio::time::sleep(*delay).await;
+        }
+
+        if self.failing.iter().any(covers) {
+            return Err(ChainError::Other(anyhow!("header not found")));
+        }
+
+        Ok(matching(&self.logs, filter).cloned().collect())
+    }
+
+    async fn subscribe_logs<'a>(&'a self, filter: &Filter) -> Result<LogStream<'a>, ChainError> {
+        let logs: Vec<Log> = matching(&self.live, filter).cloned().collect();
+        Ok(Box::pin(tokio_stream::iter(logs)))
+    }
+
+    async fn get_code(&self, address: Address, block: u64) -> Result<Bytes, ChainError> {
+        match self.contracts.get(&address) {
+            Some(contract) if contract.deployed_block <= block => Ok(vec![0x60, 0x80].into()),
+            _ => Ok(Bytes::new()),
+        }
+    }
+
+    async fn call(&self, to: Address, data: Bytes) -> Result<Bytes, ChainError> {
+        let reverted = || ChainError::Other(anyhow!("execution reverted"));
+        let contract = self.contracts.get(&to).ok_or_else(reverted)?;
+
+        let output = match data.get(..4) {
+            Some(selector) if selector == SymbolCall::selector() => {
+                SymbolReturn(contract.symbol.clone()).encode()
+            }
+            Some(selector) if selector == DecimalsCall::selector() => {
+                DecimalsReturn(contract.decimals).encode()
+            }
+            _ => return Err(reverted()),
+        };
+
+        Ok(output.into())
+    }
+}
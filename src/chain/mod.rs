@@ -0,0 +1,92 @@
+//! Client of evm network used by indexer
+
+#[cfg(test)]
+mod mock;
+mod provider;
+
+#[cfg(test)]
+pub use mock::MockChain;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::{
+    contract::abigen,
+    prelude::ProviderError::{self, JsonRpcClientError},
+    types::{Address, Bytes, Filter, Log},
+};
+use std::{env, fmt, pin::Pin, sync::Arc};
+use tokio_stream::Stream;
+
+abigen!(
+    Erc20,
+    r#"[
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+    ]"#
+);
+
+/// Chain client shared by indexer tasks and readiness check
+pub type SharedChain = Arc<dyn ChainClient>;
+
+/// Live logs of subscription, ends when subscription is closed
+pub type LogStream<'a> = Pin<Box<dyn Stream<Item = Log> + Send + 'a>>;
+
+#[derive(Debug)]
+pub enum ChainError {
+    /// `-32005` response, logs request has to be split into smaller ranges
+    LimitExceeded,
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::LimitExceeded => write!(f, "Limit exceeded (code: -32005)"),
+            ChainError::Other(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl From<ProviderError> for ChainError {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            JsonRpcClientError(err) if err.to_string().contains("code: -32005") => {
+                ChainError::LimitExceeded
+            }
+            err => ChainError::Other(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    async fn block_number(&self) -> Result<u64, ChainError>;
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError>;
+
+    /// Logs of blocks produced after subscription, earlier blocks of filter are not replayed
+    async fn subscribe_logs<'a>(&'a self, filter: &Filter) -> Result<LogStream<'a>, ChainError>;
+
+    /// Code of account at block, empty if there is no contract
+    async fn get_code(&self, address: Address, block: u64) -> Result<Bytes, ChainError>;
+
+    /// Call contract at latest block, returns abi encoded output
+    async fn call(&self, to: Address, data: Bytes) -> Result<Bytes, ChainError>;
+}
+
+/// Chain client by `RPC_URL` env, transport is chosen by scheme:
+/// `http(s)://`, `ws(s)://`, `ipc://path` or path of IPC socket.
+/// `RPC_URL_WS` is used if it is not set
+pub async fn from_env() -> Result<SharedChain> {
+    let url = env::var("RPC_URL").or_else(|_| env::var("RPC_URL_WS"))?;
+
+    match url.split_once("://") {
+        Some(("http" | "https", _)) => provider::connect_http(&url),
+        Some(("ws" | "wss", _)) => provider::connect_ws(&url).await,
+        Some(("ipc", path)) => provider::connect_ipc(path).await,
+        Some(_) => Err(anyhow!("Unsupported RPC_URL '{url}'")),
+        None => provider::connect_ipc(&url).await,
+    }
+}
@@ -0,0 +1,66 @@
+use super::{ChainClient, ChainError, LogStream, SharedChain};
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Ipc, Middleware, Provider, Ws},
+    types::{Address, Bytes, Filter, Log, TransactionRequest},
+};
+use std::{sync::Arc, time::Duration};
+
+/// Interval of polling filter changes of HTTP node
+const LOG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Create websocket provider
+pub async fn connect_ws(url: &str) -> Result<SharedChain> {
+    let provider = Provider::<Ws>::connect(url).await?;
+    Ok(Arc::new(provider))
+}
+
+/// Create HTTP provider, live logs are polled with filter
+pub fn connect_http(url: &str) -> Result<SharedChain> {
+    let provider = Provider::<Http>::try_from(url)?.interval(LOG_POLL_INTERVAL);
+    Ok(Arc::new(provider))
+}
+
+/// Create provider on IPC socket of local node
+pub async fn connect_ipc(path: &str) -> Result<SharedChain> {
+    let provider = Provider::<Ipc>::connect_ipc(path).await?;
+    Ok(Arc::new(provider))
+}
+
+/// Implement chain client for provider, `$logs` is method
+/// of provider used to receive live logs
+macro_rules! impl_chain_client {
+    ($transport:ty, $logs:ident) => {
+        #[async_trait]
+        impl ChainClient for Provider<$transport> {
+            async fn block_number(&self) -> Result<u64, ChainError> {
+                Ok(self.get_block_number().await?.as_u64())
+            }
+
+            async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError> {
+                Ok(Middleware::get_logs(self, filter).await?)
+            }
+
+            async fn subscribe_logs<'a>(
+                &'a self,
+                filter: &Filter,
+            ) -> Result<LogStream<'a>, ChainError> {
+                Ok(Box::pin(Middleware::$logs(self, filter).await?))
+            }
+
+            async fn get_code(&self, address: Address, block: u64) -> Result<Bytes, ChainError> {
+                Ok(Middleware::get_code(self, address, Some(block.into())).await?)
+            }
+
+            async fn call(&self, to: Address, data: Bytes) -> Result<Bytes, ChainError> {
+                let tx = TransactionRequest::new().to(to).data(data).into();
+                Ok(Middleware::call(self, &tx, None).await?)
+            }
+        }
+    };
+}
+
+impl_chain_client!(Ws, subscribe_logs);
+impl_chain_client!(Ipc, subscribe_logs);
+impl_chain_client!(Http, watch);
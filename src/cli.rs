@@ -0,0 +1,327 @@
+use crate::{
+    auth, chain, evm,
+    health::Health,
+    model::{Column, Cursor, Filter, Operator, PageCursor, Sort, Token},
+    rest,
+    storage::{self, SharedStorage},
+    utils,
+};
+use anyhow::{anyhow, Context, Result};
+use axum::Router;
+use clap::{Parser, Subcommand};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio::{net::TcpListener, sync::broadcast};
+
+/// Balances fetched per query of snapshot export
+const EXPORT_BATCH_SIZE: i64 = 10_000;
+
+/// Requests per minute of api key created by cli
+const DEFAULT_RATE_LIMIT: i32 = 600;
+
+/// Index ERC20 token holders and serve them over API.
+/// Serves API and indexes tokens in one process without subcommand
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Serve API only, tokens are indexed by separate `index` process
+    Serve,
+    /// Index tokens only, with health and metrics endpoints
+    Index,
+    /// Add token by contract address, indexer picks it up from db
+    AddToken {
+        /// Contract address
+        addr: String,
+        /// First block of backfill, deployment block of contract by default
+        #[arg(long)]
+        start_block: Option<i64>,
+    },
+    /// Clear balances of token and index it again, running indexer picks it up from db
+    Reindex {
+        /// Token id or contract address
+        token: String,
+        /// First block of new checkpoint
+        #[arg(long, default_value_t = 0)]
+        start_block: i64,
+    },
+    /// Print indexing state of tokens
+    Status,
+    /// Export non-zero balances of token as CSV, largest first
+    ExportSnapshot {
+        /// Token id or contract address
+        token: String,
+        /// Output file, stdout if not set
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Create api key and print it, the key is shown only once.
+    /// Bootstraps the first admin key, others can be created over API
+    CreateApiKey {
+        /// Name of key
+        #[arg(long, default_value = "admin")]
+        name: String,
+        /// Comma separated scopes: read, add-token, admin
+        #[arg(long, value_delimiter = ',', default_value = "admin")]
+        scopes: Vec<String>,
+        /// Requests per minute
+        #[arg(long, default_value_t = DEFAULT_RATE_LIMIT)]
+        rate_limit: i32,
+    },
+}
+
+/// Run subcommand
+pub async fn run(command: Option<Command>) -> Result<()> {
+    match command {
+        None => run_all().await,
+        Some(Command::Serve) => serve().await,
+        Some(Command::Index) => index().await,
+        Some(Command::AddToken { addr, start_block }) => add_token(&addr, start_block).await,
+        Some(Command::Reindex { token, start_block }) => reindex(&token, start_block).await,
+        Some(Command::Status) => status().await,
+        Some(Command::ExportSnapshot { token, output }) => export_snapshot(&token, output).await,
+        Some(Command::CreateApiKey {
+            name,
+            scopes,
+            rate_limit,
+        }) => create_api_key(&name, &scopes, rate_limit).await,
+    }
+}
+
+/// Wrapper to use try methods
+async fn serve_wrapper(listener: TcpListener, app: Router) -> Result<()> {
+    Ok(axum::serve(listener, app).await?)
+}
+
+async fn create_listener() -> Result<TcpListener> {
+    TcpListener::bind(format!(
+        "{}:{}",
+        std::env::var("SERVICE_IP")?,
+        std::env::var("SERVICE_PORT")?
+    ))
+    .await
+    .context("Error creating TcpListener")
+}
+
+/// Connect to storage from `STORAGE_URL` or `DATABASE_URL` env and apply its migrations
+async fn connect_storage() -> Result<SharedStorage> {
+    storage::from_env()
+        .await
+        .context("Error connecting to the storage")
+}
+
+/// Find token by id or by contract address
+async fn find_token(storage: &SharedStorage, id: &str) -> Result<Token> {
+    storage::find_token(storage, id)
+        .await?
+        .ok_or_else(|| anyhow!("Token not found"))
+}
+
+/// Serve API and index tokens, service keeps serving
+/// and fails liveness check if indexer stops
+async fn run_all() -> Result<()> {
+    let storage = connect_storage().await?;
+    let chain = chain::from_env()
+        .await
+        .context("Error creating a chain client for evm")?;
+
+    // Create a channel to stream live transfers
+    let (events, _) = broadcast::channel(1024);
+
+    let health = Arc::new(Health::new(storage.clone(), Some(chain.clone())));
+
+    let app = rest::create_router(storage.clone(), events.clone(), health.clone());
+    let listener = create_listener().await?;
+
+    let indexer = tokio::spawn(evm::update_db(storage, chain, events));
+    tokio::spawn(health.watch_indexer(indexer));
+
+    serve_wrapper(listener, app).await
+}
+
+/// Serve API without indexer, live transfers are published
+/// only by indexer process and are not streamed from this one
+async fn serve() -> Result<()> {
+    let storage = connect_storage().await?;
+    let (events, _) = broadcast::channel(1);
+
+    let health = Arc::new(Health::new(storage.clone(), None));
+    let app = rest::create_router(storage, events, health);
+
+    serve_wrapper(create_listener().await?, app).await
+}
+
+/// Index tokens and serve health and metrics endpoints
+async fn index() -> Result<()> {
+    let storage = connect_storage().await?;
+    let chain = chain::from_env()
+        .await
+        .context("Error creating a chain client for evm")?;
+
+    let (events, _) = broadcast::channel(1024);
+
+    let health = Arc::new(Health::new(storage.clone(), Some(chain.clone())));
+    let app = rest::create_probe_router(health.clone());
+    let listener = create_listener().await?;
+
+    let indexer = tokio::spawn(evm::update_db(storage, chain, events));
+    tokio::spawn(health.watch_indexer(indexer));
+
+    serve_wrapper(listener, app).await
+}
+
+async fn add_token(addr: &str, start_block: Option<i64>) -> Result<()> {
+    let storage = connect_storage().await?;
+
+    let contract_addr = utils::normalize_addr(addr).map_err(|err| anyhow!(err))?;
+
+    if let Some(token) = storage.token_by_contract(&contract_addr).await? {
+        return Err(anyhow!("Token is already tracked with id {}", token.id));
+    }
+
+    let token = evm::add_token_by_contract(&storage, &contract_addr, start_block).await?;
+
+    println!("Added token {} ({})", token.id, token.contract_addr);
+
+    Ok(())
+}
+
+async fn reindex(id: &str, start_block: i64) -> Result<()> {
+    if start_block < 0 {
+        return Err(anyhow!("Start block must not be negative"));
+    }
+
+    let storage = connect_storage().await?;
+    let token = find_token(&storage, id).await?;
+
+    storage.request_reindex(&token.id, &start_block).await?;
+
+    println!(
+        "Token {} ({}) will be indexed again from block {}",
+        token.id, token.contract_addr, start_block
+    );
+
+    Ok(())
+}
+
+async fn status() -> Result<()> {
+    let storage = connect_storage().await?;
+    let token_count = storage.all_token_count().await?;
+    let tokens = storage
+        .all_token(
+            0,
+            token_count,
+            &[Sort {
+                column: Column::TokenId,
+                descending: false,
+            }],
+        )
+        .await?;
+
+    println!(
+        "{:>5}  {:<10}  {:<40}  {:<11}  {:>10}  {:>10}  {:>8}  {:>8}  ERROR",
+        "ID", "SYMBOL", "CONTRACT", "STATE", "CHECKED", "HEAD", "LAG", "ETA"
+    );
+
+    for token in tokens.iter() {
+        println!(
+            "{:>5}  {:<10}  {:<40}  {:<11}  {:>10}  {:>10}  {:>8}  {:>8}  {}",
+            token.id,
+            token.symbol,
+            token.contract_addr,
+            token.sync_state,
+            token.last_checked_block,
+            token.head_block,
+            token.lag,
+            token.eta.map(|eta| format!("{eta}s")).unwrap_or_default(),
+            token.sync_error.as_deref().unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Write balances page by page with keyset pagination
+async fn export_snapshot(id: &str, output: Option<PathBuf>) -> Result<()> {
+    let storage = connect_storage().await?;
+    let token = find_token(&storage, id).await?;
+
+    let mut writer: BufWriter<Box<dyn Write>> = BufWriter::new(match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    });
+
+    let filter = [Filter {
+        column: Column::TokenId,
+        operator: Operator::In,
+        values: vec![token.id.to_string()],
+    }];
+    let mut cursor = PageCursor::After(None);
+
+    writeln!(writer, "holder_addr,amount,amount_decimal")?;
+
+    loop {
+        let (balances, has_more) = storage
+            .all_balance_by_cursor(&filter, &cursor, EXPORT_BATCH_SIZE, true)
+            .await?;
+
+        for balance in balances.iter() {
+            writeln!(
+                writer,
+                "0x{},{},{}",
+                balance.holder.holder_addr,
+                balance.amount,
+                utils::format_amount(&balance.amount, token.decimals, None)
+            )?;
+        }
+
+        match balances.last() {
+            Some(balance) if has_more => cursor = PageCursor::After(Some(Cursor::of(balance))),
+            _ => break,
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+async fn create_api_key(name: &str, scopes: &[String], rate_limit: i32) -> Result<()> {
+    if let Some(scope) = scopes
+        .iter()
+        .find(|scope| auth::Scope::from_param(scope).is_none())
+    {
+        return Err(anyhow!(
+            "Invalid scope '{scope}'. Valid values: read, add-token, admin"
+        ));
+    }
+
+    if rate_limit <= 0 {
+        return Err(anyhow!("Rate limit must be positive"));
+    }
+
+    let storage = connect_storage().await?;
+    let key = auth::generate_key();
+
+    let api_key = storage
+        .add_api_key(&auth::hash_key(&key), name, scopes, &rate_limit)
+        .await?
+        .ok_or_else(|| anyhow!("API key already exists"))?;
+
+    println!(
+        "Created api key {} ({}), it is shown only once:",
+        api_key.id, api_key.name
+    );
+    println!("{key}");
+
+    Ok(())
+}
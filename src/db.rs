@@ -1,98 +1,118 @@
-use anyhow::Result;
-use jsonapi::{api::*, jsonapi_model, model::*};
-use serde::{Deserialize, Serialize};
-use sqlx::{Error, FromRow, PgPool};
-use std::env;
-
-#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
-pub struct Token {
-    #[sqlx(rename = "token_id")]
-    pub id: i32,
-    pub contract_addr: String,
-    pub last_checked_block: i64,
-    pub symbol: String,
-    pub decimals: i16,
-}
-
-#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
-pub struct Holder {
-    #[sqlx(rename = "holder_id")]
-    pub id: i32,
-    pub holder_addr: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
-pub struct Balance {
-    pub id: String,
-    pub amount: String,
-    #[sqlx(flatten)]
-    pub token: Token,
-    #[sqlx(flatten)]
-    pub holder: Holder,
-}
-
-fn sort_to_sql(sort: Option<Vec<String>>) -> String {
-    if let Some(sort) = sort {
-        // let sort: Vec<_> = sort
-        //     .iter()
-        //     .map(|col_name| {
-        //         if col_name.starts_with("-") {
-        //             let col_name = &col_name[1..];
-        //             format!("{} DESC", col_name)
-        //         } else {
-        //             format!("{} ASC", col_name)
-        //         }
-        //     })
-        //     .collect();
-
-        format!("ORDER BY {}", sort.join(", "))
-    } else {
-        String::new()
+use crate::{
+    model::{
+        ApiKey, Balance, Column, DueWebhookDelivery, Filter, Holder, Job, JobState, Operator,
+        PageCursor, Sort, SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+    utils,
+};
+use anyhow::{Context, Result};
+use sqlx::{Error, PgPool, Postgres, QueryBuilder};
+use std::time::Duration;
+
+const WEBHOOK_COLUMNS: &str = "webhook_id, url, event, token_id,
+    encode(holder_addr, 'hex') AS holder_addr, min_amount::TEXT";
+
+const WEBHOOK_DELIVERY_COLUMNS: &str = "delivery_id, webhook_id, payload, attempts,
+    status_code, delivered, error, created_at::TEXT";
+
+const API_KEY_COLUMNS: &str = "api_key_id, name, scopes, rate_limit, created_at::TEXT";
+
+const JOB_COLUMNS: &str = "job_id, encode(contract_addr, 'hex') AS contract_addr, start_block,
+    state, token_id, error, created_at::TEXT, updated_at::TEXT";
+
+/// Token columns with calculated lag and eta (in seconds)
+const TOKEN_COLUMNS: &str = "token.token_id,
+    encode(token.contract_addr, 'hex') AS contract_addr,
+    token.last_checked_block, token.symbol, token.decimals,
+    token.sync_state, token.head_block,
+    GREATEST(token.head_block - token.last_checked_block, 0) AS lag,
+    token.backfill_rate,
+    CASE WHEN token.sync_state = 'backfilling' AND token.backfill_rate > 0
+        THEN CEIL(GREATEST(token.head_block - token.last_checked_block, 0) / token.backfill_rate)::BIGINT
+    END AS eta,
+    token.sync_error";
+
+/// Bind value with conversion to column type
+fn push_value(builder: &mut QueryBuilder<Postgres>, column: Column, value: &str) {
+    match column {
+        Column::ContractAddr | Column::HolderAddr => {
+            builder
+                .push("decode(")
+                .push_bind(utils::strip_hex_prefix(value).to_string())
+                .push(", 'hex')");
+        }
+        Column::Amount => {
+            builder.push_bind(value.to_string()).push("::NUMERIC");
+        }
+        Column::BlockNumber => {
+            builder.push_bind(value.to_string()).push("::BIGINT");
+        }
+        Column::TokenId => {
+            builder.push_bind(value.to_string()).push("::INT");
+        }
+        Column::Symbol => {
+            builder.push_bind(value.to_string());
+        }
     }
 }
 
-fn filter_to_sql(filter: HashMap<String, Vec<String>>) -> String {
-    let mut sql_conditions = Vec::new();
+/// Append ` AND column operator values` for every filter
+fn push_filter(builder: &mut QueryBuilder<Postgres>, filter: &[Filter]) {
+    for Filter {
+        column,
+        operator,
+        values,
+    } in filter.iter()
+    {
+        builder
+            .push(" AND ")
+            .push(column.sql())
+            .push(operator.sql());
+
+        if *operator == Operator::In {
+            builder.push("(");
 
-    for (key, values) in filter.iter() {
-        let condition: String;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    builder.push(", ");
+                }
+                push_value(builder, *column, value);
+            }
 
-        if key.contains("holder_addr") || key.contains("contract_addr") {
-            condition = if values.len() == 1 {
-                format!("{} = decode('{}', 'hex')", key, values[0])
-            } else {
-                format!(
-                    "{} IN (decode('{}', 'hex'))",
-                    key,
-                    values.join("', 'hex'), decode('")
-                )
-            };
+            builder.push(")");
         } else {
-            condition = if values.len() == 1 {
-                format!("{} = '{}'", key, values[0])
-            } else {
-                format!("{} IN ('{}')", key, values.join("', '"))
-            };
+            push_value(builder, *column, &values[0]);
         }
-
-        sql_conditions.push(condition);
     }
+}
 
-    format!("WHERE {}", sql_conditions.join(" AND "))
+/// Append ` ORDER BY` clause
+fn push_sort(builder: &mut QueryBuilder<Postgres>, sort: &[Sort]) {
+    for (i, Sort { column, descending }) in sort.iter().enumerate() {
+        builder
+            .push(if i == 0 { " ORDER BY " } else { ", " })
+            .push(column.sql())
+            .push(if *descending { " DESC" } else { " ASC" });
+    }
 }
 
-pub async fn init_db() -> Result<PgPool> {
-    jsonapi_model!(Token; "token");
-    jsonapi_model!(Holder; "holder");
-    jsonapi_model!(Balance; "balance"; has one token, holder);
+/// Append ` OFFSET LIMIT` clause for page
+fn push_page(builder: &mut QueryBuilder<Postgres>, number: i64, size: i64) {
+    builder
+        .push(" OFFSET ")
+        .push_bind(number * size)
+        .push(" LIMIT ")
+        .push_bind(size);
+}
 
-    let database_url = env::var("DATABASE_URL").expect("Error, missing DATABASE_URL in .env");
-    let connection_pool = PgPool::connect(&database_url).await?;
+/// Connect to database and apply migrations
+pub async fn init_db(database_url: &str) -> Result<PgPool> {
+    let connection_pool = PgPool::connect(database_url).await?;
 
     sqlx::migrate!("./migrations")
         .run(&connection_pool)
         .await
-        .expect("Unable to migrate database");
+        .context("Unable to migrate database")?;
 
     Ok(connection_pool)
 }
@@ -101,19 +121,40 @@ pub async fn all_token(
     connection_pool: &PgPool,
     number: i64,
     size: i64,
-    sort: Option<Vec<String>>,
+    sort: &[Sort],
 ) -> Result<Vec<Token>, Error> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM token", TOKEN_COLUMNS));
+
+    push_sort(&mut builder, sort);
+    push_page(&mut builder, number, size);
+
+    builder
+        .build_query_as::<Token>()
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn token_by_id(connection_pool: &PgPool, token_id: &i32) -> Result<Option<Token>, Error> {
+    let sql = format!("SELECT {} FROM token WHERE token_id = $1", TOKEN_COLUMNS);
+
+    sqlx::query_as::<_, Token>(&sql)
+        .bind(token_id)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn token_by_contract(
+    connection_pool: &PgPool,
+    contract_addr: &str,
+) -> Result<Option<Token>, Error> {
     let sql = format!(
-        "SELECT token_id,
-        encode(contract_addr, 'hex') AS contract_addr, 
-        last_checked_block, symbol, decimals FROM token {} OFFSET $1 LIMIT $2",
-        sort_to_sql(sort)
+        "SELECT {} FROM token WHERE contract_addr = decode($1, 'hex')",
+        TOKEN_COLUMNS
     );
 
     sqlx::query_as::<_, Token>(&sql)
-        .bind(number * size)
-        .bind(size)
-        .fetch_all(connection_pool)
+        .bind(contract_addr)
+        .fetch_optional(connection_pool)
         .await
 }
 
@@ -152,6 +193,26 @@ pub async fn add_token(
     Ok(token_id)
 }
 
+pub async fn update_token_metadata(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    symbol: &str,
+    decimals: &i16,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE token
+                SET symbol = $1, decimals = $2
+                WHERE token_id = $3",
+    )
+    .bind(symbol)
+    .bind(decimals)
+    .bind(token_id)
+    .execute(connection_pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn update_token_last_checked_block(
     connection_pool: &PgPool,
     last_checked_block: &i64,
@@ -170,6 +231,136 @@ pub async fn update_token_last_checked_block(
     Ok(())
 }
 
+pub async fn update_token_sync(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    sync_state: SyncState,
+    head_block: &i64,
+    backfill_rate: Option<f64>,
+    sync_error: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE token
+                SET sync_state = $1,
+                    head_block = GREATEST(head_block, $2),
+                    backfill_rate = COALESCE($3, backfill_rate),
+                    sync_error = $4
+                WHERE token_id = $5",
+    )
+    .bind(sync_state.as_str())
+    .bind(head_block)
+    .bind(backfill_rate)
+    .bind(sync_error)
+    .bind(token_id)
+    .execute(connection_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Ask indexer to index token again from `start_block`
+pub async fn request_reindex(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    start_block: &i64,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE token SET reindex_block = $1 WHERE token_id = $2")
+        .bind(start_block)
+        .bind(token_id)
+        .execute(connection_pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Ids and start blocks of tokens waiting for reindex
+pub async fn all_reindex_request(connection_pool: &PgPool) -> Result<Vec<(i32, i64)>, Error> {
+    sqlx::query_as("SELECT token_id, reindex_block FROM token WHERE reindex_block IS NOT NULL")
+        .fetch_all(connection_pool)
+        .await
+}
+
+/// Take or renew lease of token indexing by worker for `lease`.
+/// Returns false if another worker holds unexpired lease of token
+pub async fn claim_token(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    worker: &str,
+    lease: Duration,
+) -> Result<bool, Error> {
+    let result = sqlx::query(
+        "UPDATE token SET owner = $2, lease_expires_at = NOW() + make_interval(secs => $3)
+            WHERE token_id = $1
+                AND (owner IS NULL OR owner = $2 OR lease_expires_at <= NOW())",
+    )
+    .bind(token_id)
+    .bind(worker)
+    .bind(lease.as_secs_f64())
+    .execute(connection_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Give up lease of token held by worker
+pub async fn release_token(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    worker: &str,
+) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE token SET owner = NULL, lease_expires_at = NULL
+            WHERE token_id = $1 AND owner = $2",
+    )
+    .bind(token_id)
+    .bind(worker)
+    .execute(connection_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Clear balances, stats and history of token and move its checkpoint back
+/// to `last_checked_block` so that it is indexed again
+pub async fn reset_token(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    last_checked_block: &i64,
+) -> Result<()> {
+    let mut transaction = connection_pool.begin().await?;
+
+    for sql in [
+        "DELETE FROM balance WHERE token_id = $1",
+        "DELETE FROM token_stats WHERE token_id = $1",
+        "DELETE FROM token_history WHERE token_id = $1",
+    ] {
+        sqlx::query(sql)
+            .bind(token_id)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    sqlx::query(
+        "UPDATE token
+                SET last_checked_block = $1,
+                    sync_state = $2,
+                    backfill_rate = 0,
+                    sync_error = NULL,
+                    reindex_block = NULL
+                WHERE token_id = $3",
+    )
+    .bind(last_checked_block)
+    .bind(SyncState::Backfilling.as_str())
+    .bind(token_id)
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Concurrent tasks adding the same holder get the same id
 pub async fn add_or_get_holder(connection_pool: &PgPool, holder_addr: &str) -> Result<i32, Error> {
     let holder_id = sqlx::query_scalar::<_, i32>(
         "SELECT holder_id FROM holder WHERE holder_addr = decode($1, 'hex')",
@@ -184,6 +375,7 @@ pub async fn add_or_get_holder(connection_pool: &PgPool, holder_addr: &str) -> R
             sqlx::query_scalar::<_, i32>(
                 "INSERT INTO holder (holder_addr) 
                     VALUES (decode($1, 'hex'))
+                    ON CONFLICT (holder_addr) DO UPDATE SET holder_addr = EXCLUDED.holder_addr
                     RETURNING holder_id",
             )
             .bind(holder_addr)
@@ -193,75 +385,799 @@ pub async fn add_or_get_holder(connection_pool: &PgPool, holder_addr: &str) -> R
     }
 }
 
+pub async fn holder_by_addr(
+    connection_pool: &PgPool,
+    holder_addr: &str,
+) -> Result<Option<Holder>, Error> {
+    sqlx::query_as::<_, Holder>(
+        "SELECT holder_id, encode(holder_addr, 'hex') AS holder_addr
+        FROM holder WHERE holder_addr = decode($1, 'hex')",
+    )
+    .bind(holder_addr)
+    .fetch_optional(connection_pool)
+    .await
+}
+
+/// Non-zero balances joined with token and holder
+const BALANCE_SELECT: &str = "FROM balance
+    INNER JOIN holder ON balance.holder_id = holder.holder_id
+    INNER JOIN token ON balance.token_id = token.token_id
+    WHERE balance.amount > 0";
+
 pub async fn all_balance_by_filter(
     connection_pool: &PgPool,
-    filter: HashMap<String, Vec<String>>,
+    filter: &[Filter],
     number: i64,
     size: i64,
-    sort: Option<Vec<String>>,
+    sort: &[Sort],
 ) -> Result<Vec<Balance>, Error> {
-    let sql = format!(
+    let mut builder = QueryBuilder::new(format!(
         "SELECT 
             CONCAT(balance.holder_id, '_', balance.token_id) AS id, balance.amount::TEXT,
-            token.token_id, encode(token.contract_addr, 'hex') AS contract_addr, 
-            token.last_checked_block, token.symbol, token.decimals,
+            {},
             holder.holder_id,
             encode(holder.holder_addr, 'hex') AS holder_addr
-        FROM balance
-        INNER JOIN holder ON balance.holder_id = holder.holder_id
-        INNER JOIN token ON balance.token_id = token.token_id
-        {} AND balance.amount > 0
-        {} OFFSET $1 LIMIT $2",
-        filter_to_sql(filter),
-        sort_to_sql(sort)
-    );
+        {}",
+        TOKEN_COLUMNS, BALANCE_SELECT
+    ));
 
-    sqlx::query_as::<_, Balance>(&sql)
-        .bind(number * size)
-        .bind(size)
+    push_filter(&mut builder, filter);
+    push_sort(&mut builder, sort);
+    push_page(&mut builder, number, size);
+
+    builder
+        .build_query_as::<Balance>()
         .fetch_all(connection_pool)
         .await
 }
 
+/// Page of balances next to cursor, ordered by amount, holder and token.
+/// Filter of single token is resolved to its id, so page of token is
+/// seeked by amount and holder on `idx_token_amount`.
+/// Returns balances in sort order and whether more balances follow in cursor direction
+pub async fn all_balance_by_cursor(
+    connection_pool: &PgPool,
+    filter: &[Filter],
+    cursor: &PageCursor,
+    size: i64,
+    descending: bool,
+) -> Result<(Vec<Balance>, bool), Error> {
+    let is_token_filter = |filter: &Filter| {
+        filter.column == Column::ContractAddr
+            && filter.operator == Operator::In
+            && filter.values.len() == 1
+    };
+
+    let token_id = match filter.iter().find(|filter| is_token_filter(filter)) {
+        Some(token_filter) => {
+            let contract_addr = utils::strip_hex_prefix(&token_filter.values[0]).to_lowercase();
+
+            match token_by_contract(connection_pool, &contract_addr).await? {
+                Some(token) => Some(token.id),
+                None => return Ok((Vec::new(), false)),
+            }
+        }
+        None => None,
+    };
+
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT
+            CONCAT(balance.holder_id, '_', balance.token_id) AS id, balance.amount::TEXT,
+            {},
+            holder.holder_id,
+            encode(holder.holder_addr, 'hex') AS holder_addr
+        {}",
+        TOKEN_COLUMNS, BALANCE_SELECT
+    ));
+
+    match token_id {
+        Some(token_id) => {
+            let filter: Vec<Filter> = filter
+                .iter()
+                .filter(|filter| !is_token_filter(filter))
+                .cloned()
+                .collect();
+
+            builder.push(" AND balance.token_id = ").push_bind(token_id);
+            push_filter(&mut builder, &filter);
+        }
+        None => push_filter(&mut builder, filter),
+    }
+
+    let (position, backward) = match cursor {
+        PageCursor::After(position) => (position.as_ref(), false),
+        PageCursor::Before(position) => (Some(position), true),
+    };
+    let descending = descending != backward;
+    let order = if descending { " DESC" } else { " ASC" };
+
+    // Balances of single token are unique by holder
+    let key = match token_id {
+        Some(_) => "(balance.amount, balance.holder_id)",
+        None => "(balance.amount, balance.holder_id, balance.token_id)",
+    };
+
+    if let Some(position) = position {
+        builder
+            .push(" AND ")
+            .push(key)
+            .push(if descending { " < (" } else { " > (" })
+            .push_bind(position.amount.clone())
+            .push("::NUMERIC, ")
+            .push_bind(position.holder_id);
+
+        if token_id.is_none() {
+            builder.push(", ").push_bind(position.token_id);
+        }
+
+        builder.push(")");
+    }
+
+    builder
+        .push(" ORDER BY balance.amount")
+        .push(order)
+        .push(", balance.holder_id")
+        .push(order);
+
+    if token_id.is_none() {
+        builder.push(", balance.token_id").push(order);
+    }
+
+    builder.push(" LIMIT ").push_bind(size + 1);
+
+    let mut balances = builder
+        .build_query_as::<Balance>()
+        .fetch_all(connection_pool)
+        .await?;
+
+    let has_more = balances.len() as i64 > size;
+    balances.truncate(size as usize);
+
+    if backward {
+        balances.reverse();
+    }
+
+    Ok((balances, has_more))
+}
+
 pub async fn all_balance_by_filter_count(
     connection_pool: &PgPool,
-    filter: HashMap<String, Vec<String>>,
+    filter: &[Filter],
 ) -> Result<i64, Error> {
-    let sql = format!(
-        "SELECT COUNT(*)
-        FROM balance
-        INNER JOIN holder ON balance.holder_id = holder.holder_id
-        INNER JOIN token ON balance.token_id = token.token_id
-        {} AND balance.amount > 0",
-        filter_to_sql(filter)
-    );
+    let mut builder = QueryBuilder::new(format!("SELECT COUNT(*) {}", BALANCE_SELECT));
+
+    push_filter(&mut builder, filter);
 
-    sqlx::query_scalar(&sql).fetch_one(connection_pool).await
+    builder
+        .build_query_scalar()
+        .fetch_one(connection_pool)
+        .await
 }
 
+/// Move amount between holders and checkpoint of token in one transaction,
+/// returns updated balances as (holder_id, amount)
 pub async fn upsert_balance(
     connection_pool: &PgPool,
     from_holder_id: &i32,
     to_holder_id: &i32,
     token_id: &i32,
     amount: &str,
-) -> Result<()> {
+    last_checked_block: &i64,
+) -> Result<Vec<(i32, String)>> {
+    let mut transaction = connection_pool.begin().await?;
+
     let sql = String::from(
         "INSERT INTO balance (holder_id, token_id, amount) 
         VALUES
             ($1, $3, $4::NUMERIC),
             ($2, $3, $5::NUMERIC)
-        ON CONFLICT (holder_id, token_id) DO UPDATE SET amount = balance.amount + EXCLUDED.amount",
+        ON CONFLICT (holder_id, token_id) DO UPDATE SET amount = balance.amount + EXCLUDED.amount
+        RETURNING holder_id, amount::TEXT",
     );
 
-    sqlx::query(&sql)
+    let balances = sqlx::query_as::<_, (i32, String)>(&sql)
         .bind(from_holder_id)
         .bind(to_holder_id)
         .bind(token_id)
         .bind(format!("-{amount}"))
         .bind(amount)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+    sqlx::query("UPDATE token SET last_checked_block = $1 WHERE token_id = $2")
+        .bind(last_checked_block)
+        .bind(token_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(balances)
+}
+
+/// Add net balance changes of holders as (holder_addr, signed amount)
+/// and move checkpoint of token in one transaction.
+/// New holders are inserted in address order, so that concurrent merges lock them in the same order
+pub async fn apply_balance_deltas(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    deltas: &[(String, String)],
+    last_checked_block: &i64,
+) -> Result<(), Error> {
+    let (holder_addrs, amounts): (Vec<&str>, Vec<&str>) = deltas
+        .iter()
+        .map(|(holder_addr, amount)| (holder_addr.as_str(), amount.as_str()))
+        .unzip();
+
+    let mut transaction = connection_pool.begin().await?;
+
+    sqlx::query(
+        "INSERT INTO holder (holder_addr)
+            SELECT decode(holder_addr, 'hex') FROM UNNEST($1::TEXT[]) AS holder_addr
+            ORDER BY 1
+            ON CONFLICT (holder_addr) DO NOTHING",
+    )
+    .bind(&holder_addrs)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO balance (holder_id, token_id, amount)
+            SELECT holder.holder_id, $2, delta.amount::NUMERIC
+            FROM UNNEST($1::TEXT[], $3::TEXT[]) AS delta (holder_addr, amount)
+            JOIN holder ON holder.holder_addr = decode(delta.holder_addr, 'hex')
+            ON CONFLICT (holder_id, token_id) DO UPDATE SET amount = balance.amount + EXCLUDED.amount",
+    )
+    .bind(&holder_addrs)
+    .bind(token_id)
+    .bind(&amounts)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query("UPDATE token SET last_checked_block = $1 WHERE token_id = $2")
+        .bind(last_checked_block)
+        .bind(token_id)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await
+}
+
+/// Cached stats of token if calculated for the checkpoint or not older than `max_age`
+pub async fn fresh_token_stats(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    last_checked_block: &i64,
+    max_age: Duration,
+) -> Result<Option<TokenStats>, Error> {
+    sqlx::query_as::<_, TokenStats>(
+        "SELECT token_id, last_checked_block, holder_count, total_supply::TEXT,
+            top10_share, top100_share, gini, herfindahl, nakamoto
+        FROM token_stats
+        WHERE token_id = $1
+            AND (last_checked_block = $2 OR calculated_at > NOW() - make_interval(secs => $3))",
+    )
+    .bind(token_id)
+    .bind(last_checked_block)
+    .bind(max_age.as_secs_f64())
+    .fetch_optional(connection_pool)
+    .await
+}
+
+/// Calculate holder concentration metrics from non-zero balances
+/// and cache them for the given checkpoint
+pub async fn calculate_token_stats(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    last_checked_block: &i64,
+) -> Result<TokenStats, Error> {
+    sqlx::query_as::<_, TokenStats>(
+        "WITH ranked AS (
+            SELECT amount,
+                ROW_NUMBER() OVER (ORDER BY amount ASC) AS rank_asc,
+                ROW_NUMBER() OVER (ORDER BY amount DESC) AS rank_desc,
+                SUM(amount) OVER (ORDER BY amount DESC ROWS UNBOUNDED PRECEDING) AS cumulative
+            FROM balance
+            WHERE token_id = $1 AND amount > 0
+        ), totals AS (
+            SELECT COUNT(*) AS holder_count, COALESCE(SUM(amount), 0) AS total_supply
+            FROM ranked
+        )
+        INSERT INTO token_stats (token_id, last_checked_block, holder_count, total_supply,
+            top10_share, top100_share, gini, herfindahl, nakamoto)
+        SELECT $1, $2, totals.holder_count, totals.total_supply,
+            COALESCE(SUM(amount) FILTER (WHERE rank_desc <= 10)
+                / NULLIF(totals.total_supply, 0), 0)::FLOAT8,
+            COALESCE(SUM(amount) FILTER (WHERE rank_desc <= 100)
+                / NULLIF(totals.total_supply, 0), 0)::FLOAT8,
+            COALESCE(2 * SUM(rank_asc * amount)
+                / NULLIF(totals.holder_count * totals.total_supply, 0)
+                - (totals.holder_count + 1)::NUMERIC / NULLIF(totals.holder_count, 0), 0)::FLOAT8,
+            COALESCE(SUM((amount / NULLIF(totals.total_supply, 0))
+                * (amount / NULLIF(totals.total_supply, 0))), 0)::FLOAT8,
+            COUNT(*) FILTER (WHERE cumulative - amount <= totals.total_supply / 2)
+        FROM totals
+        LEFT JOIN ranked ON TRUE
+        GROUP BY totals.holder_count, totals.total_supply
+        ON CONFLICT (token_id) DO UPDATE SET
+            last_checked_block = EXCLUDED.last_checked_block,
+            holder_count = EXCLUDED.holder_count,
+            total_supply = EXCLUDED.total_supply,
+            top10_share = EXCLUDED.top10_share,
+            top100_share = EXCLUDED.top100_share,
+            gini = EXCLUDED.gini,
+            herfindahl = EXCLUDED.herfindahl,
+            nakamoto = EXCLUDED.nakamoto,
+            calculated_at = NOW()
+        RETURNING token_id, last_checked_block, holder_count, total_supply::TEXT,
+            top10_share, top100_share, gini, herfindahl, nakamoto",
+    )
+    .bind(token_id)
+    .bind(last_checked_block)
+    .fetch_one(connection_pool)
+    .await
+}
+
+/// Save current non-zero holder count and total supply of token
+pub async fn add_token_history(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    block_number: &i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO token_history (token_id, block_number, holder_count, total_supply)
+        SELECT $1, $2, COUNT(*), COALESCE(SUM(amount), 0)
+        FROM balance
+        WHERE token_id = $1 AND amount > 0
+        ON CONFLICT (token_id, block_number) DO NOTHING",
+    )
+    .bind(token_id)
+    .bind(block_number)
+    .execute(connection_pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn all_token_history(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    filter: &[Filter],
+    number: i64,
+    size: i64,
+    sort: &[Sort],
+) -> Result<Vec<TokenHistory>, Error> {
+    let mut builder = QueryBuilder::new(
+        "SELECT CONCAT(token_id, '_', block_number) AS id,
+            block_number, holder_count, total_supply::TEXT
+        FROM token_history
+        WHERE token_id = ",
+    );
+    builder.push_bind(*token_id);
+
+    push_filter(&mut builder, filter);
+    push_sort(&mut builder, sort);
+    push_page(&mut builder, number, size);
+
+    builder
+        .build_query_as::<TokenHistory>()
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn all_token_history_count(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    filter: &[Filter],
+) -> Result<i64, Error> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM token_history WHERE token_id = ");
+    builder.push_bind(*token_id);
+
+    push_filter(&mut builder, filter);
+
+    builder
+        .build_query_scalar()
+        .fetch_one(connection_pool)
+        .await
+}
+
+pub async fn add_webhook(
+    connection_pool: &PgPool,
+    url: &str,
+    secret: &str,
+    event: &str,
+    token_id: Option<i32>,
+    holder_addr: Option<&str>,
+    min_amount: Option<&str>,
+) -> Result<Webhook, Error> {
+    let sql = format!(
+        "INSERT INTO webhook (url, secret, event, token_id, holder_addr, min_amount)
+            VALUES ($1, $2, $3, $4, decode($5, 'hex'), $6::NUMERIC)
+            RETURNING {}",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&sql)
+        .bind(url)
+        .bind(secret)
+        .bind(event)
+        .bind(token_id)
+        .bind(holder_addr)
+        .bind(min_amount)
+        .fetch_one(connection_pool)
+        .await
+}
+
+pub async fn webhook_by_id(
+    connection_pool: &PgPool,
+    webhook_id: &i32,
+) -> Result<Option<Webhook>, Error> {
+    let sql = format!(
+        "SELECT {} FROM webhook WHERE webhook_id = $1",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&sql)
+        .bind(webhook_id)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn all_webhook(
+    connection_pool: &PgPool,
+    number: i64,
+    size: i64,
+) -> Result<Vec<Webhook>, Error> {
+    let sql = format!(
+        "SELECT {} FROM webhook ORDER BY webhook_id OFFSET $1 LIMIT $2",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&sql)
+        .bind(number * size)
+        .bind(size)
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn all_webhook_count(connection_pool: &PgPool) -> Result<i64, Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM webhook")
+        .fetch_one(connection_pool)
+        .await
+}
+
+/// Delete webhook with its delivery log, returns false if webhook is missing
+pub async fn delete_webhook(connection_pool: &PgPool, webhook_id: &i32) -> Result<bool, Error> {
+    let result = sqlx::query("DELETE FROM webhook WHERE webhook_id = $1")
+        .bind(webhook_id)
         .execute(connection_pool)
         .await?;
 
+    Ok(result.rows_affected() > 0)
+}
+
+/// Webhooks with rules matching transfer of token between holders
+pub async fn matching_webhooks(
+    connection_pool: &PgPool,
+    token_id: &i32,
+    from_holder_addr: &str,
+    to_holder_addr: &str,
+    amount: &str,
+) -> Result<Vec<Webhook>, Error> {
+    let sql = format!(
+        "SELECT {} FROM webhook
+        WHERE (token_id IS NULL OR token_id = $1)
+            AND (holder_addr IS NULL OR holder_addr IN (decode($2, 'hex'), decode($3, 'hex')))
+            AND (event <> 'transfer' OR min_amount IS NULL OR min_amount <= $4::NUMERIC)",
+        WEBHOOK_COLUMNS
+    );
+
+    sqlx::query_as::<_, Webhook>(&sql)
+        .bind(token_id)
+        .bind(from_holder_addr)
+        .bind(to_holder_addr)
+        .bind(amount)
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn add_webhook_delivery(
+    connection_pool: &PgPool,
+    webhook_id: &i32,
+    payload: &str,
+) -> Result<i32, Error> {
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO webhook_delivery (webhook_id, payload)
+            VALUES ($1, $2)
+            RETURNING delivery_id",
+    )
+    .bind(webhook_id)
+    .bind(payload)
+    .fetch_one(connection_pool)
+    .await
+}
+
+/// Claim due undelivered payloads with attempts left,
+/// claimed deliveries are skipped by other workers until `lease` expires
+pub async fn claim_webhook_deliveries(
+    connection_pool: &PgPool,
+    max_attempts: &i32,
+    limit: i64,
+    lease: Duration,
+) -> Result<Vec<DueWebhookDelivery>, Error> {
+    sqlx::query_as::<_, DueWebhookDelivery>(
+        "WITH due AS (
+            SELECT delivery_id FROM webhook_delivery
+            WHERE NOT delivered AND attempts < $1 AND next_attempt_at <= NOW()
+            ORDER BY next_attempt_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        UPDATE webhook_delivery
+            SET next_attempt_at = NOW() + make_interval(secs => $3)
+            FROM due, webhook
+            WHERE webhook_delivery.delivery_id = due.delivery_id
+                AND webhook.webhook_id = webhook_delivery.webhook_id
+            RETURNING webhook_delivery.delivery_id, webhook_delivery.webhook_id,
+                webhook.url, webhook.secret, webhook_delivery.payload, webhook_delivery.attempts",
+    )
+    .bind(max_attempts)
+    .bind(limit)
+    .bind(lease.as_secs_f64())
+    .fetch_all(connection_pool)
+    .await
+}
+
+/// Log delivery attempt, failed delivery is retried after `retry_in`
+pub async fn update_webhook_delivery(
+    connection_pool: &PgPool,
+    delivery_id: &i32,
+    attempts: &i32,
+    status_code: Option<i32>,
+    delivered: bool,
+    error: Option<&str>,
+    retry_in: Duration,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE webhook_delivery
+                SET attempts = $1, status_code = $2, delivered = $3, error = $4,
+                    next_attempt_at = NOW() + make_interval(secs => $5)
+                WHERE delivery_id = $6",
+    )
+    .bind(attempts)
+    .bind(status_code)
+    .bind(delivered)
+    .bind(error)
+    .bind(retry_in.as_secs_f64())
+    .bind(delivery_id)
+    .execute(connection_pool)
+    .await?;
+
     Ok(())
 }
+
+pub async fn all_webhook_delivery(
+    connection_pool: &PgPool,
+    webhook_id: &i32,
+    number: i64,
+    size: i64,
+) -> Result<Vec<WebhookDelivery>, Error> {
+    let sql = format!(
+        "SELECT {} FROM webhook_delivery
+        WHERE webhook_id = $1
+        ORDER BY delivery_id DESC OFFSET $2 LIMIT $3",
+        WEBHOOK_DELIVERY_COLUMNS
+    );
+
+    sqlx::query_as::<_, WebhookDelivery>(&sql)
+        .bind(webhook_id)
+        .bind(number * size)
+        .bind(size)
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn all_webhook_delivery_count(
+    connection_pool: &PgPool,
+    webhook_id: &i32,
+) -> Result<i64, Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM webhook_delivery WHERE webhook_id = $1")
+        .bind(webhook_id)
+        .fetch_one(connection_pool)
+        .await
+}
+
+/// Add api key by hash of key, returns `None` if key already exists
+pub async fn add_api_key(
+    connection_pool: &PgPool,
+    key_hash: &str,
+    name: &str,
+    scopes: &[String],
+    rate_limit: &i32,
+) -> Result<Option<ApiKey>, Error> {
+    let sql = format!(
+        "INSERT INTO api_key (key_hash, name, scopes, rate_limit)
+            VALUES (decode($1, 'hex'), $2, $3, $4)
+            ON CONFLICT (key_hash) DO NOTHING
+            RETURNING {}",
+        API_KEY_COLUMNS
+    );
+
+    sqlx::query_as::<_, ApiKey>(&sql)
+        .bind(key_hash)
+        .bind(name)
+        .bind(scopes)
+        .bind(rate_limit)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn api_key_by_hash(
+    connection_pool: &PgPool,
+    key_hash: &str,
+) -> Result<Option<ApiKey>, Error> {
+    let sql = format!(
+        "SELECT {} FROM api_key WHERE key_hash = decode($1, 'hex')",
+        API_KEY_COLUMNS
+    );
+
+    sqlx::query_as::<_, ApiKey>(&sql)
+        .bind(key_hash)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn all_api_key(
+    connection_pool: &PgPool,
+    number: i64,
+    size: i64,
+) -> Result<Vec<ApiKey>, Error> {
+    let sql = format!(
+        "SELECT {} FROM api_key ORDER BY api_key_id OFFSET $1 LIMIT $2",
+        API_KEY_COLUMNS
+    );
+
+    sqlx::query_as::<_, ApiKey>(&sql)
+        .bind(number * size)
+        .bind(size)
+        .fetch_all(connection_pool)
+        .await
+}
+
+pub async fn all_api_key_count(connection_pool: &PgPool) -> Result<i64, Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM api_key")
+        .fetch_one(connection_pool)
+        .await
+}
+
+/// Revoke api key, returns false if key is missing
+pub async fn delete_api_key(connection_pool: &PgPool, api_key_id: &i32) -> Result<bool, Error> {
+    let result = sqlx::query("DELETE FROM api_key WHERE api_key_id = $1")
+        .bind(api_key_id)
+        .execute(connection_pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Add pending onboarding job of token.
+/// Returns None if job with the same idempotency key exists,
+/// fails with unique violation if open job of contract exists
+pub async fn add_job(
+    connection_pool: &PgPool,
+    contract_addr: &str,
+    start_block: Option<i64>,
+    idempotency_key: Option<&str>,
+) -> Result<Option<Job>, Error> {
+    let sql = format!(
+        "INSERT INTO job (contract_addr, start_block, idempotency_key)
+            VALUES (decode($1, 'hex'), $2, $3)
+            ON CONFLICT (idempotency_key) DO NOTHING
+            RETURNING {}",
+        JOB_COLUMNS
+    );
+
+    sqlx::query_as::<_, Job>(&sql)
+        .bind(contract_addr)
+        .bind(start_block)
+        .bind(idempotency_key)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn job_by_idempotency_key(
+    connection_pool: &PgPool,
+    idempotency_key: &str,
+) -> Result<Option<Job>, Error> {
+    let sql = format!("SELECT {} FROM job WHERE idempotency_key = $1", JOB_COLUMNS);
+
+    sqlx::query_as::<_, Job>(&sql)
+        .bind(idempotency_key)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+/// Pending or running job of contract
+pub async fn open_job_by_contract(
+    connection_pool: &PgPool,
+    contract_addr: &str,
+) -> Result<Option<Job>, Error> {
+    let sql = format!(
+        "SELECT {} FROM job
+            WHERE contract_addr = decode($1, 'hex') AND state IN ($2, $3)
+            ORDER BY job_id
+            LIMIT 1",
+        JOB_COLUMNS
+    );
+
+    sqlx::query_as::<_, Job>(&sql)
+        .bind(contract_addr)
+        .bind(JobState::Pending.as_str())
+        .bind(JobState::Running.as_str())
+        .fetch_optional(connection_pool)
+        .await
+}
+
+pub async fn job_by_id(connection_pool: &PgPool, job_id: &i32) -> Result<Option<Job>, Error> {
+    let sql = format!("SELECT {} FROM job WHERE job_id = $1", JOB_COLUMNS);
+
+    sqlx::query_as::<_, Job>(&sql)
+        .bind(job_id)
+        .fetch_optional(connection_pool)
+        .await
+}
+
+/// Mark oldest pending job, or running job with expired lease, as running
+/// by worker for `lease` and return it. Jobs locked by other processes are skipped
+pub async fn claim_job(
+    connection_pool: &PgPool,
+    worker: &str,
+    lease: Duration,
+) -> Result<Option<Job>, Error> {
+    let sql = format!(
+        "UPDATE job SET state = $1, claimed_by = $3,
+                lease_expires_at = NOW() + make_interval(secs => $4), updated_at = NOW()
+            WHERE job_id = (
+                SELECT job_id FROM job
+                WHERE state = $2 OR (state = $1 AND lease_expires_at <= NOW())
+                ORDER BY job_id
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING {}",
+        JOB_COLUMNS
+    );
+
+    sqlx::query_as::<_, Job>(&sql)
+        .bind(JobState::Running.as_str())
+        .bind(JobState::Pending.as_str())
+        .bind(worker)
+        .bind(lease.as_secs_f64())
+        .fetch_optional(connection_pool)
+        .await
+}
+
+/// Set final state of job claimed by worker with its token or error.
+/// Returns false if job was claimed by another worker after lease expired
+pub async fn finish_job(
+    connection_pool: &PgPool,
+    job_id: &i32,
+    worker: &str,
+    state: JobState,
+    token_id: Option<i32>,
+    error: Option<&str>,
+) -> Result<bool, Error> {
+    let result = sqlx::query(
+        "UPDATE job SET state = $1, token_id = $2, error = $3, updated_at = NOW()
+            WHERE job_id = $4 AND claimed_by = $5",
+    )
+    .bind(state.as_str())
+    .bind(token_id)
+    .bind(error)
+    .bind(job_id)
+    .bind(worker)
+    .execute(connection_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -13,7 +13,7 @@ macro_rules! app_err_response {
 }
 
 pub struct AppError {
-    body: JsonApiError,
+    body: Box<JsonApiError>,
 }
 
 impl AppError {
@@ -26,38 +26,41 @@ impl AppError {
         tracing::error!("{}", error.to_string());
 
         Self {
-            body: JsonApiError {
+            body: Box::new(JsonApiError {
                 status: Some(code.as_u16().to_string()),
                 title: code.canonical_reason().map(|s| s.to_string()),
                 detail: detail.map(|s| s.to_string()),
                 source: Self::get_source(param_name),
                 ..Default::default()
-            },
+            }),
         }
     }
 
+    /// Link to resource the error is about
+    pub fn with_about_link(mut self, url: String) -> Self {
+        self.body.links = Some([("about".to_string(), url.into())].into_iter().collect());
+        self
+    }
+
     fn get_source(param_name: Option<&str>) -> Option<ErrorSource> {
-        match param_name {
-            Some(param_name) => Some(ErrorSource {
-                pointer: Some(format!("/data/attributes/{}", param_name)),
-                parameter: Some(param_name.to_string()),
-            }),
-            None => None,
-        }
+        param_name.map(|param_name| ErrorSource {
+            pointer: Some(format!("/data/attributes/{}", param_name)),
+            parameter: Some(param_name.to_string()),
+        })
     }
 }
 
 pub struct AppErrorResponse {
     code: StatusCode,
-    body: Json<JsonApiDocument>,
+    body: Box<JsonApiDocument>,
 }
 
 impl AppErrorResponse {
     pub fn new(code: StatusCode, errors: Vec<AppError>) -> Self {
         Self {
             code,
-            body: Json(JsonApiDocument::Error(DocumentError {
-                errors: errors.iter().map(|err| err.body.clone()).collect(),
+            body: Box::new(JsonApiDocument::Error(DocumentError {
+                errors: errors.into_iter().map(|err| *err.body).collect(),
                 ..Default::default()
             })),
         }
@@ -66,7 +69,7 @@ impl AppErrorResponse {
 
 impl IntoResponse for AppErrorResponse {
     fn into_response(self) -> Response {
-        (self.code, self.body).into_response()
+        (self.code, Json(*self.body)).into_response()
     }
 }
 
@@ -75,3 +78,9 @@ impl From<sqlx::Error> for AppErrorResponse {
         app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, value)
     }
 }
+
+impl From<anyhow::Error> for AppErrorResponse {
+    fn from(value: anyhow::Error) -> Self {
+        app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, value)
+    }
+}
@@ -1,56 +1,367 @@
-use crate::db::{self, Token};
-use anyhow::Result;
-use ethers::{prelude::ProviderError::JsonRpcClientError, prelude::*, utils::hex::ToHex};
-use sqlx::PgPool;
-use std::{env, sync::Arc};
-use tokio::sync::mpsc;
+use crate::{
+    amount::Amount,
+    chain::{ChainError, DecimalsCall, DecimalsReturn, SharedChain, SymbolCall, SymbolReturn},
+    model::{Job, JobState, SyncState, Token},
+    storage::SharedStorage,
+    telemetry, webhook,
+};
+use anyhow::{anyhow, Result};
+use ethers::{
+    core::rand,
+    prelude::*,
+    utils::{hex, hex::ToHex},
+};
+use futures::{
+    stream::{self, StreamExt},
+    FutureExt,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    panic::AssertUnwindSafe,
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, oneshot};
 
-/// Create websocket provider
-pub async fn create_provider() -> Result<Arc<Provider<Ws>>> {
-    let provider = Provider::<Ws>::connect(env::var("RPC_URL_WS")?).await?;
-    Ok(Arc::new(provider))
+/// Transfer of token applied to holder balances
+#[derive(Debug, Serialize, Clone)]
+pub struct TransferEvent {
+    pub token: String,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+    pub from_balance: String,
+    pub to_balance: String,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<String>,
 }
 
-/// Add tokens to db if its empty and start updating all tokens
-pub async fn update_db(
-    connection_pool: PgPool,
-    provider: Arc<Provider<Ws>>,
-    mut rx: mpsc::Receiver<Token>,
+/// Sender of live transfers applied by log listeners
+pub type EventSender = broadcast::Sender<TransferEvent>;
+
+/// Interval of checking db for tokens added by other processes
+const TOKEN_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval of checking db for pending onboarding jobs
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time of job claim, job of stopped indexer is claimed by another one after it
+const JOB_LEASE: Duration = Duration::from_secs(60);
+
+/// Time of token claim, renewed by every token poll.
+/// Token of stopped indexer is taken over by another one after it
+const TOKEN_LEASE: Duration = Duration::from_secs(60);
+
+/// Delay before first restart of failed token, doubled for every next failure
+const FIRST_RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// Max delay before restart of failed token, failure count is reset
+/// if token was indexed longer than that
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(600);
+
+/// Interval of checking chain head while listening to logs
+const HEAD_POLL_INTERVAL: Duration = Duration::from_secs(12);
+
+/// Symbol of token until metadata is fetched from contract
+const UNKNOWN_SYMBOL: &str = "UNKNOWN";
+
+/// Positive number from env, default if it is not set or invalid
+fn env_number(name: &str, default: i64) -> i64 {
+    env::var(name)
+        .ok()
+        .and_then(|number| number.parse().ok())
+        .filter(|number| *number > 0)
+        .unwrap_or(default)
+}
+
+/// Blocks between holder history records
+fn history_block_interval() -> i64 {
+    env_number("HISTORY_BLOCK_INTERVAL", 7200)
+}
+
+/// Settings of token backfill
+#[derive(Debug, Clone, Copy)]
+struct BackfillConfig {
+    /// Partitions fetched in parallel
+    workers: usize,
+    /// Blocks in partition
+    partition_blocks: i64,
+    /// Blocks between holder history records
+    history_interval: i64,
+}
+
+impl BackfillConfig {
+    fn from_env() -> Self {
+        Self {
+            workers: env_number("BACKFILL_WORKERS", 4) as usize,
+            partition_blocks: env_number("BACKFILL_PARTITION_BLOCKS", 100_000),
+            history_interval: history_block_interval(),
+        }
+    }
+}
+
+/// Record holder count and total supply at every multiple of history interval
+/// crossed by token checkpoint, balances must be unchanged since the first of them
+async fn record_token_history(
+    storage: &SharedStorage,
+    token_id: &i32,
+    prev_block: i64,
+    block: i64,
+    interval: i64,
 ) -> Result<()> {
-    let mut token_count = db::all_token_count(&connection_pool).await?;
+    let mut boundary = (prev_block.div_euclid(interval) + 1) * interval;
 
-    if token_count == 0 {
-        token_count = add_start_tokens(&connection_pool).await?;
+    while boundary <= block {
+        storage.add_token_history(token_id, &boundary).await?;
+        boundary += interval;
     }
 
-    let mut set = tokio::task::JoinSet::new();
-    let tokens = db::all_token(&connection_pool, 0, token_count, None).await?;
+    Ok(())
+}
+
+/// Block ranges of backfill, cut at history interval multiples
+/// so history is recorded right after the boundary block is applied
+fn backfill_partitions(
+    start: i64,
+    last_block: i64,
+    partition_blocks: i64,
+    interval: i64,
+) -> impl Iterator<Item = (i64, i64)> {
+    let partition = move |start: i64| {
+        let boundary = (start + interval - 1).div_euclid(interval) * interval;
+        (
+            start,
+            (start + partition_blocks - 1).min(boundary).min(last_block),
+        )
+    };
+
+    std::iter::successors(Some(partition(start)), move |&(_, end)| {
+        (end < last_block).then(|| partition(end + 1))
+    })
+}
+
+/// Delay before next attempt after consecutive failures
+fn restart_delay(failures: u32) -> Duration {
+    FIRST_RESTART_DELAY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_RESTART_DELAY)
+}
 
-    for token in tokens.iter() {
-        set.spawn(add_balances_by_token(
-            connection_pool.clone(),
-            provider.clone(),
-            token.clone(),
-        ));
+/// Add tokens to db if its empty and start updating all tokens.
+/// Onboarding jobs, tokens added by other processes and reindex requests are picked up from db periodically.
+/// Tokens are leased to this process, so every token is indexed by one replica at a time.
+/// Failed token is restarted with backoff, other tokens keep running.
+/// Storage errors of the loop are retried with backoff too
+pub async fn update_db(
+    storage: SharedStorage,
+    chain: SharedChain,
+    events: EventSender,
+) -> Result<()> {
+    if storage.all_token_count().await? == 0 {
+        add_start_tokens(&storage).await?;
     }
 
+    tokio::spawn(webhook::deliver_pending(storage.clone()));
+
+    // Jobs and tokens of this process are leased to its id, so other replicas
+    // take them over only when their lease expires
+    let worker = hex::encode(rand::random::<[u8; 8]>());
+
+    let mut set = tokio::task::JoinSet::new();
+    // Stop signals of started tokens
+    let mut started: HashMap<i32, oneshot::Sender<()>> = HashMap::new();
+    // Start blocks of stopping tokens, they are reset when their task ends
+    let mut reindexing: HashMap<i32, i64> = HashMap::new();
+    // Consecutive failures of tokens and time of their next start
+    let mut restarts: HashMap<i32, (u32, Instant)> = HashMap::new();
+    // Consecutive storage failures of the loop and time of next poll
+    let (mut failures, mut retry_at) = (0, Instant::now());
+    let mut poll = tokio::time::interval(TOKEN_POLL_INTERVAL);
+    let mut jobs = tokio::time::interval(JOB_POLL_INTERVAL);
+
+    let spawn = |set: &mut tokio::task::JoinSet<_>, token: Token| {
+        let (stop, stopped) = oneshot::channel();
+        let token_id = token.id;
+        let task = add_balances_by_token(storage.clone(), chain.clone(), token, events.clone());
+
+        set.spawn(async move {
+            let started_at = Instant::now();
+            let result = tokio::select! {
+                res = task => res,
+                _ = stopped => Ok(()),
+            };
+
+            (token_id, started_at.elapsed(), result)
+        });
+
+        stop
+    };
+
     loop {
-        tokio::select! {
-            Some(res) = set.join_next() => res??, //BUG: Check if its skip err when spawn new task
-            Some(token) = rx.recv() => {
-                set.spawn(add_balances_by_token(
-                    connection_pool.clone(),
-                    provider.clone(),
-                    token.clone(),
-                ));
+        let result: Result<()> = tokio::select! {
+            Some(res) = set.join_next() => {
+                let (token_id, run_time, result) = res?;
+
+                // Stopped token is reset even if its task ended with error,
+                // reset that failed is retried by poll of reindex requests
+                if let Some(start_block) = reindexing.remove(&token_id) {
+                    reindex_token(&storage, &token_id, &start_block)
+                        .await
+                        .map(|token| {
+                            started.insert(token_id, spawn(&mut set, token));
+                        })
+                } else if let Err(err) = result {
+                    let failures = match restarts.get(&token_id) {
+                        Some((failures, _)) if run_time < MAX_RESTART_DELAY => failures + 1,
+                        _ => 1,
+                    };
+                    let delay = restart_delay(failures);
+
+                    tracing::error!(
+                        "Token: Id: {}; Error: {}; Restart in: {:?};",
+                        token_id,
+                        err,
+                        delay
+                    );
+
+                    // Token is started again by poll after delay,
+                    // other replicas can take it over meanwhile
+                    started.remove(&token_id);
+                    restarts.insert(token_id, (failures, Instant::now() + delay));
+                    storage.release_token(&token_id, &worker).await
+                } else {
+                    Ok(())
+                }
+            },
+            _ = jobs.tick(), if Instant::now() >= retry_at => async {
+                while let Some(job) = storage.claim_job(&worker, JOB_LEASE).await? {
+                    if let Some(token) = run_job(&storage, &worker, job).await? {
+                        if !started.contains_key(&token.id)
+                            && storage.claim_token(&token.id, &worker, TOKEN_LEASE).await?
+                        {
+                            started.insert(token.id, spawn(&mut set, token));
+                        }
+                    }
+                }
+
+                Ok(())
+            }.await,
+            _ = poll.tick(), if Instant::now() >= retry_at => async {
+                for (token_id, start_block) in storage.all_reindex_request().await? {
+                    if reindexing.contains_key(&token_id) {
+                        continue;
+                    }
+
+                    // Running task is stopped first to not write balances after reset,
+                    // token of another replica is reset by it
+                    match started.remove(&token_id).map(|stop| stop.send(())) {
+                        Some(Ok(())) => {
+                            reindexing.insert(token_id, start_block);
+                        }
+                        _ => {
+                            if storage.claim_token(&token_id, &worker, TOKEN_LEASE).await? {
+                                let token = reindex_token(&storage, &token_id, &start_block).await?;
+                                started.insert(token_id, spawn(&mut set, token));
+                            }
+                        }
+                    }
+                }
+
+                let token_count = storage.all_token_count().await?;
+
+                for token in storage.all_token(0, token_count, &[]).await? {
+                    let waiting = restarts
+                        .get(&token.id)
+                        .is_some_and(|(_, restart_at)| *restart_at > Instant::now());
+
+                    if started.contains_key(&token.id) || reindexing.contains_key(&token.id) {
+                        // Lease is renewed, task is stopped if another replica took token over
+                        if !storage.claim_token(&token.id, &worker, TOKEN_LEASE).await? {
+                            tracing::warn!("Token: Id: {}; Lease lost;", token.id);
+
+                            if let Some(stop) = started.remove(&token.id) {
+                                let _ = stop.send(());
+                            }
+                        }
+                    } else if !waiting
+                        && storage.claim_token(&token.id, &worker, TOKEN_LEASE).await?
+                    {
+                        started.insert(token.id, spawn(&mut set, token));
+                    }
+                }
+
+                Ok(())
+            }.await,
+        };
+
+        match result {
+            Ok(()) => failures = 0,
+            Err(err) => {
+                failures += 1;
+                let delay = restart_delay(failures);
+
+                tracing::error!("Indexer: Error: {}; Retry in: {:?};", err, delay);
+                retry_at = Instant::now() + delay;
             }
         }
     }
 }
 
+/// Add token of claimed onboarding job and finish the job, returns token of job
+async fn run_job(storage: &SharedStorage, worker: &str, job: Job) -> Result<Option<Token>> {
+    // Token could be added by cli after job was queued
+    let added = match storage.token_by_contract(&job.contract_addr).await? {
+        Some(token) => Ok(token),
+        None => add_token_by_contract(storage, &job.contract_addr, job.start_block).await,
+    };
+
+    let finished = match &added {
+        Ok(token) => {
+            storage
+                .finish_job(&job.id, worker, JobState::Done, Some(token.id), None)
+                .await?
+        }
+        Err(err) => {
+            tracing::warn!("Job: Id: {}; Error: {};", job.id, err);
+            storage
+                .finish_job(
+                    &job.id,
+                    worker,
+                    JobState::Failed,
+                    None,
+                    Some(&err.to_string()),
+                )
+                .await?
+        }
+    };
+
+    if !finished {
+        tracing::warn!("Job: Id: {}; Lease expired;", job.id);
+    }
+
+    Ok(added.ok())
+}
+
+/// Clear balances of token and move its checkpoint before `start_block`
+async fn reindex_token(
+    storage: &SharedStorage,
+    token_id: &i32,
+    start_block: &i64,
+) -> Result<Token> {
+    tracing::debug!("Reindex: Token: {}; Block: {};", token_id, start_block);
+
+    storage.reset_token(token_id, &(start_block - 1)).await?;
+
+    storage
+        .token_by_id(token_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Token {token_id} not found"))
+}
+
 /// Add some start tokens to db (TRX, TONCOIN, LEO, INJ, FDUSD)
-async fn add_start_tokens(connection_pool: &PgPool) -> Result<i64> {
-    let addresses = vec![
+async fn add_start_tokens(storage: &SharedStorage) -> Result<i64> {
+    let addresses = [
         "50327c6c5a14DCaDE707ABad2E27eB517df87AB5", //trx 24,352
         "582d872A1B094FC48F5DE31D3B73F2D9bE47def1", //toncoin 94,646
         "2AF5D2aD76741191D15Dfe7bF6aC92d4Bd912Ca3", //leo 41,588
@@ -59,26 +370,26 @@ async fn add_start_tokens(connection_pool: &PgPool) -> Result<i64> {
     ];
 
     for contract_addr in addresses.iter() {
-        add_token_by_contract(connection_pool, contract_addr).await?;
+        add_token_by_contract(storage, contract_addr, None).await?;
     }
 
     Ok(addresses.len() as i64)
 }
 
-/// Add token to db using contract address
-pub async fn add_token_by_contract(connection_pool: &PgPool, contract_addr: &str) -> Result<Token> {
-    let symbol = "UNKNOWN".to_string();
+/// Add token to db using contract address, backfill starts at `start_block`.
+/// Without it indexer starts at deployment block of contract
+pub async fn add_token_by_contract(
+    storage: &SharedStorage,
+    contract_addr: &str,
+    start_block: Option<i64>,
+) -> Result<Token> {
+    let symbol = UNKNOWN_SYMBOL.to_string();
     let decimals = 0;
-    let last_checked_block = -1;
-
-    let token_id = db::add_token(
-        connection_pool,
-        contract_addr,
-        &last_checked_block,
-        &symbol,
-        &decimals,
-    )
-    .await?;
+    let last_checked_block = start_block.map_or(-1, |start_block| start_block - 1);
+
+    let token_id = storage
+        .add_token(contract_addr, &last_checked_block, &symbol, &decimals)
+        .await?;
 
     Ok(Token {
         id: token_id,
@@ -86,52 +397,309 @@ pub async fn add_token_by_contract(connection_pool: &PgPool, contract_addr: &str
         last_checked_block,
         symbol,
         decimals,
+        sync_state: SyncState::Backfilling.as_str().to_string(),
+        head_block: 0,
+        lag: 0,
+        backfill_rate: 0.0,
+        eta: None,
+        sync_error: None,
     })
 }
 
+/// Fetch ERC20 symbol and decimals of token from contract and save them to db.
+/// Keep unknown metadata if contract does not implement them
+async fn update_token_metadata(
+    storage: &SharedStorage,
+    chain: SharedChain,
+    token: &mut Token,
+) -> Result<()> {
+    let contract = token.contract_addr.parse::<Address>()?;
+
+    let (symbol, decimals) = match (
+        call::<_, SymbolReturn>(&chain, contract, SymbolCall).await,
+        call::<_, DecimalsReturn>(&chain, contract, DecimalsCall).await,
+    ) {
+        (Ok(SymbolReturn(symbol)), Ok(DecimalsReturn(decimals))) => (symbol, decimals as i16),
+        (symbol, decimals) => {
+            tracing::warn!(
+                "Token metadata: Id: {}; Symbol: {:?}; Decimals: {:?};",
+                token.id,
+                symbol.err(),
+                decimals.err()
+            );
+            return Ok(());
+        }
+    };
+
+    storage
+        .update_token_metadata(&token.id, &symbol, &decimals)
+        .await?;
+
+    token.symbol = symbol;
+    token.decimals = decimals;
+
+    Ok(())
+}
+
+/// Call contract and decode its output
+async fn call<C: abi::AbiEncode, R: abi::AbiDecode>(
+    chain: &SharedChain,
+    to: Address,
+    call: C,
+) -> Result<R> {
+    let output = telemetry::rpc("eth_call", chain.call(to, call.encode().into())).await?;
+    Ok(R::decode(output)?)
+}
+
 /// Add balances to db from evm network
-/// and subscribe on new logs for this token
+/// and subscribe on new logs for this token.
+/// Mark token as failed if indexing stops with error or panics
 async fn add_balances_by_token(
-    connection_pool: PgPool,
-    provider: Arc<Provider<Ws>>,
+    storage: SharedStorage,
+    chain: SharedChain,
+    token: Token,
+    events: EventSender,
+) -> Result<()> {
+    let token_id = token.id;
+    let result = AssertUnwindSafe(backfill_and_listen(storage.clone(), chain, token, events))
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("Indexing of token panicked")));
+
+    if let Err(err) = &result {
+        storage
+            .update_token_sync(
+                &token_id,
+                SyncState::Failed,
+                &0,
+                None,
+                Some(&err.to_string()),
+            )
+            .await?;
+    }
+
+    result
+}
+
+async fn backfill_and_listen(
+    storage: SharedStorage,
+    chain: SharedChain,
     mut token: Token,
+    events: EventSender,
 ) -> Result<()> {
-    let mut last_block: i64 = provider.get_block_number().await?.as_u32().into();
+    if token.symbol == UNKNOWN_SYMBOL {
+        update_token_metadata(&storage, chain.clone(), &mut token).await?;
+    }
 
-    let mut from = token.last_checked_block + 1;
-    let mut step = 1_000_000;
+    let last_block = telemetry::rpc("eth_blockNumber", chain.block_number()).await? as i64;
 
-    if from + step > last_block {
-        step = last_block - from;
+    let contract = token.contract_addr.parse::<Address>()?;
+
+    // Token without checkpoint is backfilled from deployment of its contract.
+    // Nodes without state history can't tell it, then backfill starts from genesis
+    if token.last_checked_block < 0 {
+        match find_deployment_block(&chain, contract, last_block as u64).await {
+            Ok(Some(deployment_block)) => {
+                tracing::debug!(
+                    "Deployment: Token: {}; Block: {};",
+                    token.contract_addr,
+                    deployment_block
+                );
+
+                token.last_checked_block = deployment_block as i64 - 1;
+                storage
+                    .update_token_last_checked_block(&token.last_checked_block, &token.id)
+                    .await?;
+            }
+            Ok(None) => tracing::warn!("No contract code: Token: {};", token.contract_addr),
+            Err(err) => tracing::warn!(
+                "Deployment: Token: {}; Error: {};",
+                token.contract_addr,
+                err
+            ),
+        }
     }
 
-    let mut filter = Filter::new()
-        .address(token.contract_addr.parse::<Address>()?)
+    backfill(
+        &storage,
+        &chain,
+        &mut token,
+        last_block,
+        BackfillConfig::from_env(),
+    )
+    .await?;
+
+    log_listener(storage, chain, token, events).await?;
+
+    Ok(())
+}
+
+/// Fetch balances of token from its checkpoint up to `last_block`
+/// and following blocks produced meanwhile
+async fn backfill(
+    storage: &SharedStorage,
+    chain: &SharedChain,
+    token: &mut Token,
+    mut last_block: i64,
+    config: BackfillConfig,
+) -> Result<()> {
+    let contract = token.contract_addr.parse::<Address>()?;
+    let start_from = token.last_checked_block + 1;
+    let start_time = Instant::now();
+
+    storage
+        .update_token_sync(&token.id, SyncState::Backfilling, &last_block, None, None)
+        .await?;
+
+    while token.last_checked_block + 1 < last_block {
+        let partitions = backfill_partitions(
+            token.last_checked_block + 1,
+            last_block,
+            config.partition_blocks,
+            config.history_interval,
+        );
+
+        // Partitions are fetched in parallel and merged in order,
+        // so checkpoint moves only over contiguous ranges
+        let mut partitions = stream::iter(partitions)
+            .map(|(start, end)| fetch_partition(chain, contract, &token.contract_addr, start, end))
+            .buffered(config.workers);
+
+        while let Some(partition) = partitions.next().await {
+            let partition = partition?;
+
+            storage
+                .apply_balance_deltas(&token.id, &partition.deltas, &partition.end)
+                .await?;
+            record_token_history(
+                storage,
+                &token.id,
+                token.last_checked_block,
+                partition.end,
+                config.history_interval,
+            )
+            .await?;
+            token.last_checked_block = partition.end;
+
+            tracing::debug!(
+                "Update: Token: {}; Block: {}; Logs: {};",
+                token.contract_addr,
+                partition.end,
+                partition.logs
+            );
+
+            telemetry::logs_processed(&token.contract_addr, partition.logs);
+            telemetry::token_lag(&token.contract_addr, last_block, partition.end);
+
+            let backfill_rate =
+                (partition.end + 1 - start_from) as f64 / start_time.elapsed().as_secs_f64();
+
+            storage
+                .update_token_sync(
+                    &token.id,
+                    SyncState::Backfilling,
+                    &last_block,
+                    Some(backfill_rate),
+                    None,
+                )
+                .await?;
+        }
+
+        // Blocks produced during backfill are left to listener if there are few of them
+        let actual_last_block =
+            telemetry::rpc("eth_blockNumber", chain.block_number()).await? as i64;
+        if actual_last_block - last_block <= 10 {
+            break;
+        }
+        last_block = actual_last_block;
+    }
+
+    Ok(())
+}
+
+/// Lowest block with code of contract, binary search over `eth_getCode`.
+/// None if there is no code at head block
+async fn find_deployment_block(
+    chain: &SharedChain,
+    contract: Address,
+    head_block: u64,
+) -> Result<Option<u64>, ChainError> {
+    let has_code = |block: u64| async move {
+        let code = telemetry::rpc("eth_getCode", chain.get_code(contract, block)).await?;
+        Ok::<_, ChainError>(!code.is_empty())
+    };
+
+    if !has_code(head_block).await? {
+        return Ok(None);
+    }
+
+    let (mut low, mut high) = (0, head_block);
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        if has_code(middle).await? {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    Ok(Some(low))
+}
+
+/// Net balance changes of holders in range of blocks
+struct Partition {
+    end: i64,
+    /// Non-zero changes as (holder_addr, signed amount), ordered by address
+    deltas: Vec<(String, String)>,
+    logs: usize,
+}
+
+/// Fetch transfer logs of blocks `start..=end` and sum them up by holder.
+/// Step of logs requests grows while there are few logs and shrinks on `-32005` response
+async fn fetch_partition(
+    chain: &SharedChain,
+    contract: Address,
+    contract_addr: &str,
+    start: i64,
+    end: i64,
+) -> Result<Partition> {
+    let filter = Filter::new()
+        .address(contract)
         .event("Transfer(address,address,uint256)");
 
-    while from < last_block {
-        filter = filter.select(from..from + step);
+    let mut deltas: BTreeMap<Address, Amount> = BTreeMap::new();
+    let mut logs_count = 0;
+    let mut from = start;
+    let mut step = end - start;
+
+    while from <= end {
+        let to = (from + step).min(end);
 
-        let logs = provider.get_logs(&filter).await;
+        telemetry::backfill_step(contract_addr, step);
+
+        let logs = telemetry::rpc(
+            "eth_getLogs",
+            chain.get_logs(&filter.clone().from_block(from).to_block(to)),
+        )
+        .await;
 
         match logs {
             Ok(logs) => {
-                for log in logs.iter() {
-                    upsert_balance_from_log(&connection_pool, log, &token.id).await?;
-                }
+                for log in logs.iter().filter(|log| log.topics[1] != log.topics[2]) {
+                    let amount = Amount::from(U256::from_big_endian(&log.data));
 
-                from += step;
-
-                tracing::debug!(
-                    "Update: Token: {}; Block: {}; Logs: {};",
-                    token.contract_addr,
-                    from,
-                    logs.len()
-                );
+                    for (topic, delta) in [(log.topics[1], -amount), (log.topics[2], amount)] {
+                        let balance = deltas.entry(Address::from(topic)).or_default();
+                        *balance = balance
+                            .checked_add(delta)
+                            .ok_or_else(|| anyhow!("Balance overflow"))?;
+                    }
+                }
+                logs_count += logs.len();
 
-                db::update_token_last_checked_block(&connection_pool, &from, &token.id).await?;
-                token.last_checked_block = from;
-                from += 1;
+                from = to + 1;
 
                 step = match logs.len() {
                     0..=100 => step * 2,
@@ -139,17 +707,10 @@ async fn add_balances_by_token(
                     1001..=5000 => step + step / 4,
                     _ => step,
                 };
-
-                if from + step > last_block {
-                    let actual_last_block: i64 = provider.get_block_number().await?.as_u32().into();
-                    if actual_last_block - last_block > 10 {
-                        last_block = actual_last_block;
-                    }
-                    step = last_block - from;
-                }
             }
-            Err(JsonRpcClientError(err)) if err.to_string().contains("code: -32005") => {
-                tracing::debug!("Too Many: Token: {}; Block: {};", token.contract_addr, from);
+            Err(ChainError::LimitExceeded) => {
+                tracing::debug!("Too Many: Token: {}; Block: {};", contract_addr, from);
+                telemetry::rpc_limit_retry(contract_addr);
 
                 step /= 3;
 
@@ -163,40 +724,82 @@ async fn add_balances_by_token(
         }
     }
 
-    log_listener(connection_pool, provider, token).await?;
+    let deltas = deltas
+        .into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|(holder_addr, delta)| (holder_addr.encode_hex(), delta.to_string()))
+        .collect();
 
-    Ok(())
+    Ok(Partition {
+        end,
+        deltas,
+        logs: logs_count,
+    })
 }
 
-/// Insert new holders from log and upsert balance
+/// Insert new holders from log and upsert balance,
+/// publish transfer to streams and matching webhooks
+/// Apply transfer of log and move checkpoint of token to `last_checked_block` with it
 async fn upsert_balance_from_log(
-    connection_pool: &PgPool,
+    storage: &SharedStorage,
     log: &Log,
-    token_id: &i32,
+    token: &Token,
+    last_checked_block: &i64,
+    events: &EventSender,
 ) -> Result<()> {
-    if log.topics[1] != log.topics[2] {
-        let from_holder_id = db::add_or_get_holder(
-            connection_pool,
-            &Address::from(log.topics[1]).encode_hex_upper::<String>(),
-        )
-        .await?;
+    if log.topics[1] == log.topics[2] {
+        storage
+            .update_token_last_checked_block(last_checked_block, &token.id)
+            .await?;
+    } else {
+        let from_holder_addr = Address::from(log.topics[1]).encode_hex::<String>();
+        let to_holder_addr = Address::from(log.topics[2]).encode_hex::<String>();
 
-        let to_holder_id = db::add_or_get_holder(
-            connection_pool,
-            &Address::from(log.topics[2]).encode_hex_upper::<String>(),
-        )
-        .await?;
+        let from_holder_id = storage.add_or_get_holder(&from_holder_addr).await?;
+        let to_holder_id = storage.add_or_get_holder(&to_holder_addr).await?;
 
         let amount = U256::from_big_endian(&log.data).to_string();
 
-        db::upsert_balance(
-            connection_pool,
-            &from_holder_id,
-            &to_holder_id,
-            &token_id,
-            &amount,
-        )
-        .await?;
+        let balances = storage
+            .upsert_balance(
+                &from_holder_id,
+                &to_holder_id,
+                &token.id,
+                &amount,
+                last_checked_block,
+            )
+            .await?;
+
+        let balance_of = |holder_id: i32| {
+            balances
+                .iter()
+                .find(|(id, _)| *id == holder_id)
+                .map(|(_, amount)| amount.clone())
+                .unwrap_or_default()
+        };
+
+        let event = TransferEvent {
+            token: token.contract_addr.clone(),
+            from: from_holder_addr,
+            to: to_holder_addr,
+            amount,
+            from_balance: balance_of(from_holder_id),
+            to_balance: balance_of(to_holder_id),
+            block_number: log.block_number.map(|block| block.as_u64()),
+            transaction_hash: log.transaction_hash.map(|hash| hash.encode_hex()),
+        };
+
+        // Webhook failure must not stop indexing of token
+        if let Err(err) = webhook::notify(storage, &token.id, &event).await {
+            tracing::error!(
+                "Webhook notify: Token: {}; Error: {};",
+                token.contract_addr,
+                err
+            );
+        }
+
+        // Error means that there are no active streams
+        let _ = events.send(event);
     }
 
     Ok(())
@@ -204,25 +807,69 @@ async fn upsert_balance_from_log(
 
 /// Subscribe on new logs for token from evm network
 pub async fn log_listener(
-    connection_pool: PgPool,
-    provider: Arc<Provider<Ws>>,
-    token: Token,
+    storage: SharedStorage,
+    chain: SharedChain,
+    mut token: Token,
+    events: EventSender,
 ) -> Result<()> {
+    let filter = Filter::new()
+        .address(token.contract_addr.parse::<Address>()?)
+        .event("Transfer(address,address,uint256)")
+        .select((token.last_checked_block + 1)..);
+
+    let live = telemetry::rpc("eth_subscribe", chain.subscribe_logs(&filter)).await?;
+
+    // Subscription yields logs of blocks produced after it only, so blocks
+    // from checkpoint up to head read after subscribing are fetched here
+    let mut head_block = telemetry::rpc("eth_blockNumber", chain.block_number()).await? as i64;
+    let gap_end = head_block;
+
     tracing::debug!(
         "Start Listen: Token: {}; Block: {}/{}",
         token.contract_addr,
         token.last_checked_block,
-        provider.get_block_number().await?
+        head_block
     );
 
-    let filter = Filter::new()
-        .address(token.contract_addr.parse::<Address>()?)
-        .event("Transfer(address,address,uint256)")
-        .select((token.last_checked_block + 1)..);
+    let gap = if token.last_checked_block < gap_end {
+        let filter = filter.clone().to_block(gap_end);
+        telemetry::rpc("eth_getLogs", chain.get_logs(&filter)).await?
+    } else {
+        Vec::new()
+    };
+
+    let mut stream = stream::iter(gap).chain(live.filter(move |log| {
+        let block_num = log.block_number.map(|block_num| block_num.as_u64() as i64);
+        futures::future::ready(block_num.is_some_and(|block_num| block_num > gap_end))
+    }));
+
+    storage
+        .update_token_sync(&token.id, SyncState::Live, &head_block, None, None)
+        .await?;
+    telemetry::token_lag(&token.contract_addr, head_block, token.last_checked_block);
+
+    let history_interval = history_block_interval();
+    let mut head_poll = tokio::time::interval(HEAD_POLL_INTERVAL);
+    head_poll.tick().await;
+
+    loop {
+        let log = tokio::select! {
+            log = stream.next() => match log {
+                Some(log) => log,
+                None => break,
+            },
+            _ = head_poll.tick() => {
+                // Head comes from chain, so stalled stream shows up as growing lag
+                head_block = telemetry::rpc("eth_blockNumber", chain.block_number()).await? as i64;
 
-    let mut stream = provider.subscribe_logs(&filter).await?;
+                storage
+                    .update_token_sync(&token.id, SyncState::Live, &head_block, None, None)
+                    .await?;
+                telemetry::token_lag(&token.contract_addr, head_block, token.last_checked_block);
+                continue;
+            }
+        };
 
-    while let Some(log) = stream.next().await {
         tracing::debug!(
             "NewLog: Token: {}; Block: {:?}; Hash: {:?};",
             token.contract_addr,
@@ -231,16 +878,407 @@ pub async fn log_listener(
         );
 
         if let Some(block_num) = log.block_number {
-            db::update_token_last_checked_block(
-                &connection_pool,
-                &block_num.as_u32().into(),
+            let block_num: i64 = block_num.as_u32().into();
+
+            // Balances are complete up to previous block on new block log
+            record_token_history(
+                &storage,
                 &token.id,
+                token.last_checked_block,
+                block_num - 1,
+                history_interval,
             )
             .await?;
 
-            upsert_balance_from_log(&connection_pool, &log, &token.id).await?;
+            // Checkpoint stays before block of log, so that rest of block
+            // is fetched again if indexer stops in the middle of it
+            let last_checked_block = token.last_checked_block.max(block_num - 1);
+            upsert_balance_from_log(&storage, &log, &token, &last_checked_block, &events).await?;
+            token.last_checked_block = last_checked_block;
+            telemetry::logs_processed(&token.contract_addr, 1);
         }
     }
 
+    tracing::debug!("Stop Listen: Token: {};", token.contract_addr);
+
+    storage
+        .update_token_sync(&token.id, SyncState::Paused, &head_block, None, None)
+        .await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chain::MockChain, storage::MemoryStorage};
+    use std::sync::Arc;
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    /// Contract of token, holders are addresses 1, 2, 3
+    fn contract() -> Address {
+        address(0xC0FFEE)
+    }
+
+    fn mint() -> Address {
+        Address::zero()
+    }
+
+    async fn token(storage: &SharedStorage, start_block: i64) -> Token {
+        add_token_by_contract(
+            storage,
+            &contract().encode_hex::<String>(),
+            Some(start_block),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn balance_of(storage: &SharedStorage, holder: Address) -> String {
+        let holder = crate::model::Filter {
+            column: crate::model::Column::HolderAddr,
+            operator: crate::model::Operator::In,
+            values: vec![holder.encode_hex()],
+        };
+
+        storage
+            .all_balance_by_filter(&[holder], 0, 1, &[])
+            .await
+            .unwrap()
+            .first()
+            .map(|balance| balance.amount.clone())
+            .unwrap_or_else(|| "0".to_string())
+    }
+
+    #[tokio::test]
+    async fn backfill_step_shrinks_on_limit_and_grows_after_success() {
+        let mock = Arc::new(MockChain::new(&[1000]).max_range(100));
+        let chain: SharedChain = mock.clone();
+
+        fetch_partition(&chain, contract(), "token", 0, 999)
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        // Step is divided by 3 on every -32005 and doubled after empty range
+        assert_eq!(
+            requests[..5],
+            [(0, 999), (0, 333), (0, 111), (0, 37), (38, 112)]
+        );
+
+        let fetched: Vec<(u64, u64)> = requests
+            .into_iter()
+            .filter(|(from, to)| to - from < 100)
+            .collect();
+        assert_eq!(fetched.first().unwrap().0, 0);
+        assert_eq!(fetched.last().unwrap().1, 999);
+        assert!(fetched.windows(2).all(|pair| pair[1].0 == pair[0].1 + 1));
+    }
+
+    #[tokio::test]
+    async fn backfill_step_grows_slower_with_more_logs() {
+        let mock = (0..200).fold(MockChain::new(&[1000]).max_range(100), |mock, _| {
+            mock.log(contract(), 5, mint(), address(1), U256::one())
+        });
+        let mock = Arc::new(mock);
+        let chain: SharedChain = mock.clone();
+
+        fetch_partition(&chain, contract(), "token", 0, 999)
+            .await
+            .unwrap();
+
+        // 200 logs in 0..=37 grow step by half
+        assert_eq!(mock.requests()[3..5], [(0, 37), (38, 93)]);
+    }
+
+    #[tokio::test]
+    async fn backfill_retries_limited_ranges_without_losing_logs() {
+        let mock =
+            (0..1000)
+                .step_by(7)
+                .fold(MockChain::new(&[1000]).max_range(50), |mock, block| {
+                    mock.log(contract(), block, mint(), address(1), U256::from(3))
+                        .log(contract(), block, address(1), address(2), U256::one())
+                });
+        let chain: SharedChain = Arc::new(mock);
+
+        let partition = fetch_partition(&chain, contract(), "token", 0, 999)
+            .await
+            .unwrap();
+
+        let transfers = (0..1000).step_by(7).count() as i64;
+        assert_eq!(partition.end, 999);
+        assert_eq!(partition.logs, 2 * transfers as usize);
+        assert_eq!(
+            partition.deltas,
+            [
+                (mint().encode_hex(), (-3 * transfers).to_string()),
+                (address(1).encode_hex(), (2 * transfers).to_string()),
+                (address(2).encode_hex(), transfers.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn backfill_sums_transfers_over_i256_without_wrapping() {
+        let chain: SharedChain = Arc::new(
+            MockChain::new(&[10])
+                .log(contract(), 1, mint(), address(1), U256::MAX)
+                .log(contract(), 2, address(1), address(2), U256::MAX - 1),
+        );
+
+        let partition = fetch_partition(&chain, contract(), "token", 0, 9)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            partition.deltas,
+            [
+                (mint().encode_hex(), format!("-{}", U256::MAX)),
+                (address(1).encode_hex(), "1".to_string()),
+                (address(2).encode_hex(), (U256::MAX - 1).to_string()),
+            ]
+        );
+
+        // Deltas are applied to storage as they are
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        let token = token(&storage, 0).await;
+        storage
+            .apply_balance_deltas(&token.id, &partition.deltas, &partition.end)
+            .await
+            .unwrap();
+        assert_eq!(
+            balance_of(&storage, address(2)).await,
+            (U256::MAX - 1).to_string()
+        );
+
+        let chain: SharedChain = Arc::new(
+            MockChain::new(&[10])
+                .log(contract(), 1, mint(), address(1), U256::MAX)
+                .log(contract(), 2, mint(), address(2), U256::one()),
+        );
+
+        assert!(fetch_partition(&chain, contract(), "token", 0, 9)
+            .await
+            .is_err());
+    }
+
+    /// Partitions of 10 blocks fetched by 4 workers, history is recorded every 20 blocks
+    fn config() -> BackfillConfig {
+        BackfillConfig {
+            workers: 4,
+            partition_blocks: 10,
+            history_interval: 20,
+        }
+    }
+
+    /// Chain with transfers at blocks 5, 15 and 25 and head at block 99
+    fn transfers() -> MockChain {
+        MockChain::new(&[99])
+            .log(contract(), 5, mint(), address(1), U256::from(100))
+            .log(contract(), 15, address(1), address(2), U256::from(30))
+            .log(contract(), 25, mint(), address(3), U256::from(5))
+    }
+
+    #[tokio::test]
+    async fn backfill_partitions_are_merged_in_order() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        // Partition of first transfer completes after the following ones
+        let chain: SharedChain = Arc::new(transfers().delay_at(5, Duration::from_millis(50)));
+        let mut token = token(&storage, 0).await;
+
+        backfill(&storage, &chain, &mut token, 99, config())
+            .await
+            .unwrap();
+
+        assert_eq!(token.last_checked_block, 99);
+        assert_eq!(balance_of(&storage, address(1)).await, "70");
+        assert_eq!(balance_of(&storage, address(2)).await, "30");
+        assert_eq!(balance_of(&storage, address(3)).await, "5");
+
+        let history = storage
+            .all_token_history(&token.id, &[], 0, 10, &[])
+            .await
+            .unwrap();
+        let history: Vec<(i64, i64, &str)> = history
+            .iter()
+            .map(|record| {
+                (
+                    record.block_number,
+                    record.holder_count,
+                    record.total_supply.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (0, 0, "0"),
+                (20, 2, "100"),
+                (40, 3, "105"),
+                (60, 3, "105"),
+                (80, 3, "105")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn backfill_checkpoint_advances_only_over_contiguous_partitions() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        // Partition 11..=20 fails after the following ones are fetched
+        let chain: SharedChain = Arc::new(
+            transfers()
+                .delay_at(15, Duration::from_millis(50))
+                .fail_at(15),
+        );
+        let mut token = token(&storage, 0).await;
+
+        assert!(backfill(&storage, &chain, &mut token, 99, config())
+            .await
+            .is_err());
+
+        let stored = storage.token_by_id(&token.id).await.unwrap().unwrap();
+        assert_eq!(
+            (token.last_checked_block, stored.last_checked_block),
+            (10, 10)
+        );
+        assert_eq!(balance_of(&storage, address(1)).await, "100");
+        assert_eq!(balance_of(&storage, address(3)).await, "0");
+    }
+
+    #[tokio::test]
+    async fn listener_applies_live_transfers_and_pauses_at_stream_end() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        let chain: SharedChain = Arc::new(
+            MockChain::new(&[7200])
+                .log(contract(), 7199, mint(), address(1), U256::from(100))
+                .live(contract(), 7201, address(1), address(2), U256::from(30))
+                .live(contract(), 7202, address(2), address(2), U256::from(5)),
+        );
+        let token = token(&storage, 7199).await;
+        storage
+            .add_webhook(
+                "http://localhost/hook",
+                "secret",
+                "transfer",
+                Some(token.id),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let (events, mut receiver) = broadcast::channel(16);
+
+        log_listener(storage.clone(), chain, token.clone(), events)
+            .await
+            .unwrap();
+
+        assert_eq!(balance_of(&storage, address(1)).await, "70");
+        assert_eq!(balance_of(&storage, address(2)).await, "30");
+
+        // Self transfer is not published
+        let first = receiver.try_recv().unwrap();
+        let second = receiver.try_recv().unwrap();
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            (first.amount.as_str(), first.to_balance.as_str()),
+            ("100", "100")
+        );
+        assert_eq!(
+            (second.from_balance.as_str(), second.to_balance.as_str()),
+            ("70", "30")
+        );
+        assert_eq!(storage.all_webhook_delivery_count(&1).await.unwrap(), 2);
+
+        let token = storage.token_by_id(&token.id).await.unwrap().unwrap();
+        assert_eq!(token.sync_state, SyncState::Paused.as_str());
+        // Checkpoint stays before block of last log, lag of paused token is kept
+        assert_eq!(token.last_checked_block, 7201);
+        assert_eq!(token.head_block, 7200);
+
+        // History of block 7200 is recorded when log of next block arrives
+        let history = storage
+            .all_token_history(&token.id, &[], 0, 10, &[])
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].block_number, 7200);
+        assert_eq!(history[0].holder_count, 1);
+        assert_eq!(history[0].total_supply, "100");
+    }
+
+    #[tokio::test]
+    async fn listener_fetches_blocks_between_checkpoint_and_subscription() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        let mock = Arc::new(
+            MockChain::new(&[7205])
+                .log(contract(), 7203, mint(), address(1), U256::from(100))
+                // Subscription may deliver block fetched already
+                .live(contract(), 7203, mint(), address(1), U256::from(100))
+                .live(contract(), 7206, address(1), address(2), U256::from(40)),
+        );
+        let chain: SharedChain = mock.clone();
+        let token = token(&storage, 7201).await;
+        let (events, _receiver) = broadcast::channel(16);
+
+        log_listener(storage.clone(), chain, token, events)
+            .await
+            .unwrap();
+
+        assert_eq!(mock.requests(), [(7201, 7205)]);
+        assert_eq!(balance_of(&storage, address(1)).await, "60");
+        assert_eq!(balance_of(&storage, address(2)).await, "40");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replicas_index_token_once() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        let chain: SharedChain = Arc::new(
+            MockChain::new(&[100])
+                .contract(contract(), "TKN", 6, 0)
+                .live(contract(), 101, mint(), address(1), U256::from(10)),
+        );
+        token(&storage, 100).await;
+        let (events, _) = broadcast::channel(16);
+
+        let replicas = futures::future::join(
+            update_db(storage.clone(), chain.clone(), events.clone()),
+            update_db(storage.clone(), chain, events),
+        );
+        let _ = tokio::time::timeout(TOKEN_POLL_INTERVAL * 3, replicas).await;
+
+        assert_eq!(balance_of(&storage, address(1)).await, "10");
+    }
+
+    #[tokio::test]
+    async fn token_is_backfilled_from_deployment_and_listened() {
+        let storage: SharedStorage = Arc::new(MemoryStorage::default());
+        let chain: SharedChain = Arc::new(
+            MockChain::new(&[100])
+                .contract(contract(), "TKN", 6, 10)
+                .log(contract(), 10, mint(), address(1), U256::from(50))
+                .log(contract(), 60, address(1), address(3), U256::from(20))
+                .live(contract(), 101, address(3), address(2), U256::from(5)),
+        );
+        let mut token = token(&storage, 0).await;
+        token.last_checked_block = -1;
+        storage
+            .update_token_last_checked_block(&-1, &token.id)
+            .await
+            .unwrap();
+        let (events, _) = broadcast::channel(16);
+
+        backfill_and_listen(storage.clone(), chain, token.clone(), events)
+            .await
+            .unwrap();
+
+        let token = storage.token_by_id(&token.id).await.unwrap().unwrap();
+        assert_eq!((token.symbol.as_str(), token.decimals), ("TKN", 6));
+        assert_eq!(balance_of(&storage, address(1)).await, "30");
+        assert_eq!(balance_of(&storage, address(2)).await, "5");
+        assert_eq!(balance_of(&storage, address(3)).await, "15");
+    }
+}
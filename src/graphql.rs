@@ -0,0 +1,288 @@
+use crate::{
+    model::{Balance, Column, Filter, Holder, Operator, Sort, Token},
+    storage::SharedStorage,
+    utils,
+};
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, Error, Object, Result, Schema,
+};
+use axum::response::{Html, IntoResponse};
+
+/// Max nesting of selections, enough for token -> holders -> balances -> token
+const MAX_DEPTH: usize = 8;
+/// Max complexity, list fields cost their page size times child complexity
+const MAX_COMPLEXITY: usize = 5000;
+/// Max page size of list fields
+const MAX_PAGE_SIZE: i64 = 100;
+/// Max page number of list fields, so that offset of page fits in i64
+const MAX_PAGE: i64 = i64::MAX / MAX_PAGE_SIZE;
+
+pub type AppSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn create_schema(storage: SharedStorage) -> AppSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(storage)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// GraphiQL page to explore the schema
+pub async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+fn valid_page(page: i64, size: i64) -> Result<()> {
+    if !(0..=MAX_PAGE).contains(&page) || !(0..=MAX_PAGE_SIZE).contains(&size) {
+        Err(Error::new(format!(
+            "page must be between 0 and {MAX_PAGE} and size must be between 0 and {MAX_PAGE_SIZE}"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn valid_address(addr: &str) -> Result<String> {
+    let addr = utils::strip_hex_prefix(addr);
+
+    if utils::is_hex_address(addr) {
+        Ok(addr.to_string())
+    } else {
+        Err(Error::new(format!("'{addr}' is not a valid address")))
+    }
+}
+
+/// Non-zero balances by token and holder address, sorted by amount
+async fn balances(
+    ctx: &Context<'_>,
+    contract_addr: Option<String>,
+    holder_addr: Option<String>,
+    page: i64,
+    size: i64,
+) -> Result<Vec<BalanceObject>> {
+    valid_page(page, size)?;
+
+    let mut filter = Vec::new();
+
+    if let Some(contract_addr) = contract_addr {
+        filter.push(Filter {
+            column: Column::ContractAddr,
+            operator: Operator::In,
+            values: vec![valid_address(&contract_addr)?],
+        });
+    }
+
+    if let Some(holder_addr) = holder_addr {
+        filter.push(Filter {
+            column: Column::HolderAddr,
+            operator: Operator::In,
+            values: vec![valid_address(&holder_addr)?],
+        });
+    }
+
+    if filter.is_empty() {
+        return Err(Error::new("contractAddr or holderAddr is required"));
+    }
+
+    let balances = ctx
+        .data::<SharedStorage>()?
+        .all_balance_by_filter(
+            &filter,
+            page,
+            size,
+            &[Sort {
+                column: Column::Amount,
+                descending: true,
+            }],
+        )
+        .await?;
+
+    Ok(balances.into_iter().map(BalanceObject).collect())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Tracked tokens sorted by id
+    #[graphql(
+        complexity = "(size.clamp(0, MAX_PAGE_SIZE) as usize).saturating_mul(child_complexity)"
+    )]
+    async fn tokens(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0)] page: i64,
+        #[graphql(default = 10)] size: i64,
+    ) -> Result<Vec<TokenObject>> {
+        valid_page(page, size)?;
+
+        let tokens = ctx
+            .data::<SharedStorage>()?
+            .all_token(
+                page,
+                size,
+                &[Sort {
+                    column: Column::TokenId,
+                    descending: false,
+                }],
+            )
+            .await?;
+
+        Ok(tokens.into_iter().map(TokenObject).collect())
+    }
+
+    /// Token by id or contract address
+    async fn token(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<i32>,
+        contract_addr: Option<String>,
+    ) -> Result<Option<TokenObject>> {
+        let storage = ctx.data::<SharedStorage>()?;
+
+        let token = match (id, contract_addr) {
+            (Some(id), None) => storage.token_by_id(&id).await?,
+            (None, Some(contract_addr)) => {
+                storage
+                    .token_by_contract(&valid_address(&contract_addr)?)
+                    .await?
+            }
+            _ => return Err(Error::new("exactly one of id or contractAddr is required")),
+        };
+
+        Ok(token.map(TokenObject))
+    }
+
+    async fn holder(&self, ctx: &Context<'_>, addr: String) -> Result<Option<HolderObject>> {
+        let holder = ctx
+            .data::<SharedStorage>()?
+            .holder_by_addr(&valid_address(&addr)?)
+            .await?;
+
+        Ok(holder.map(HolderObject))
+    }
+
+    /// Non-zero balances filtered by token and/or holder, largest first
+    #[graphql(
+        complexity = "(size.clamp(0, MAX_PAGE_SIZE) as usize).saturating_mul(child_complexity)"
+    )]
+    async fn balances(
+        &self,
+        ctx: &Context<'_>,
+        contract_addr: Option<String>,
+        holder_addr: Option<String>,
+        #[graphql(default = 0)] page: i64,
+        #[graphql(default = 10)] size: i64,
+    ) -> Result<Vec<BalanceObject>> {
+        balances(ctx, contract_addr, holder_addr, page, size).await
+    }
+}
+
+pub struct TokenObject(Token);
+
+#[Object(name = "Token")]
+impl TokenObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn contract_addr(&self) -> &str {
+        &self.0.contract_addr
+    }
+
+    async fn symbol(&self) -> &str {
+        &self.0.symbol
+    }
+
+    async fn decimals(&self) -> i16 {
+        self.0.decimals
+    }
+
+    async fn last_checked_block(&self) -> i64 {
+        self.0.last_checked_block
+    }
+
+    async fn sync_state(&self) -> &str {
+        &self.0.sync_state
+    }
+
+    async fn lag(&self) -> i64 {
+        self.0.lag
+    }
+
+    /// Holders with the largest balances of token
+    #[graphql(
+        complexity = "(size.clamp(0, MAX_PAGE_SIZE) as usize).saturating_mul(child_complexity)"
+    )]
+    async fn top_holders(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0)] page: i64,
+        #[graphql(default = 10)] size: i64,
+    ) -> Result<Vec<BalanceObject>> {
+        balances(ctx, Some(self.0.contract_addr.clone()), None, page, size).await
+    }
+}
+
+pub struct HolderObject(Holder);
+
+#[Object(name = "Holder")]
+impl HolderObject {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn holder_addr(&self) -> &str {
+        &self.0.holder_addr
+    }
+
+    /// Non-zero balances of holder in all tokens, largest first
+    #[graphql(
+        complexity = "(size.clamp(0, MAX_PAGE_SIZE) as usize).saturating_mul(child_complexity)"
+    )]
+    async fn balances(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 0)] page: i64,
+        #[graphql(default = 10)] size: i64,
+    ) -> Result<Vec<BalanceObject>> {
+        balances(ctx, None, Some(self.0.holder_addr.clone()), page, size).await
+    }
+}
+
+pub struct BalanceObject(Balance);
+
+#[Object(name = "Balance")]
+impl BalanceObject {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn amount(&self) -> &str {
+        &self.0.amount
+    }
+
+    /// Amount divided by token decimals, fraction truncated to precision digits
+    async fn amount_decimal(&self, precision: Option<usize>) -> Result<String> {
+        if precision.is_some_and(|precision| precision > utils::MAX_PRECISION) {
+            return Err(Error::new(format!(
+                "precision must be between 0 and {}",
+                utils::MAX_PRECISION
+            )));
+        }
+
+        Ok(utils::format_amount(
+            &self.0.amount,
+            self.0.token.decimals,
+            precision,
+        ))
+    }
+
+    async fn token(&self) -> TokenObject {
+        TokenObject(self.0.token.clone())
+    }
+
+    async fn holder(&self) -> HolderObject {
+        HolderObject(self.0.holder.clone())
+    }
+}
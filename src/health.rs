@@ -0,0 +1,106 @@
+use crate::{chain::SharedChain, storage::SharedStorage};
+use anyhow::Result;
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use serde_json::{json, Map, Value};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::timeout};
+
+/// Max duration of dependency check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Dependencies of service and state of indexer task.
+/// Chain client is set only in processes running the indexer
+pub struct Health {
+    storage: SharedStorage,
+    chain: Option<SharedChain>,
+    indexer_error: Mutex<Option<String>>,
+}
+
+impl Health {
+    pub fn new(storage: SharedStorage, chain: Option<SharedChain>) -> Self {
+        Self {
+            storage,
+            chain,
+            indexer_error: Mutex::new(None),
+        }
+    }
+
+    /// Wait for indexer task and remember why it stopped
+    pub async fn watch_indexer(self: Arc<Self>, indexer: JoinHandle<Result<()>>) {
+        let error = match indexer.await {
+            Ok(Ok(())) => "indexer stopped".to_string(),
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        tracing::error!("Indexer: {}", error);
+
+        *self.indexer_error.lock().unwrap() = Some(error);
+    }
+}
+
+/// Status of component, error of check if it is degraded
+async fn check<T, E: ToString>(call: impl Future<Output = Result<T, E>>) -> Option<String> {
+    match timeout(CHECK_TIMEOUT, call).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some("check timed out".to_string()),
+    }
+}
+
+fn component(error: &Option<String>) -> Value {
+    match error {
+        None => json!({ "status": "ok" }),
+        Some(error) => json!({ "status": "degraded", "error": error }),
+    }
+}
+
+/// Liveness, service is able to respond and its indexer task is running.
+/// 503 after indexer has exited, so that process is restarted
+pub async fn healthz(Extension(health): Extension<Arc<Health>>) -> impl IntoResponse {
+    match health.indexer_error.lock().unwrap().clone() {
+        None => (StatusCode::OK, Json(json!({ "status": "ok" }))),
+        Some(error) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "failed", "error": error })),
+        ),
+    }
+}
+
+/// Readiness of database, evm provider and indexer,
+/// 503 with degraded components if any of them is not ready
+pub async fn readyz(Extension(health): Extension<Arc<Health>>) -> impl IntoResponse {
+    let mut components = Map::new();
+
+    let database = check(health.storage.ping()).await;
+    components.insert("database".to_string(), component(&database));
+
+    if let Some(chain) = &health.chain {
+        let provider = check(chain.block_number()).await;
+        components.insert("provider".to_string(), component(&provider));
+
+        let indexer = health.indexer_error.lock().unwrap().clone();
+        components.insert("indexer".to_string(), component(&indexer));
+    }
+
+    let ready = components
+        .values()
+        .all(|component| component["status"] == "ok");
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let body = json!({
+        "status": if ready { "ok" } else { "degraded" },
+        "components": components,
+    });
+
+    (status, Json(body))
+}
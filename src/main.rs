@@ -1,25 +1,34 @@
+mod amount;
+mod auth;
+mod chain;
+mod cli;
+#[cfg(feature = "postgres")]
 mod db;
 mod error;
 mod evm;
+mod graphql;
+mod health;
+mod model;
+mod openapi;
 mod rest;
+mod storage;
+mod telemetry;
 mod utils;
 mod validators;
+mod webhook;
 
 use anyhow::Result;
-use axum::Router;
-use tokio::{net::TcpListener, sync::mpsc};
-
-/// Wrapper to use try methods
-async fn serve_wrapper(listener: TcpListener, app: Router) -> Result<()> {
-    Ok(axum::serve(listener, app).await?)
-}
+use clap::Parser;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Load environment vars from .env
-    dotenv::dotenv().expect("Error loading variables from .env file");
+    // Load environment vars from .env if it exists
+    dotenv::dotenv().ok();
 
-    // Configuring a fmt subscriber and set it as default
+    let cli = cli::Cli::parse();
+
+    // Configuring a fmt subscriber and set it as default,
+    // logs are written to stderr to keep output of commands clean
     let filter = tracing_subscriber::EnvFilter::builder()
         .with_default_directive(tracing::level_filters::LevelFilter::OFF.into())
         .from_env()?
@@ -27,38 +36,11 @@ async fn main() -> Result<()> {
 
     tracing_subscriber::fmt::fmt()
         .with_env_filter(filter)
+        .with_writer(std::io::stderr)
         .init();
 
-    // Create connection with evm
-    let provider = evm::create_provider()
-        .await
-        .expect("Error creating a router for evm");
-
-    // Create connection with db
-    let connection_pool = db::init_db()
-        .await
-        .expect("Error connecting to the database");
-
-    // Сreate a channel to add a new token update task
-    let (tx, rx) = mpsc::channel(1);
-
-    // Create router and tcp listener
-    let app = rest::create_router(connection_pool.clone(), tx);
-    let listener = TcpListener::bind(format!(
-        "{}:{}",
-        std::env::var("SERVICE_IP")?,
-        std::env::var("SERVICE_PORT")?
-    ))
-    .await
-    .expect("Error creating TcpListener");
-
-    // Update db from evm network
-    // Running a service
-    // TODO: Make it as tasks
-    tokio::try_join!(
-        evm::update_db(connection_pool, provider, rx),
-        serve_wrapper(listener, app)
-    )?;
+    // Install metrics recorder
+    telemetry::install()?;
 
-    Ok(())
+    cli::run(cli.command).await
 }
@@ -0,0 +1,464 @@
+//! Resources of api with filter, sort and cursor params of their queries
+
+use crate::utils;
+use jsonapi::{api::*, jsonapi_model, model::*};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct Token {
+    #[schema(ignore)]
+    #[sqlx(rename = "token_id")]
+    pub id: i32,
+    pub contract_addr: String,
+    pub last_checked_block: i64,
+    pub symbol: String,
+    pub decimals: i16,
+    pub sync_state: String,
+    pub head_block: i64,
+    pub lag: i64,
+    pub backfill_rate: f64,
+    pub eta: Option<i64>,
+    pub sync_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct Holder {
+    #[schema(ignore)]
+    #[sqlx(rename = "holder_id")]
+    pub id: i32,
+    pub holder_addr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Balance {
+    pub id: String,
+    pub amount: String,
+    /// Amount divided by token decimals, set by api
+    #[sqlx(skip)]
+    pub amount_decimal: String,
+    #[sqlx(flatten)]
+    pub token: Token,
+    #[sqlx(flatten)]
+    pub holder: Holder,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct TokenStats {
+    #[schema(ignore)]
+    #[sqlx(rename = "token_id")]
+    pub id: i32,
+    pub last_checked_block: i64,
+    pub holder_count: i64,
+    pub total_supply: String,
+    pub top10_share: f64,
+    pub top100_share: f64,
+    pub gini: f64,
+    pub herfindahl: f64,
+    pub nakamoto: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct TokenHistory {
+    #[schema(ignore)]
+    pub id: String,
+    pub block_number: i64,
+    pub holder_count: i64,
+    pub total_supply: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct Webhook {
+    #[schema(ignore)]
+    #[sqlx(rename = "webhook_id")]
+    pub id: i32,
+    pub url: String,
+    pub event: String,
+    pub token_id: Option<i32>,
+    pub holder_addr: Option<String>,
+    pub min_amount: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct WebhookDelivery {
+    #[schema(ignore)]
+    #[sqlx(rename = "delivery_id")]
+    pub id: i32,
+    pub webhook_id: i32,
+    pub payload: String,
+    pub attempts: i32,
+    pub status_code: Option<i32>,
+    pub delivered: bool,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+/// Undelivered payload of webhook claimed for next attempt
+#[derive(Debug, FromRow, Clone)]
+pub struct DueWebhookDelivery {
+    pub delivery_id: i32,
+    pub webhook_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub payload: String,
+    pub attempts: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct ApiKey {
+    #[schema(ignore)]
+    #[sqlx(rename = "api_key_id")]
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Requests per minute
+    pub rate_limit: i32,
+    pub created_at: String,
+    /// Plain key, returned only on creation
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// Onboarding job of token, token is set when job is done
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
+pub struct Job {
+    #[schema(ignore)]
+    #[sqlx(rename = "job_id")]
+    pub id: i32,
+    pub contract_addr: String,
+    pub start_block: Option<i64>,
+    pub state: String,
+    pub token_id: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Indexing state of token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    Backfilling,
+    Live,
+    Paused,
+    Failed,
+}
+
+impl SyncState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncState::Backfilling => "backfilling",
+            SyncState::Live => "live",
+            SyncState::Paused => "paused",
+            SyncState::Failed => "failed",
+        }
+    }
+}
+
+/// State of onboarding job
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+/// Columns allowed in filters and sorting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    TokenId,
+    ContractAddr,
+    Symbol,
+    HolderAddr,
+    Amount,
+    BlockNumber,
+}
+
+impl Column {
+    /// Column by jsonapi parameter name from allow-list of endpoint
+    pub fn from_param(columns: &[(&str, Column)], param: &str) -> Option<Self> {
+        columns
+            .iter()
+            .find(|(name, _)| *name == param)
+            .map(|(_, column)| *column)
+    }
+
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    pub fn sql(&self) -> &'static str {
+        match self {
+            Column::TokenId => "token.token_id",
+            Column::ContractAddr => "token.contract_addr",
+            Column::Symbol => "token.symbol",
+            Column::HolderAddr => "holder.holder_addr",
+            Column::Amount => "balance.amount",
+            Column::BlockNumber => "token_history.block_number",
+        }
+    }
+
+    /// Check that value can be bound to column
+    pub fn is_valid_value(&self, value: &str) -> bool {
+        match self {
+            Column::ContractAddr | Column::HolderAddr => {
+                utils::is_hex_address(utils::strip_hex_prefix(value))
+            }
+            Column::Amount | Column::BlockNumber | Column::TokenId => {
+                !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+            }
+            Column::Symbol => !value.is_empty(),
+        }
+    }
+
+    /// Numeric columns can be compared with gt, gte, lt, lte
+    pub fn is_comparable(&self) -> bool {
+        matches!(self, Column::Amount | Column::BlockNumber | Column::TokenId)
+    }
+}
+
+/// Comparison of column with filter values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    In,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Operator {
+    /// Operator by name from `filter[column][operator]`
+    pub fn from_param(param: &str) -> Option<Self> {
+        match param {
+            "gt" => Some(Operator::Gt),
+            "gte" => Some(Operator::Gte),
+            "lt" => Some(Operator::Lt),
+            "lte" => Some(Operator::Lte),
+            _ => None,
+        }
+    }
+
+    #[cfg(any(feature = "postgres", feature = "sqlite"))]
+    pub fn sql(&self) -> &'static str {
+        match self {
+            Operator::In => " IN ",
+            Operator::Gt => " > ",
+            Operator::Gte => " >= ",
+            Operator::Lt => " < ",
+            Operator::Lte => " <= ",
+        }
+    }
+}
+
+/// Column compared with values, `In` matches one of values
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub column: Column,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+impl Filter {
+    /// Parse `filter[column]=a,b` and `filter[column][operator]=a` params of raw query.
+    /// Returns column param name with filter or error message for every param
+    pub fn parse_params(
+        raw_query: &str,
+        columns: &[(&str, Column)],
+    ) -> Vec<(String, String, Result<Self, String>)> {
+        form_urlencoded::parse(raw_query.as_bytes())
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix("filter[")?.strip_suffix(']')?;
+
+                let (name, operator) = match name.split_once("][") {
+                    Some((name, operator)) => (name, Some(operator)),
+                    None => (name, None),
+                };
+
+                let values: Vec<String> = value.split(',').map(|v| v.to_string()).collect();
+
+                Some((
+                    key.to_string(),
+                    name.to_string(),
+                    Self::parse(columns, name, operator, values),
+                ))
+            })
+            .collect()
+    }
+
+    fn parse(
+        columns: &[(&str, Column)],
+        name: &str,
+        operator: Option<&str>,
+        values: Vec<String>,
+    ) -> Result<Self, String> {
+        let column = Column::from_param(columns, name).ok_or("unknown filter column")?;
+
+        let operator = match operator {
+            None => Operator::In,
+            Some(operator) => {
+                let operator = Operator::from_param(operator)
+                    .ok_or("unknown filter operator, expected: gt, gte, lt, lte")?;
+
+                if !column.is_comparable() {
+                    return Err("column cannot be compared".to_string());
+                }
+
+                if values.len() != 1 {
+                    return Err("operator requires exactly one value".to_string());
+                }
+
+                operator
+            }
+        };
+
+        if !values.iter().all(|value| column.is_valid_value(value)) {
+            return Err("invalid filter value".to_string());
+        }
+
+        Ok(Self {
+            column,
+            operator,
+            values,
+        })
+    }
+
+    /// Valid filters of raw query
+    pub fn from_params(raw_query: &str, columns: &[(&str, Column)]) -> Vec<Self> {
+        Self::parse_params(raw_query, columns)
+            .into_iter()
+            .filter_map(|(_, _, filter)| filter.ok())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sort {
+    pub column: Column,
+    pub descending: bool,
+}
+
+impl Sort {
+    /// Sort keys from jsonapi query, `-` prefix means descending order.
+    /// Error for key missing in allow-list of endpoint
+    pub fn from_query(
+        sort: Option<Vec<String>>,
+        columns: &[(&str, Column)],
+    ) -> Result<Vec<Self>, String> {
+        sort.unwrap_or_default()
+            .iter()
+            .map(|param| {
+                let (name, descending) = match param.strip_prefix('-') {
+                    Some(name) => (name, true),
+                    None => (param.as_str(), false),
+                };
+
+                Column::from_param(columns, name)
+                    .map(|column| Sort { column, descending })
+                    .ok_or_else(|| format!("unknown sort column '{param}'"))
+            })
+            .collect()
+    }
+}
+
+/// Position of balance in keyset pagination, encoded as `amount_holderid_tokenid`.
+/// Token id breaks ties of holder balances in different tokens
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub amount: String,
+    pub holder_id: i32,
+    pub token_id: i32,
+}
+
+impl Cursor {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split('_');
+
+        let amount = parts.next()?;
+        let holder_id = parts.next()?.parse().ok()?;
+        let token_id = parts.next()?.parse().ok()?;
+
+        if parts.next().is_some() || !Column::Amount.is_valid_value(amount) {
+            return None;
+        }
+
+        Some(Self {
+            amount: amount.to_string(),
+            holder_id,
+            token_id,
+        })
+    }
+
+    pub fn of(balance: &Balance) -> Self {
+        Self {
+            amount: balance.amount.clone(),
+            holder_id: balance.holder.id,
+            token_id: balance.token.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}_{}_{}", self.amount, self.holder_id, self.token_id)
+    }
+}
+
+/// Page of balances after cursor, `After(None)` starts from the first balance
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageCursor {
+    After(Option<Cursor>),
+    Before(Cursor),
+}
+
+impl PageCursor {
+    /// Parse `page[after]` or `page[before]` param of raw query.
+    /// Returns param name with cursor or error message, `None` if not set
+    pub fn parse_params(raw_query: &str) -> Option<(String, Result<Self, String>)> {
+        let params: Vec<(String, String)> = form_urlencoded::parse(raw_query.as_bytes())
+            .filter(|(key, _)| key == "page[after]" || key == "page[before]")
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let (key, value) = params.first()?.clone();
+
+        if params.len() > 1 {
+            return Some((
+                key,
+                Err("only one of page[after] and page[before] can be set".to_string()),
+            ));
+        }
+
+        let cursor = match (key.as_str(), Cursor::parse(&value)) {
+            ("page[after]", _) if value.is_empty() => Ok(PageCursor::After(None)),
+            ("page[after]", Some(cursor)) => Ok(PageCursor::After(Some(cursor))),
+            ("page[before]", Some(cursor)) => Ok(PageCursor::Before(cursor)),
+            _ => Err("invalid cursor".to_string()),
+        };
+
+        Some((key, cursor))
+    }
+
+    /// Valid cursor of raw query
+    pub fn from_params(raw_query: &str) -> Option<Self> {
+        Self::parse_params(raw_query).and_then(|(_, cursor)| cursor.ok())
+    }
+}
+
+jsonapi_model!(Token; "token");
+jsonapi_model!(Holder; "holder");
+jsonapi_model!(Balance; "balance"; has one token, holder);
+jsonapi_model!(TokenStats; "token_stats");
+jsonapi_model!(TokenHistory; "token_history");
+jsonapi_model!(Webhook; "webhook");
+jsonapi_model!(WebhookDelivery; "webhook_delivery");
+jsonapi_model!(ApiKey; "api_key");
+jsonapi_model!(Job; "job");
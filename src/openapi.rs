@@ -0,0 +1,220 @@
+use crate::{
+    model::{ApiKey, Holder, Job, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery},
+    rest,
+};
+use axum::Json;
+use serde_json::Value;
+use std::collections::HashMap;
+use utoipa::{
+    openapi::{
+        security::{ApiKey as ApiKeyScheme, ApiKeyValue, SecurityScheme},
+        OpenApi as OpenApiDocument,
+    },
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Token holders", description = "ERC20 token holders and balances indexer"),
+    paths(
+        rest::get_tokens,
+        rest::post_token,
+        rest::get_token,
+        rest::get_token_stats,
+        rest::get_token_history,
+        rest::post_token_reindex,
+        rest::get_job,
+        rest::get_balances,
+        rest::get_holder,
+        rest::get_holder_balances,
+        rest::get_stream,
+        rest::post_webhook,
+        rest::get_webhooks,
+        rest::get_webhook,
+        rest::delete_webhook,
+        rest::get_webhook_deliveries,
+        rest::post_api_key,
+        rest::get_api_keys,
+        rest::delete_api_key,
+    ),
+    components(schemas(
+        Token,
+        Holder,
+        BalanceAttributes,
+        TokenStats,
+        TokenHistory,
+        Webhook,
+        WebhookDelivery,
+        ApiKey,
+        Job,
+        Resource,
+        SingleDocument,
+        ListDocument,
+        ListMeta,
+        RequestDocument,
+        ErrorDocument,
+        ErrorObject,
+        ErrorSource,
+    )),
+    modifiers(&ApiKeySecurity),
+    security(("api_key" = [])),
+    tags(
+        (name = "tokens", description = "Indexed tokens, requires `read` or `add-token` scope, reindex requires `admin` scope"),
+        (name = "jobs", description = "Onboarding jobs of tokens, requires `add-token` scope"),
+        (name = "balances", description = "Holder balances, requires `read` scope"),
+        (name = "webhooks", description = "Webhook rules and deliveries, requires `admin` scope"),
+        (name = "api_keys", description = "API keys, requires `admin` scope"),
+    )
+)]
+pub struct ApiDoc;
+
+/// `X-Api-Key` header, also accepted as `Authorization: Bearer` token
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKeyScheme::Header(ApiKeyValue::new("X-Api-Key"))),
+            );
+        }
+    }
+}
+
+/// Serve OpenAPI document
+pub async fn openapi_json() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+/// Offset pagination, `page[size]` is also the size of cursor pages
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+#[allow(dead_code)]
+pub struct PageQuery {
+    /// Page number from 0
+    #[param(rename = "page[number]", minimum = 0)]
+    number: Option<i64>,
+    /// Resources per page
+    #[param(rename = "page[size]", minimum = 0, maximum = 1000)]
+    size: Option<i64>,
+}
+
+/// Keyset pagination by `amount_holderid_tokenid` cursor from `next` and `prev` links.
+/// Requires sort by `amount` or `-amount` and cannot be combined with `page[number]`
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+#[allow(dead_code)]
+pub struct CursorQuery {
+    /// Balances after cursor, empty value starts from the first balance
+    #[param(rename = "page[after]")]
+    after: Option<String>,
+    /// Balances before cursor
+    #[param(rename = "page[before]")]
+    before: Option<String>,
+}
+
+/// Related resources and sparse fieldsets of balances
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+#[allow(dead_code)]
+pub struct BalanceDocumentQuery {
+    /// Sideloaded relationships, comma separated: `token`, `holder`
+    include: Option<String>,
+    /// Attributes and relationships of balance, comma separated
+    #[param(rename = "fields[balance]")]
+    balance_fields: Option<String>,
+    /// Attributes of included tokens, comma separated
+    #[param(rename = "fields[token]")]
+    token_fields: Option<String>,
+    /// Attributes of included holders, comma separated
+    #[param(rename = "fields[holder]")]
+    holder_fields: Option<String>,
+    /// Fraction digits of `amount_decimal`, from 0 to 78
+    #[param(maximum = 78)]
+    precision: Option<usize>,
+}
+
+/// Attributes of `balance` resource with `token` and `holder` relationships
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct BalanceAttributes {
+    /// Base unit amount
+    amount: String,
+    /// Amount divided by token decimals
+    amount_decimal: String,
+}
+
+/// JSON:API resource, attributes are described by schema of its type
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct Resource {
+    #[schema(rename = "type")]
+    resource_type: String,
+    id: String,
+    attributes: HashMap<String, Value>,
+    relationships: Option<HashMap<String, Value>>,
+}
+
+/// JSON:API document with single resource
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct SingleDocument {
+    data: Resource,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ListMeta {
+    /// Missing for cursor pages
+    total_count: Option<i64>,
+    /// Missing for cursor pages
+    page_number: Option<i64>,
+    page_size: i64,
+}
+
+/// JSON:API document with list of resources
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ListDocument {
+    data: Vec<Resource>,
+    /// Related resources requested with `include`
+    included: Option<Vec<Resource>>,
+    /// `first`, `prev`, `next` and `last` pages
+    links: Option<HashMap<String, String>>,
+    meta: ListMeta,
+}
+
+/// JSON:API document of created resource, `id` is empty
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct RequestDocument {
+    data: Resource,
+}
+
+/// JSON:API error document returned with 4xx and 5xx status
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorDocument {
+    errors: Vec<ErrorObject>,
+}
+
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorObject {
+    /// HTTP status code
+    status: String,
+    /// HTTP status reason
+    title: String,
+    /// Error message of invalid parameter or attribute
+    detail: Option<String>,
+    source: Option<ErrorSource>,
+}
+
+/// Invalid query parameter or attribute
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorSource {
+    pointer: String,
+    parameter: String,
+}
@@ -1,130 +1,1310 @@
 use crate::{
     app_err_response,
-    db::{self, Token},
+    auth::{self, Auth, RateLimiter, Scope},
     error::{AppError, AppErrorResponse},
-    evm, utils,
+    evm::EventSender,
+    graphql,
+    health::{self, Health},
+    model::{
+        ApiKey, Balance, Column, Cursor, Filter, Job, Operator, PageCursor, Sort, Token, Webhook,
+    },
+    openapi::{
+        self, BalanceDocumentQuery, CursorQuery, ErrorDocument, ListDocument, PageQuery,
+        RequestDocument, SingleDocument,
+    },
+    storage::{self, NewJob, SharedStorage},
+    telemetry, utils,
     validators::QueryParamsValidator as QPV,
 };
+use async_graphql_axum::GraphQL;
 use axum::{
-    extract::{self, RawQuery},
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::get,
+    body::Bytes,
+    extract::{self, Path, RawQuery},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post},
     Extension, Json, Router,
 };
 use jsonapi::{model::*, query};
-use sqlx::PgPool;
-use std::vec;
-use tokio::sync::mpsc;
+use std::{convert::Infallible, sync::Arc, time::Duration, vec};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
 
-pub fn create_router(connection_pool: PgPool, tx: mpsc::Sender<Token>) -> Router {
-    Router::new()
-        .route(
-            "/tokens",
-            get(get_tokens).post(move |extension, body| post_token(extension, body, tx)),
+/// Attributes of token for sparse fieldsets
+const TOKEN_FIELDS: &[&str] = &[
+    "contract_addr",
+    "last_checked_block",
+    "symbol",
+    "decimals",
+    "sync_state",
+    "head_block",
+    "lag",
+    "backfill_rate",
+    "eta",
+    "sync_error",
+];
+/// Attributes and relationships of balance for sparse fieldsets
+const BALANCE_FIELDS: &[&str] = &["amount", "amount_decimal", "token", "holder"];
+/// Attributes of holder for sparse fieldsets
+const HOLDER_FIELDS: &[&str] = &["holder_addr"];
+/// Sort columns of tokens
+const TOKEN_SORT: &[(&str, Column)] = &[("symbol", Column::Symbol), ("id", Column::TokenId)];
+/// Filter and sort columns of token history
+const HISTORY_COLUMNS: &[(&str, Column)] = &[("block_number", Column::BlockNumber)];
+/// Filter columns of balances
+const BALANCE_FILTER: &[(&str, Column)] = &[
+    ("holder.holder_addr", Column::HolderAddr),
+    ("token.contract_addr", Column::ContractAddr),
+    ("amount", Column::Amount),
+];
+/// Sort columns of balances
+const BALANCE_SORT: &[(&str, Column)] = &[
+    ("amount", Column::Amount),
+    ("holder.holder_addr", Column::HolderAddr),
+    ("token.contract_addr", Column::ContractAddr),
+];
+/// Filter columns of holder balances
+const HOLDER_BALANCE_FILTER: &[(&str, Column)] = &[
+    ("token.contract_addr", Column::ContractAddr),
+    ("amount", Column::Amount),
+];
+/// Sort columns of holder balances
+const HOLDER_BALANCE_SORT: &[(&str, Column)] = &[
+    ("amount", Column::Amount),
+    ("token.contract_addr", Column::ContractAddr),
+];
+/// Filter columns of transfer stream
+const STREAM_FILTER: &[(&str, Column)] = &[
+    ("token.contract_addr", Column::ContractAddr),
+    ("holder.holder_addr", Column::HolderAddr),
+];
+/// Time stats of live token are served from cache before recalculating
+const STATS_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Create api router, new tokens are handed to indexer by onboarding jobs in storage
+pub fn create_router(storage: SharedStorage, events: EventSender, health: Arc<Health>) -> Router {
+    let limiter = Arc::new(RateLimiter::default());
+    let auth = |scope| {
+        middleware::from_fn_with_state(
+            Auth::new(storage.clone(), limiter.clone(), scope),
+            auth::authorize,
         )
+    };
+
+    let read = Router::new()
+        .route("/tokens", get(get_tokens))
+        .route("/tokens/:id", get(get_token))
+        .route("/tokens/:id/stats", get(get_token_stats))
+        .route("/tokens/:id/history", get(get_token_history))
         .route("/balances", get(get_balances))
-        .layer(Extension(connection_pool))
+        .route("/holders/:addr", get(get_holder))
+        .route("/holders/:addr/balances", get(get_holder_balances))
+        .route("/stream", get(get_stream))
+        .route(
+            "/graphql",
+            get(graphql::graphiql)
+                .post_service(GraphQL::new(graphql::create_schema(storage.clone()))),
+        )
+        .route_layer(auth(Scope::Read));
+
+    let add_token = Router::new()
+        .route("/tokens", post(post_token))
+        .route("/jobs/:id", get(get_job))
+        .route_layer(auth(Scope::AddToken));
+
+    let admin = Router::new()
+        .route("/tokens/:id/reindex", post(post_token_reindex))
+        .route("/webhooks", post(post_webhook).get(get_webhooks))
+        .route("/webhooks/:id", get(get_webhook).delete(delete_webhook))
+        .route("/webhooks/:id/deliveries", get(get_webhook_deliveries))
+        .route("/api_keys", post(post_api_key).get(get_api_keys))
+        .route("/api_keys/:id", delete(delete_api_key))
+        .route_layer(auth(Scope::Admin));
+
+    Router::new()
+        .merge(create_probe_router(health))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .merge(read)
+        .merge(add_token)
+        .merge(admin)
+        .route_layer(middleware::from_fn(telemetry::track_http))
+        .layer(Extension(storage))
+        .layer(Extension(events))
+}
+
+/// Health and metrics routes, served by every process
+pub fn create_probe_router(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(telemetry::metrics))
+        .layer(Extension(health))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tokens",
+    tag = "tokens",
+    params(
+        PageQuery,
+        ("sort" = String, Query, description = "Comma separated `symbol`, `id`, `-` prefix for descending order"),
+        ("fields[token]" = Option<String>, Query, description = "Attributes of token, comma separated"),
+    ),
+    responses(
+        (status = 200, description = "List of `token` resources with `Token` attributes", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
 async fn get_tokens(
-    Extension(cp): Extension<PgPool>,
+    Extension(storage): Extension<SharedStorage>,
     RawQuery(query_params): RawQuery,
 ) -> Result<Response, AppErrorResponse> {
     let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
 
     let query_params = QPV::new(query_params)
         .valid_pagination()
-        .only_one_sort(vec!["symbol", "-symbol"])
+        .valid_sort(TOKEN_SORT)
         .no_filter()
         .no_include()
-        .no_fields()
+        .valid_fields(vec![("token", TOKEN_FIELDS)])
+        .collect_query()?;
+
+    let tokens = storage
+        .all_token(
+            query_params.page.unwrap().number,
+            query_params.page.unwrap().size,
+            &sort(query_params.sort.clone(), TOKEN_SORT)?,
+        )
+        .await?;
+
+    let total_count = storage.all_token_count().await?;
+
+    let document =
+        utils::vec_to_jsonapi_document(tokens, total_count, query_params.page.unwrap(), "token")?;
+
+    Ok(Json(utils::sparse_document(document, &query_params)).into_response())
+}
+
+/// Find token by id or by contract address
+async fn find_token(storage: &SharedStorage, id: &str) -> Result<Token, AppErrorResponse> {
+    storage::find_token(storage, id)
+        .await?
+        .ok_or_else(|| app_err_response!(StatusCode::NOT_FOUND, "Token not found"))
+}
+
+/// Sort keys of query from allow-list of endpoint
+fn sort(
+    sort: Option<Vec<String>>,
+    columns: &[(&str, Column)],
+) -> Result<Vec<Sort>, AppErrorResponse> {
+    Sort::from_query(sort, columns).map_err(|err| app_err_response!(StatusCode::BAD_REQUEST, err))
+}
+
+#[utoipa::path(
+    get,
+    path = "/tokens/{id}",
+    tag = "tokens",
+    params(
+        ("id" = String, Path, description = "Token id or contract address"),
+        ("fields[token]" = Option<String>, Query, description = "Attributes of token, comma separated"),
+    ),
+    responses(
+        (status = 200, description = "`token` resource with `Token` attributes", body = SingleDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 404, description = "Token not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_token(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
+
+    let query_params = QPV::new(query_params)
+        .no_include()
+        .valid_fields(vec![("token", TOKEN_FIELDS)])
         .collect_query()?;
 
-    let tokens = db::all_token(
-        &cp,
-        query_params.page.unwrap().number,
-        query_params.page.unwrap().size,
-        query_params.sort,
+    let token = find_token(&storage, &id).await?;
+
+    Ok(Json(utils::sparse_document(
+        token.to_jsonapi_document(),
+        &query_params,
+    ))
+    .into_response())
+}
+
+/// Get holder concentration metrics,
+/// recalculate them if token checkpoint has moved
+#[utoipa::path(
+    get,
+    path = "/tokens/{id}/stats",
+    tag = "tokens",
+    params(("id" = String, Path, description = "Token id or contract address")),
+    responses(
+        (status = 200, description = "`token_stats` resource with `TokenStats` attributes", body = SingleDocument),
+        (status = 404, description = "Token not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_token_stats(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    let token = find_token(&storage, &id).await?;
+
+    let stats = match storage
+        .fresh_token_stats(&token.id, &token.last_checked_block, STATS_MAX_AGE)
+        .await?
+    {
+        Some(stats) => stats,
+        None => {
+            storage
+                .calculate_token_stats(&token.id, &token.last_checked_block)
+                .await?
+        }
+    };
+
+    Ok(Json(stats.to_jsonapi_document()).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/tokens/{id}/history",
+    tag = "tokens",
+    params(
+        ("id" = String, Path, description = "Token id or contract address"),
+        PageQuery,
+        ("filter[block_number]" = Option<String>, Query, description = "Comma separated block numbers, compare with `filter[block_number][gt|gte|lt|lte]`"),
+        ("sort" = String, Query, description = "`block_number` or `-block_number`"),
+    ),
+    responses(
+        (status = 200, description = "List of `token_history` resources with `TokenHistory` attributes", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 404, description = "Token not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
     )
-    .await?;
+)]
+async fn get_token_history(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let raw_query = query_params.unwrap_or_default();
+    let query_params = query::Query::from_params(&raw_query);
+
+    let query_params = QPV::new(query_params)
+        .valid_pagination()
+        .valid_filter(&raw_query, HISTORY_COLUMNS, false)
+        .no_fields()
+        .valid_sort(HISTORY_COLUMNS)
+        .no_include()
+        .collect_query()?;
 
-    let total_count = db::all_token_count(&cp).await?;
+    let token = find_token(&storage, &id).await?;
+    let filter = Filter::from_params(&raw_query, HISTORY_COLUMNS);
+
+    let history = storage
+        .all_token_history(
+            &token.id,
+            &filter,
+            query_params.page.unwrap().number,
+            query_params.page.unwrap().size,
+            &sort(query_params.sort, HISTORY_COLUMNS)?,
+        )
+        .await?;
+
+    let total_count = storage.all_token_history_count(&token.id, &filter).await?;
 
     Ok(Json(utils::vec_to_jsonapi_document(
-        tokens,
+        history,
         total_count,
         query_params.page.unwrap(),
-        "token",
+        &format!("tokens/{id}/history"),
     )?)
     .into_response())
 }
 
+/// Clear balances of token and index it again from `start_block`.
+/// Balances of token are incomplete until it is backfilled again
+#[utoipa::path(
+    post,
+    path = "/tokens/{id}/reindex",
+    tag = "tokens",
+    params(("id" = String, Path, description = "Token id or contract address")),
+    request_body(content = Option<RequestDocument>, description = "Optional `reindex` resource with `start_block` attribute, 0 by default"),
+    responses(
+        (status = 202, description = "`token` resource with `Token` attributes, indexer picks up request from db", body = SingleDocument),
+        (status = 400, description = "Invalid attributes", body = ErrorDocument),
+        (status = 404, description = "Token not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn post_token_reindex(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Response, AppErrorResponse> {
+    let start_block = if body.is_empty() {
+        0
+    } else {
+        let doc = serde_json::from_slice::<JsonApiDocument>(&body).map_err(|err| {
+            app_err_response!(StatusCode::BAD_REQUEST, format!("Invalid document: {err}"))
+        })?;
+        let data = utils::get_data_from_doc(doc)?;
+
+        get_start_block_attribute(&data)
+            .map_err(|err| AppErrorResponse::new(StatusCode::BAD_REQUEST, vec![err]))?
+            .unwrap_or(0)
+    };
+
+    let token = find_token(&storage, &id).await?;
+
+    storage.request_reindex(&token.id, &start_block).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(token.to_jsonapi_document())).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/balances",
+    tag = "balances",
+    params(
+        PageQuery,
+        CursorQuery,
+        ("filter[holder.holder_addr]" = Option<String>, Query, description = "Comma separated holder addresses"),
+        ("filter[token.contract_addr]" = Option<String>, Query, description = "Comma separated contract addresses"),
+        ("filter[amount]" = Option<String>, Query, description = "Comma separated amounts, compare with `filter[amount][gt|gte|lt|lte]`. At least one filter is required"),
+        ("sort" = String, Query, description = "Comma separated `amount`, `holder.holder_addr`, `token.contract_addr`, `-` prefix for descending order"),
+        BalanceDocumentQuery,
+    ),
+    responses(
+        (status = 200, description = "List of `balance` resources with `BalanceAttributes`", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
 async fn get_balances(
-    Extension(cp): Extension<PgPool>,
+    Extension(storage): Extension<SharedStorage>,
     RawQuery(query_params): RawQuery,
 ) -> Result<Response, AppErrorResponse> {
-    let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
+    let raw_query = query_params.unwrap_or_default();
+    let query_params = query::Query::from_params(&raw_query);
+
+    let query_params = QPV::new(query_params)
+        .valid_pagination()
+        .valid_filter(&raw_query, BALANCE_FILTER, true)
+        .valid_fields(vec![
+            ("balance", BALANCE_FIELDS),
+            ("token", TOKEN_FIELDS),
+            ("holder", HOLDER_FIELDS),
+        ])
+        .valid_sort(BALANCE_SORT)
+        .valid_cursor(&raw_query)
+        .valid_include(vec!["token", "holder"])
+        .valid_precision(&raw_query)
+        .collect_query()?;
+
+    let filter = Filter::from_params(&raw_query, BALANCE_FILTER);
+
+    Ok(Json(
+        balances_document(
+            &storage,
+            &raw_query,
+            query_params,
+            &filter,
+            BALANCE_SORT,
+            "balances",
+        )
+        .await?,
+    )
+    .into_response())
+}
+
+/// Format amounts of balances with decimals of their tokens
+fn set_amount_decimal(balances: &mut [Balance], precision: Option<usize>) {
+    for balance in balances.iter_mut() {
+        balance.amount_decimal =
+            utils::format_amount(&balance.amount, balance.token.decimals, precision);
+    }
+}
+
+/// Balances document, paginated by cursor if `page[after]` or `page[before]` is set,
+/// with requested includes and fields
+async fn balances_document(
+    storage: &SharedStorage,
+    raw_query: &str,
+    query_params: query::Query,
+    filter: &[Filter],
+    sort_columns: &[(&str, Column)],
+    route: &str,
+) -> Result<JsonApiDocument, AppErrorResponse> {
+    let page = query_params.page.unwrap();
+    let sort = sort(query_params.sort.clone(), sort_columns)?;
+    let precision = utils::parse_precision(raw_query).and_then(Result::ok);
+
+    let Some(cursor) = PageCursor::from_params(raw_query) else {
+        let mut balances = storage
+            .all_balance_by_filter(filter, page.number, page.size, &sort)
+            .await?;
+        set_amount_decimal(&mut balances, precision);
+        let total_count = storage.all_balance_by_filter_count(filter).await?;
+
+        let document = utils::vec_to_jsonapi_document(balances, total_count, page, route)?;
+
+        return Ok(utils::sparse_document(document, &query_params));
+    };
+
+    let descending = sort.first().is_some_and(|sort| sort.descending);
+    let (mut balances, has_more) = storage
+        .all_balance_by_cursor(filter, &cursor, page.size, descending)
+        .await?;
+    set_amount_decimal(&mut balances, precision);
+
+    let (has_prev, has_next) = match cursor {
+        PageCursor::After(position) => (position.is_some(), has_more),
+        PageCursor::Before(_) => (has_more, true),
+    };
+
+    let page = utils::CursorPage {
+        size: page.size,
+        prev: balances
+            .first()
+            .filter(|_| has_prev)
+            .map(|balance| Cursor::of(balance).encode()),
+        next: balances
+            .last()
+            .filter(|_| has_next)
+            .map(|balance| Cursor::of(balance).encode()),
+        query: utils::query_without_page(raw_query),
+    };
+
+    let document = utils::vec_to_cursor_document(balances, page, route)?;
+
+    Ok(utils::sparse_document(document, &query_params))
+}
+
+#[utoipa::path(
+    get,
+    path = "/holders/{addr}",
+    tag = "balances",
+    params(("addr" = String, Path, description = "Holder address")),
+    responses(
+        (status = 200, description = "`holder` resource with `Holder` attributes", body = SingleDocument),
+        (status = 404, description = "Holder not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_holder(
+    Extension(storage): Extension<SharedStorage>,
+    Path(addr): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    match storage
+        .holder_by_addr(utils::strip_hex_prefix(&addr))
+        .await?
+    {
+        Some(holder) => Ok(Json(holder.to_jsonapi_document()).into_response()),
+        None => Err(app_err_response!(StatusCode::NOT_FOUND, "Holder not found")),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/holders/{addr}/balances",
+    tag = "balances",
+    params(
+        ("addr" = String, Path, description = "Holder address"),
+        PageQuery,
+        CursorQuery,
+        ("filter[token.contract_addr]" = Option<String>, Query, description = "Comma separated contract addresses"),
+        ("filter[amount]" = Option<String>, Query, description = "Comma separated amounts, compare with `filter[amount][gt|gte|lt|lte]`"),
+        ("sort" = String, Query, description = "Comma separated `amount`, `token.contract_addr`, `-` prefix for descending order"),
+        BalanceDocumentQuery,
+    ),
+    responses(
+        (status = 200, description = "List of `balance` resources with `BalanceAttributes`", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 404, description = "Holder not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_holder_balances(
+    Extension(storage): Extension<SharedStorage>,
+    Path(addr): Path<String>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let raw_query = query_params.unwrap_or_default();
+    let query_params = query::Query::from_params(&raw_query);
 
     let query_params = QPV::new(query_params)
         .valid_pagination()
-        .only_one_filter(vec!["holder.holder_addr", "token.contract_addr"])
+        .valid_filter(&raw_query, HOLDER_BALANCE_FILTER, false)
+        .valid_fields(vec![
+            ("balance", BALANCE_FIELDS),
+            ("token", TOKEN_FIELDS),
+            ("holder", HOLDER_FIELDS),
+        ])
+        .valid_sort(HOLDER_BALANCE_SORT)
+        .valid_cursor(&raw_query)
+        .valid_include(vec!["token", "holder"])
+        .valid_precision(&raw_query)
+        .collect_query()?;
+
+    let holder_addr = utils::strip_hex_prefix(&addr);
+
+    if storage.holder_by_addr(holder_addr).await?.is_none() {
+        return Err(app_err_response!(StatusCode::NOT_FOUND, "Holder not found"));
+    }
+
+    let mut filter = Filter::from_params(&raw_query, HOLDER_BALANCE_FILTER);
+    filter.push(Filter {
+        column: Column::HolderAddr,
+        operator: Operator::In,
+        values: vec![holder_addr.to_string()],
+    });
+
+    let route = format!("holders/{addr}/balances");
+
+    Ok(Json(
+        balances_document(
+            &storage,
+            &raw_query,
+            query_params,
+            &filter,
+            HOLDER_BALANCE_SORT,
+            &route,
+        )
+        .await?,
+    )
+    .into_response())
+}
+
+/// Server-sent events of live transfers with updated holder balances,
+/// optionally filtered by token and holder
+#[utoipa::path(
+    get,
+    path = "/stream",
+    tag = "balances",
+    params(
+        ("filter[token.contract_addr]" = Option<String>, Query, description = "Comma separated contract addresses"),
+        ("filter[holder.holder_addr]" = Option<String>, Query, description = "Comma separated holder addresses"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent `transfer` events with updated balances, `lagged` event with number of `skipped` transfers if client is too slow", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_stream(
+    Extension(events): Extension<EventSender>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppErrorResponse> {
+    let raw_query = query_params.unwrap_or_default();
+    let query_params = query::Query::from_params(&raw_query);
+
+    QPV::new(query_params)
+        .valid_filter(&raw_query, STREAM_FILTER, false)
+        .no_sort()
+        .no_include()
         .no_fields()
-        .only_one_sort(vec!["amount", "-amount"])
+        .collect_query()?;
+
+    let filter = Filter::from_params(&raw_query, STREAM_FILTER);
+    let filter_values = |column: Column| -> Vec<String> {
+        filter
+            .iter()
+            .filter(|filter| filter.column == column)
+            .flat_map(|filter| filter.values.iter())
+            .map(|value| utils::strip_hex_prefix(value).to_lowercase())
+            .collect()
+    };
+
+    let tokens = filter_values(Column::ContractAddr);
+    let holders = filter_values(Column::HolderAddr);
+
+    let is_match = move |values: &Vec<String>, value: &str| {
+        values.is_empty() || values.iter().any(|v| v == value)
+    };
+
+    // Slow client is told how many transfers it missed
+    let stream = BroadcastStream::new(events.subscribe())
+        .filter_map(move |transfer| match transfer {
+            Ok(transfer)
+                if is_match(&tokens, &transfer.token)
+                    && (is_match(&holders, &transfer.from) || is_match(&holders, &transfer.to)) =>
+            {
+                Event::default().event("transfer").json_data(transfer).ok()
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Event::default()
+                .event("lagged")
+                .json_data(serde_json::json!({ "skipped": skipped }))
+                .ok(),
+        })
+        .map(Ok);
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Queue onboarding job of token, indexer adds token and starts indexing it.
+/// Request with `Idempotency-Key` header of earlier request gets its job again
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    tag = "tokens",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Key of request, retries with the same key get the job of first request")),
+    request_body(content = RequestDocument, description = "`token` resource with `contract_addr` and optional `start_block` attributes, backfill starts at deployment block of contract by default. Address is case-insensitive, mixed case has to match its checksum"),
+    responses(
+        (status = 202, description = "Created `job` resource with `Job` attributes, `Location` header is its url", body = SingleDocument),
+        (status = 200, description = "`job` resource of earlier request with the same `Idempotency-Key`", body = SingleDocument),
+        (status = 400, description = "Invalid document or contract address", body = ErrorDocument),
+        (status = 409, description = "Token is already tracked or queued, `about` link of error is its resource", body = ErrorDocument),
+        (status = 422, description = "`Idempotency-Key` was used with another contract address", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn post_token(
+    Extension(storage): Extension<SharedStorage>,
+    headers: HeaderMap,
+    extract::Json(doc): Json<JsonApiDocument>,
+) -> Result<Response, AppErrorResponse> {
+    let data = utils::get_data_from_doc(doc)?;
+    let start_block = get_start_block_attribute(&data)
+        .map_err(|err| AppErrorResponse::new(StatusCode::BAD_REQUEST, vec![err]))?;
+
+    let contract_addr = match data.get_attribute("contract_addr") {
+        Some(value) => match value.as_str() {
+            None => return Err(app_err_response!(StatusCode::BAD_REQUEST, "Missing data")),
+            Some(contract_addr) => utils::normalize_addr(contract_addr).map_err(|err| {
+                AppErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    vec![AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        &err,
+                        Some(&err),
+                        Some("contract_addr"),
+                    )],
+                )
+            })?,
+        },
+        None => {
+            return Err(app_err_response!(
+                StatusCode::BAD_REQUEST,
+                "Missing 'contract_addr' attribute"
+            ))
+        }
+    };
+
+    let idempotency_key = match headers.get("Idempotency-Key") {
+        None => None,
+        Some(value) => Some(value.to_str().map_err(|_| {
+            app_err_response!(
+                StatusCode::BAD_REQUEST,
+                "'Idempotency-Key' header must be visible ASCII"
+            )
+        })?),
+    };
+
+    if let Some(idempotency_key) = idempotency_key {
+        if let Some(job) = storage.job_by_idempotency_key(idempotency_key).await? {
+            return replay_job(job, &contract_addr);
+        }
+    }
+
+    if let Some(token) = storage.token_by_contract(&contract_addr).await? {
+        return Err(conflict(
+            "Token is already tracked",
+            &format!("tokens/{}", token.id),
+        )?);
+    }
+
+    match storage
+        .add_job(&contract_addr, start_block, idempotency_key)
+        .await?
+    {
+        NewJob::Added(job) => {
+            let route = format!("jobs/{}", job.id);
+
+            Ok((
+                StatusCode::ACCEPTED,
+                [(header::LOCATION, format!("/{route}"))],
+                Json(utils::resource_document(&job, &route)?),
+            )
+                .into_response())
+        }
+        // Concurrent request with the same key added its job first
+        NewJob::IdempotencyKeyUsed => match idempotency_key {
+            Some(idempotency_key) => match storage.job_by_idempotency_key(idempotency_key).await? {
+                Some(job) => replay_job(job, &contract_addr),
+                None => Err(app_err_response!(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Job of idempotency key not found"
+                )),
+            },
+            None => Err(app_err_response!(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Job not added"
+            )),
+        },
+        // Open job could be finished since, then its token is linked
+        NewJob::ContractQueued => {
+            if let Some(job) = storage.open_job_by_contract(&contract_addr).await? {
+                return Err(conflict(
+                    "Token is already queued",
+                    &format!("jobs/{}", job.id),
+                )?);
+            }
+
+            match storage.token_by_contract(&contract_addr).await? {
+                Some(token) => Err(conflict(
+                    "Token is already tracked",
+                    &format!("tokens/{}", token.id),
+                )?),
+                None => Err(app_err_response!(
+                    StatusCode::CONFLICT,
+                    "Token is already queued"
+                )),
+            }
+        }
+    }
+}
+
+/// Job of earlier request with the same idempotency key,
+/// key can't be reused for another contract
+fn replay_job(job: Job, contract_addr: &str) -> Result<Response, AppErrorResponse> {
+    if job.contract_addr != contract_addr {
+        let message = "'Idempotency-Key' was used with another contract address";
+        return Err(AppErrorResponse::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            vec![AppError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                message,
+                Some(message),
+                None,
+            )],
+        ));
+    }
+
+    let route = format!("jobs/{}", job.id);
+
+    Ok(Json(utils::resource_document(&job, &route)?).into_response())
+}
+
+/// Conflict with existing resource at route
+fn conflict(message: &str, route: &str) -> Result<AppErrorResponse, AppErrorResponse> {
+    let error = AppError::new(StatusCode::CONFLICT, message, Some(message), None)
+        .with_about_link(utils::service_url(route)?);
+
+    Ok(AppErrorResponse::new(StatusCode::CONFLICT, vec![error]))
+}
+
+/// Onboarding job of token, `token_id` is set when job is done
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = i32, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "`job` resource with `Job` attributes", body = SingleDocument),
+        (status = 404, description = "Job not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_job(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    let job: Option<Job> = match id.parse::<i32>() {
+        Ok(job_id) => storage.job_by_id(&job_id).await?,
+        Err(_) => None,
+    };
+    let job = job.ok_or_else(|| app_err_response!(StatusCode::NOT_FOUND, "Job not found"))?;
+
+    Ok(Json(utils::resource_document(&job, &format!("jobs/{}", job.id))?).into_response())
+}
+
+/// Get optional non-negative `start_block` attribute of resource
+fn get_start_block_attribute(data: &Resource) -> Result<Option<i64>, AppError> {
+    match data.get_attribute("start_block") {
+        None => Ok(None),
+        Some(value) => match value.as_i64() {
+            Some(start_block) if start_block >= 0 => Ok(Some(start_block)),
+            _ => {
+                let message = "'start_block' attribute must be a non-negative integer";
+                Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    Some(message),
+                    Some("start_block"),
+                ))
+            }
+        },
+    }
+}
+
+/// Get optional string attribute of resource
+fn get_str_attribute(data: &Resource, name: &str) -> Result<Option<String>, AppError> {
+    match data.get_attribute(name) {
+        None => Ok(None),
+        Some(value) => match value.as_str() {
+            Some(value) => Ok(Some(value.to_string())),
+            None => {
+                let message = format!("'{name}' attribute must be a string");
+                Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    &message,
+                    Some(&message),
+                    Some(name),
+                ))
+            }
+        },
+    }
+}
+
+/// Register webhook with transfer or balance rule
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body(content = RequestDocument, description = "`webhook` resource with `url`, `secret`, `event` (`transfer` or `balance`) and optional `contract_addr`, `holder_addr`, `min_amount` attributes"),
+    responses(
+        (status = 201, description = "Created `webhook` resource with `Webhook` attributes", body = SingleDocument),
+        (status = 400, description = "Invalid attributes", body = ErrorDocument),
+        (status = 404, description = "Token not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn post_webhook(
+    Extension(storage): Extension<SharedStorage>,
+    extract::Json(doc): Json<JsonApiDocument>,
+) -> Result<Response, AppErrorResponse> {
+    let data = utils::get_data_from_doc(doc)?;
+    let mut errors = Vec::new();
+
+    let mut attribute = |name: &str, required: bool, valid: &dyn Fn(&str) -> bool| {
+        let value = match get_str_attribute(&data, name) {
+            Ok(value) => value,
+            Err(err) => {
+                errors.push(err);
+                return None;
+            }
+        };
+
+        let message = match &value {
+            None if required => format!("'{name}' attribute is missing"),
+            Some(value) if !valid(value) => format!("'{name}' attribute is invalid"),
+            _ => return value,
+        };
+
+        errors.push(AppError::new(
+            StatusCode::BAD_REQUEST,
+            &message,
+            Some(&message),
+            Some(name),
+        ));
+
+        None
+    };
+
+    let url = attribute("url", true, &|url| {
+        url.starts_with("http://") || url.starts_with("https://")
+    });
+    let secret = attribute("secret", true, &|secret| !secret.is_empty());
+    let event = attribute("event", true, &|event| {
+        ["transfer", "balance"].contains(&event)
+    });
+    let contract_addr = attribute("contract_addr", false, &|addr| {
+        utils::is_hex_address(utils::strip_hex_prefix(addr))
+    });
+    let holder_addr = attribute("holder_addr", false, &|addr| {
+        utils::is_hex_address(utils::strip_hex_prefix(addr))
+    });
+    let min_amount = attribute("min_amount", false, &|amount| {
+        !amount.is_empty() && amount.chars().all(|c| c.is_ascii_digit())
+    });
+
+    if !errors.is_empty() {
+        return Err(AppErrorResponse::new(StatusCode::BAD_REQUEST, errors));
+    }
+
+    let token_id = match contract_addr {
+        Some(contract_addr) => Some(find_token(&storage, &contract_addr).await?.id),
+        None => None,
+    };
+
+    let webhook = storage
+        .add_webhook(
+            &url.unwrap_or_default(),
+            &secret.unwrap_or_default(),
+            &event.unwrap_or_default(),
+            token_id,
+            holder_addr.as_deref().map(utils::strip_hex_prefix),
+            min_amount.as_deref(),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(webhook.to_jsonapi_document())).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "List of `webhook` resources with `Webhook` attributes", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_webhooks(
+    Extension(storage): Extension<SharedStorage>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
+
+    let query_params = QPV::new(query_params)
+        .valid_pagination()
+        .no_sort()
+        .no_filter()
         .no_include()
+        .no_fields()
         .collect_query()?;
 
-    let filter = query_params.filter.clone().unwrap();
+    let webhooks = storage
+        .all_webhook(
+            query_params.page.unwrap().number,
+            query_params.page.unwrap().size,
+        )
+        .await?;
+
+    let total_count = storage.all_webhook_count().await?;
+
+    Ok(Json(utils::vec_to_jsonapi_document(
+        webhooks,
+        total_count,
+        query_params.page.unwrap(),
+        "webhooks",
+    )?)
+    .into_response())
+}
+
+/// Find webhook by id
+async fn find_webhook(storage: &SharedStorage, id: &str) -> Result<Webhook, AppErrorResponse> {
+    let webhook = match id.parse::<i32>() {
+        Ok(webhook_id) => storage.webhook_by_id(&webhook_id).await?,
+        Err(_) => None,
+    };
+
+    webhook.ok_or_else(|| app_err_response!(StatusCode::NOT_FOUND, "Webhook not found"))
+}
 
-    let balances = db::all_balance_by_filter(
-        &cp,
-        query_params.filter.unwrap(),
-        query_params.page.unwrap().number,
-        query_params.page.unwrap().size,
-        query_params.sort,
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "`webhook` resource with `Webhook` attributes", body = SingleDocument),
+        (status = 404, description = "Webhook not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
     )
-    .await?;
+)]
+async fn get_webhook(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    let webhook = find_webhook(&storage, &id).await?;
+
+    Ok(Json(webhook.to_jsonapi_document()).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    responses(
+        (status = 204, description = "Webhook deleted with its delivery log"),
+        (status = 404, description = "Webhook not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn delete_webhook(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    let webhook = find_webhook(&storage, &id).await?;
+
+    storage.delete_webhook(&webhook.id).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
 
-    let total_count = db::all_balance_by_filter_count(&cp, filter).await?;
+/// Get delivery log of webhook, newest first
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id"), PageQuery),
+    responses(
+        (status = 200, description = "List of `webhook_delivery` resources with `WebhookDelivery` attributes", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 404, description = "Webhook not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_webhook_deliveries(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
+
+    let query_params = QPV::new(query_params)
+        .valid_pagination()
+        .no_sort()
+        .no_filter()
+        .no_include()
+        .no_fields()
+        .collect_query()?;
+
+    let webhook = find_webhook(&storage, &id).await?;
+
+    let deliveries = storage
+        .all_webhook_delivery(
+            &webhook.id,
+            query_params.page.unwrap().number,
+            query_params.page.unwrap().size,
+        )
+        .await?;
+
+    let total_count = storage.all_webhook_delivery_count(&webhook.id).await?;
 
     Ok(Json(utils::vec_to_jsonapi_document(
-        balances,
+        deliveries,
         total_count,
         query_params.page.unwrap(),
-        "balance",
+        &format!("webhooks/{id}/deliveries"),
     )?)
     .into_response())
 }
 
-async fn post_token(
-    Extension(cp): Extension<PgPool>,
+/// Requests per minute of api key without rate limit attribute
+const DEFAULT_RATE_LIMIT: i32 = 60;
+
+/// Create api key with scopes, plain key is returned only in this response
+#[utoipa::path(
+    post,
+    path = "/api_keys",
+    tag = "api_keys",
+    request_body(content = RequestDocument, description = "`api_key` resource with `name`, `scopes` (`read`, `add-token`, `admin`) and optional `rate_limit` attributes"),
+    responses(
+        (status = 201, description = "Created `api_key` resource with `ApiKey` attributes and plain `key`", body = SingleDocument),
+        (status = 400, description = "Invalid attributes", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn post_api_key(
+    Extension(storage): Extension<SharedStorage>,
     extract::Json(doc): Json<JsonApiDocument>,
-    tx: mpsc::Sender<Token>,
 ) -> Result<Response, AppErrorResponse> {
     let data = utils::get_data_from_doc(doc)?;
+    let mut errors = Vec::new();
 
-    match data.get_attribute("contract_addr") {
-        Some(value) => match value.as_str() {
-            None => Err(app_err_response!(StatusCode::BAD_REQUEST, "Missing data")),
-            Some(contract_addr) => {
-                if let Ok(token) = evm::add_token_by_contract(&cp, contract_addr).await {
-                    let send = tx.send(token.clone()).await;
-                    match send {
-                        Ok(_) => Ok((StatusCode::CREATED, Json(token.to_jsonapi_document()))
-                            .into_response()),
-                        Err(err) => Err(app_err_response!(StatusCode::BAD_REQUEST, err)),
-                    }
-                } else {
-                    Err(app_err_response!(
-                        StatusCode::BAD_REQUEST,
-                        "Add token by contract error"
-                    ))
-                }
+    let mut invalid = |name: &str, message: String| {
+        errors.push(AppError::new(
+            StatusCode::BAD_REQUEST,
+            &message,
+            Some(&message),
+            Some(name),
+        ));
+    };
+
+    let name = match get_str_attribute(&data, "name") {
+        Ok(Some(name)) if !name.is_empty() => name,
+        Ok(_) => {
+            invalid("name", "'name' attribute is missing".to_string());
+            String::new()
+        }
+        Err(_) => {
+            invalid("name", "'name' attribute must be a string".to_string());
+            String::new()
+        }
+    };
+
+    let scopes: Vec<String> = match data.get_attribute("scopes").and_then(|v| v.as_array()) {
+        Some(scopes) if !scopes.is_empty() => scopes
+            .iter()
+            .map(|scope| scope.as_str().unwrap_or_default().to_string())
+            .collect(),
+        _ => {
+            invalid(
+                "scopes",
+                "'scopes' attribute must be a non-empty array".to_string(),
+            );
+            Vec::new()
+        }
+    };
+
+    for scope in scopes.iter() {
+        if auth::Scope::from_param(scope).is_none() {
+            invalid(
+                "scopes",
+                format!("'scopes' attribute has invalid value '{scope}'. Valid values: read, add-token, admin"),
+            );
+        }
+    }
+
+    let rate_limit = match data.get_attribute("rate_limit") {
+        None => DEFAULT_RATE_LIMIT,
+        Some(value) => match value.as_i64().and_then(|v| i32::try_from(v).ok()) {
+            Some(rate_limit) if rate_limit > 0 => rate_limit,
+            _ => {
+                invalid(
+                    "rate_limit",
+                    "'rate_limit' attribute must be a positive integer".to_string(),
+                );
+                0
             }
         },
-        None => Err(app_err_response!(
-            StatusCode::BAD_REQUEST,
-            "Missing 'contract_addr' attribute"
-        )),
+    };
+
+    if !errors.is_empty() {
+        return Err(AppErrorResponse::new(StatusCode::BAD_REQUEST, errors));
+    }
+
+    let key = auth::generate_key();
+
+    let api_key = storage
+        .add_api_key(&auth::hash_key(&key), &name, &scopes, &rate_limit)
+        .await?
+        .ok_or_else(|| app_err_response!(StatusCode::CONFLICT, "API key already exists"))?;
+
+    let api_key = ApiKey {
+        key: Some(key),
+        ..api_key
+    };
+
+    Ok((StatusCode::CREATED, Json(api_key.to_jsonapi_document())).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api_keys",
+    tag = "api_keys",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "List of `api_key` resources with `ApiKey` attributes", body = ListDocument),
+        (status = 400, description = "Invalid query parameters", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn get_api_keys(
+    Extension(storage): Extension<SharedStorage>,
+    RawQuery(query_params): RawQuery,
+) -> Result<Response, AppErrorResponse> {
+    let query_params = query::Query::from_params(query_params.unwrap_or_default().as_str());
+
+    let query_params = QPV::new(query_params)
+        .valid_pagination()
+        .no_sort()
+        .no_filter()
+        .no_include()
+        .no_fields()
+        .collect_query()?;
+
+    let api_keys = storage
+        .all_api_key(
+            query_params.page.unwrap().number,
+            query_params.page.unwrap().size,
+        )
+        .await?;
+
+    let total_count = storage.all_api_key_count().await?;
+
+    Ok(Json(utils::vec_to_jsonapi_document(
+        api_keys,
+        total_count,
+        query_params.page.unwrap(),
+        "api_keys",
+    )?)
+    .into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api_keys/{id}",
+    tag = "api_keys",
+    params(("id" = i32, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "API key revoked"),
+        (status = 404, description = "API key not found", body = ErrorDocument),
+        (status = 401, description = "Missing or invalid API key", body = ErrorDocument),
+        (status = 403, description = "API key has no scope of route", body = ErrorDocument),
+        (status = 429, description = "Rate limit of API key exceeded", body = ErrorDocument),
+    )
+)]
+async fn delete_api_key(
+    Extension(storage): Extension<SharedStorage>,
+    Path(id): Path<String>,
+) -> Result<Response, AppErrorResponse> {
+    let deleted = match id.parse::<i32>() {
+        Ok(api_key_id) => storage.delete_api_key(&api_key_id).await?,
+        Err(_) => false,
+    };
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(app_err_response!(
+            StatusCode::NOT_FOUND,
+            "API key not found"
+        ))
     }
 }
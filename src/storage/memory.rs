@@ -0,0 +1,1148 @@
+use super::{token_stats, total_supply, with_progress, NewJob, Storage};
+use crate::{
+    amount::Amount,
+    model::{
+        ApiKey, Balance, Column, DueWebhookDelivery, Filter, Holder, Job, JobState, Operator,
+        PageCursor, Sort, SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+    utils,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::DateTime;
+use ethers::types::U256;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Default)]
+struct State {
+    tokens: BTreeMap<i32, Token>,
+    /// Start blocks of tokens waiting for reindex
+    reindex: BTreeMap<i32, i64>,
+    holders: BTreeMap<i32, Holder>,
+    holder_ids: HashMap<String, i32>,
+    /// Amounts by holder and token id
+    balances: BTreeMap<(i32, i32), Amount>,
+    /// Stats by token id with time of calculation
+    stats: BTreeMap<i32, (TokenStats, Instant)>,
+    /// History by token id and block number
+    history: BTreeMap<(i32, i64), TokenHistory>,
+    /// Webhooks by id with their secrets
+    webhooks: BTreeMap<i32, (Webhook, String)>,
+    /// Deliveries by id with time of next attempt
+    deliveries: BTreeMap<i32, (WebhookDelivery, Instant)>,
+    /// Api keys by id with hashes of keys
+    api_keys: BTreeMap<i32, (ApiKey, String)>,
+    /// Jobs by id with idempotency keys
+    jobs: BTreeMap<i32, (Job, Option<String>)>,
+    /// Workers of claimed jobs with expiry of their leases
+    job_leases: HashMap<i32, (String, Instant)>,
+    /// Workers indexing tokens with expiry of their leases
+    token_leases: HashMap<i32, (String, Instant)>,
+    /// Last ids of webhooks, deliveries, api keys and jobs,
+    /// ids of deleted items are not reused
+    last_ids: HashMap<&'static str, i32>,
+}
+
+/// Storage in process memory, for tests and short-lived deployments.
+/// Data is lost when process stops
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<State>,
+}
+
+/// Lowercase address without `0x` prefix, as stored
+fn normalize_addr(addr: &str) -> String {
+    utils::strip_hex_prefix(addr).to_lowercase()
+}
+
+/// Compare token or balance column with filter value
+fn compare_value(
+    column: Column,
+    amount: &Amount,
+    token: &Token,
+    holder: Option<&Holder>,
+    value: &str,
+) -> Option<Ordering> {
+    match column {
+        Column::TokenId => value
+            .parse::<i64>()
+            .ok()
+            .map(|v| i64::from(token.id).cmp(&v)),
+        Column::ContractAddr => Some(token.contract_addr.cmp(&normalize_addr(value))),
+        Column::Symbol => Some(token.symbol.as_str().cmp(value)),
+        Column::HolderAddr => holder.map(|holder| holder.holder_addr.cmp(&normalize_addr(value))),
+        Column::Amount => value.parse::<Amount>().ok().map(|v| amount.cmp(&v)),
+        Column::BlockNumber => None,
+    }
+}
+
+fn matches(filter: &[Filter], amount: &Amount, token: &Token, holder: &Holder) -> bool {
+    filter.iter().all(
+        |Filter {
+             column,
+             operator,
+             values,
+         }| {
+            values.iter().any(|value| {
+                let ordering = compare_value(*column, amount, token, Some(holder), value);
+
+                matches!(
+                    (operator, ordering),
+                    (Operator::In, Some(Ordering::Equal))
+                        | (Operator::Gt, Some(Ordering::Greater))
+                        | (Operator::Gte, Some(Ordering::Greater | Ordering::Equal))
+                        | (Operator::Lt, Some(Ordering::Less))
+                        | (Operator::Lte, Some(Ordering::Less | Ordering::Equal))
+                )
+            })
+        },
+    )
+}
+
+fn compare_token(column: Column, a: &Token, b: &Token) -> Ordering {
+    match column {
+        Column::TokenId => a.id.cmp(&b.id),
+        Column::ContractAddr => a.contract_addr.cmp(&b.contract_addr),
+        Column::Symbol => a.symbol.cmp(&b.symbol),
+        _ => Ordering::Equal,
+    }
+}
+
+/// Order by sort keys, keeps storage order of equal items
+fn sort_by<T>(items: &mut [T], sort: &[Sort], compare: impl Fn(Column, &T, &T) -> Ordering) {
+    items.sort_by(|a, b| {
+        sort.iter()
+            .map(|Sort { column, descending }| {
+                let ordering = compare(*column, a, b);
+                if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Filter of token history by block number
+fn matches_history(filter: &[Filter], history: &TokenHistory) -> bool {
+    filter.iter().all(
+        |Filter {
+             column,
+             operator,
+             values,
+         }| {
+            values.iter().any(|value| {
+                let ordering = match column {
+                    Column::BlockNumber => value
+                        .parse::<i64>()
+                        .ok()
+                        .map(|v| history.block_number.cmp(&v)),
+                    _ => None,
+                };
+
+                matches!(
+                    (operator, ordering),
+                    (Operator::In, Some(Ordering::Equal))
+                        | (Operator::Gt, Some(Ordering::Greater))
+                        | (Operator::Gte, Some(Ordering::Greater | Ordering::Equal))
+                        | (Operator::Lt, Some(Ordering::Less))
+                        | (Operator::Lte, Some(Ordering::Less | Ordering::Equal))
+                )
+            })
+        },
+    )
+}
+
+/// Current UTC time as `YYYY-MM-DD HH:MM:SS`
+fn now_text() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+
+    DateTime::from_timestamp(secs, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn page<T>(items: Vec<T>, number: i64, size: i64) -> Vec<T> {
+    items
+        .into_iter()
+        .skip((number * size).max(0) as usize)
+        .take(size.max(0) as usize)
+        .collect()
+}
+
+impl State {
+    fn next_id(&mut self, table: &'static str) -> i32 {
+        let last_id = self.last_ids.entry(table).or_insert(0);
+        *last_id += 1;
+        *last_id
+    }
+
+    /// Non-zero balances of token
+    fn positive_amounts(&self, token_id: &i32) -> Vec<U256> {
+        self.balances
+            .iter()
+            .filter(|((_, id), amount)| id == token_id && amount.is_positive())
+            .map(|(_, amount)| amount.magnitude())
+            .collect()
+    }
+
+    fn job_mut(&mut self, job_id: &i32) -> Result<&mut Job> {
+        self.jobs
+            .get_mut(job_id)
+            .map(|(job, _)| job)
+            .ok_or_else(|| anyhow!("Job {job_id} not found"))
+    }
+
+    fn token_mut(&mut self, token_id: &i32) -> Result<&mut Token> {
+        self.tokens
+            .get_mut(token_id)
+            .ok_or_else(|| anyhow!("Token {token_id} not found"))
+    }
+
+    /// Id of holder, holder is added if it is new
+    fn holder_id(&mut self, holder_addr: &str) -> i32 {
+        let holder_addr = normalize_addr(holder_addr);
+
+        if let Some(holder_id) = self.holder_ids.get(&holder_addr) {
+            return *holder_id;
+        }
+
+        let holder_id = self.holders.keys().last().map_or(1, |id| id + 1);
+
+        self.holder_ids.insert(holder_addr.clone(), holder_id);
+        self.holders.insert(
+            holder_id,
+            Holder {
+                id: holder_id,
+                holder_addr,
+            },
+        );
+
+        holder_id
+    }
+
+    /// Balance of holder, zero for unknown holder
+    fn balance(&self, holder_addr: &str, token_id: i32) -> Amount {
+        self.holder_ids
+            .get(&normalize_addr(holder_addr))
+            .and_then(|holder_id| self.balances.get(&(*holder_id, token_id)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Add signed amounts to balances of holders at once,
+    /// none of them is changed if one overflows. Returns new balances
+    fn add_balances<'a>(
+        &mut self,
+        token_id: i32,
+        deltas: &[(&'a str, Amount)],
+    ) -> Result<Vec<(&'a str, Amount)>> {
+        let mut balances: Vec<(&str, Amount)> = Vec::new();
+
+        for (holder_addr, delta) in deltas {
+            // Holder could be changed twice in the same batch
+            let current = balances
+                .iter()
+                .rev()
+                .find(|(addr, _)| normalize_addr(addr) == normalize_addr(holder_addr))
+                .map_or_else(
+                    || self.balance(holder_addr, token_id),
+                    |(_, balance)| *balance,
+                );
+            let balance = current
+                .checked_add(*delta)
+                .ok_or_else(|| anyhow!("Balance overflow"))?;
+
+            balances.push((holder_addr, balance));
+        }
+
+        for (holder_addr, balance) in balances.iter() {
+            let holder_id = self.holder_id(holder_addr);
+            self.balances.insert((holder_id, token_id), *balance);
+        }
+
+        Ok(balances)
+    }
+
+    /// Non-zero balances matching filter with their amounts
+    fn balances(&self, filter: &[Filter]) -> Vec<(Amount, Balance)> {
+        self.balances
+            .iter()
+            .filter(|(_, amount)| amount.is_positive())
+            .filter_map(|((holder_id, token_id), amount)| {
+                let token = self.tokens.get(token_id)?;
+                let holder = self.holders.get(holder_id)?;
+
+                matches(filter, amount, token, holder).then(|| {
+                    let balance = Balance {
+                        id: format!("{holder_id}_{token_id}"),
+                        amount: amount.to_string(),
+                        amount_decimal: String::new(),
+                        token: with_progress(token.clone()),
+                        holder: holder.clone(),
+                    };
+
+                    (*amount, balance)
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn all_token(&self, number: i64, size: i64, sort: &[Sort]) -> Result<Vec<Token>> {
+        let state = self.state.lock().unwrap();
+        let mut tokens: Vec<Token> = state.tokens.values().cloned().map(with_progress).collect();
+
+        sort_by(&mut tokens, sort, compare_token);
+
+        Ok(page(tokens, number, size))
+    }
+
+    async fn all_token_count(&self) -> Result<i64> {
+        Ok(self.state.lock().unwrap().tokens.len() as i64)
+    }
+
+    async fn token_by_id(&self, token_id: &i32) -> Result<Option<Token>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state.tokens.get(token_id).cloned().map(with_progress))
+    }
+
+    async fn token_by_contract(&self, contract_addr: &str) -> Result<Option<Token>> {
+        let contract_addr = normalize_addr(contract_addr);
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .tokens
+            .values()
+            .find(|token| token.contract_addr == contract_addr)
+            .cloned()
+            .map(with_progress))
+    }
+
+    async fn add_token(
+        &self,
+        contract_addr: &str,
+        last_checked_block: &i64,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<i32> {
+        let contract_addr = normalize_addr(contract_addr);
+        let mut state = self.state.lock().unwrap();
+
+        if state
+            .tokens
+            .values()
+            .any(|token| token.contract_addr == contract_addr)
+        {
+            return Err(anyhow!("Token {contract_addr} already exists"));
+        }
+
+        let token_id = state.tokens.keys().last().map_or(1, |id| id + 1);
+
+        state.tokens.insert(
+            token_id,
+            Token {
+                id: token_id,
+                contract_addr,
+                last_checked_block: *last_checked_block,
+                symbol: symbol.to_string(),
+                decimals: *decimals,
+                sync_state: SyncState::Backfilling.as_str().to_string(),
+                head_block: 0,
+                lag: 0,
+                backfill_rate: 0.0,
+                eta: None,
+                sync_error: None,
+            },
+        );
+
+        Ok(token_id)
+    }
+
+    async fn update_token_metadata(
+        &self,
+        token_id: &i32,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let token = state.token_mut(token_id)?;
+
+        token.symbol = symbol.to_string();
+        token.decimals = *decimals;
+
+        Ok(())
+    }
+
+    async fn update_token_last_checked_block(
+        &self,
+        last_checked_block: &i64,
+        token_id: &i32,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.token_mut(token_id)?.last_checked_block = *last_checked_block;
+
+        Ok(())
+    }
+
+    async fn update_token_sync(
+        &self,
+        token_id: &i32,
+        sync_state: SyncState,
+        head_block: &i64,
+        backfill_rate: Option<f64>,
+        sync_error: Option<&str>,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let token = state.token_mut(token_id)?;
+
+        token.sync_state = sync_state.as_str().to_string();
+        token.head_block = token.head_block.max(*head_block);
+        token.backfill_rate = backfill_rate.unwrap_or(token.backfill_rate);
+        token.sync_error = sync_error.map(|error| error.to_string());
+
+        Ok(())
+    }
+
+    async fn request_reindex(&self, token_id: &i32, start_block: &i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.tokens.contains_key(token_id) {
+            state.reindex.insert(*token_id, *start_block);
+        }
+
+        Ok(())
+    }
+
+    async fn all_reindex_request(&self) -> Result<Vec<(i32, i64)>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .reindex
+            .iter()
+            .map(|(id, block)| (*id, *block))
+            .collect())
+    }
+
+    async fn claim_token(&self, token_id: &i32, worker: &str, lease: Duration) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if !state.tokens.contains_key(token_id) {
+            return Ok(false);
+        }
+
+        let free = state
+            .token_leases
+            .get(token_id)
+            .is_none_or(|(owner, expires_at)| owner == worker || *expires_at <= now);
+
+        if free {
+            state
+                .token_leases
+                .insert(*token_id, (worker.to_string(), now + lease));
+        }
+
+        Ok(free)
+    }
+
+    async fn release_token(&self, token_id: &i32, worker: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if state
+            .token_leases
+            .get(token_id)
+            .is_some_and(|(owner, _)| owner == worker)
+        {
+            state.token_leases.remove(token_id);
+        }
+
+        Ok(())
+    }
+
+    async fn reset_token(&self, token_id: &i32, last_checked_block: &i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        state.balances.retain(|(_, id), _| id != token_id);
+        state.history.retain(|(id, _), _| id != token_id);
+        state.stats.remove(token_id);
+        state.reindex.remove(token_id);
+
+        let token = state.token_mut(token_id)?;
+        token.last_checked_block = *last_checked_block;
+        token.sync_state = SyncState::Backfilling.as_str().to_string();
+        token.backfill_rate = 0.0;
+        token.sync_error = None;
+
+        Ok(())
+    }
+
+    async fn add_or_get_holder(&self, holder_addr: &str) -> Result<i32> {
+        Ok(self.state.lock().unwrap().holder_id(holder_addr))
+    }
+
+    async fn holder_by_addr(&self, holder_addr: &str) -> Result<Option<Holder>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .holder_ids
+            .get(&normalize_addr(holder_addr))
+            .and_then(|holder_id| state.holders.get(holder_id))
+            .cloned())
+    }
+
+    async fn all_balance_by_filter(
+        &self,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<Balance>> {
+        let mut balances = self.state.lock().unwrap().balances(filter);
+
+        sort_by(
+            &mut balances,
+            sort,
+            |column, (a_amount, a), (b_amount, b)| match column {
+                Column::Amount => a_amount.cmp(b_amount),
+                Column::HolderAddr => a.holder.holder_addr.cmp(&b.holder.holder_addr),
+                column => compare_token(column, &a.token, &b.token),
+            },
+        );
+
+        Ok(page(balances, number, size)
+            .into_iter()
+            .map(|(_, balance)| balance)
+            .collect())
+    }
+
+    async fn all_balance_by_cursor(
+        &self,
+        filter: &[Filter],
+        cursor: &PageCursor,
+        size: i64,
+        descending: bool,
+    ) -> Result<(Vec<Balance>, bool)> {
+        let (position, backward) = match cursor {
+            PageCursor::After(position) => (position.as_ref(), false),
+            PageCursor::Before(position) => (Some(position), true),
+        };
+        let descending = descending != backward;
+
+        let position = match position {
+            Some(position) => Some((
+                position.amount.parse::<Amount>()?,
+                position.holder_id,
+                position.token_id,
+            )),
+            None => None,
+        };
+
+        let key =
+            |(amount, balance): &(Amount, Balance)| (*amount, balance.holder.id, balance.token.id);
+
+        let mut balances: Vec<(Amount, Balance)> = self
+            .state
+            .lock()
+            .unwrap()
+            .balances(filter)
+            .into_iter()
+            .filter(|balance| match &position {
+                Some(position) if descending => key(balance) < *position,
+                Some(position) => key(balance) > *position,
+                None => true,
+            })
+            .collect();
+
+        balances.sort_by_key(key);
+        if descending {
+            balances.reverse();
+        }
+
+        let has_more = balances.len() as i64 > size;
+        let mut balances: Vec<Balance> = page(balances, 0, size)
+            .into_iter()
+            .map(|(_, balance)| balance)
+            .collect();
+
+        if backward {
+            balances.reverse();
+        }
+
+        Ok((balances, has_more))
+    }
+
+    async fn all_balance_by_filter_count(&self, filter: &[Filter]) -> Result<i64> {
+        Ok(self.state.lock().unwrap().balances(filter).len() as i64)
+    }
+
+    async fn upsert_balance(
+        &self,
+        from_holder_id: &i32,
+        to_holder_id: &i32,
+        token_id: &i32,
+        amount: &str,
+        last_checked_block: &i64,
+    ) -> Result<Vec<(i32, String)>> {
+        let amount = amount.parse::<Amount>()?;
+        let mut state = self.state.lock().unwrap();
+        let holder_addr = |holder_id: &i32| {
+            state
+                .holders
+                .get(holder_id)
+                .map(|holder| holder.holder_addr.clone())
+                .ok_or_else(|| anyhow!("Holder {holder_id} not found"))
+        };
+        let (from_holder_addr, to_holder_addr) =
+            (holder_addr(from_holder_id)?, holder_addr(to_holder_id)?);
+
+        // Check token before any balance is changed
+        state.token_mut(token_id)?;
+        let balances = state.add_balances(
+            *token_id,
+            &[(&from_holder_addr, -amount), (&to_holder_addr, amount)],
+        )?;
+        state.token_mut(token_id)?.last_checked_block = *last_checked_block;
+
+        Ok([*from_holder_id, *to_holder_id]
+            .into_iter()
+            .zip(balances)
+            .map(|(holder_id, (_, balance))| (holder_id, balance.to_string()))
+            .collect())
+    }
+
+    async fn apply_balance_deltas(
+        &self,
+        token_id: &i32,
+        deltas: &[(String, String)],
+        last_checked_block: &i64,
+    ) -> Result<()> {
+        let deltas = deltas
+            .iter()
+            .map(|(holder_addr, delta)| Ok((holder_addr.as_str(), delta.parse::<Amount>()?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut state = self.state.lock().unwrap();
+
+        // Check token before any balance is changed
+        state.token_mut(token_id)?;
+        state.add_balances(*token_id, &deltas)?;
+        state.token_mut(token_id)?.last_checked_block = *last_checked_block;
+
+        Ok(())
+    }
+
+    async fn add_token_history(&self, token_id: &i32, block_number: &i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let amounts = state.positive_amounts(token_id);
+        let total_supply = total_supply(&amounts)?;
+
+        state
+            .history
+            .entry((*token_id, *block_number))
+            .or_insert_with(|| TokenHistory {
+                id: format!("{token_id}_{block_number}"),
+                block_number: *block_number,
+                holder_count: amounts.len() as i64,
+                total_supply: total_supply.to_string(),
+            });
+
+        Ok(())
+    }
+
+    async fn all_token_history(
+        &self,
+        token_id: &i32,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<TokenHistory>> {
+        let state = self.state.lock().unwrap();
+        let mut history: Vec<TokenHistory> = state
+            .history
+            .range((*token_id, i64::MIN)..=(*token_id, i64::MAX))
+            .map(|(_, history)| history)
+            .filter(|history| matches_history(filter, history))
+            .cloned()
+            .collect();
+
+        sort_by(&mut history, sort, |column, a, b| match column {
+            Column::BlockNumber => a.block_number.cmp(&b.block_number),
+            _ => Ordering::Equal,
+        });
+
+        Ok(page(history, number, size))
+    }
+
+    async fn all_token_history_count(&self, token_id: &i32, filter: &[Filter]) -> Result<i64> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .history
+            .range((*token_id, i64::MIN)..=(*token_id, i64::MAX))
+            .filter(|(_, history)| matches_history(filter, history))
+            .count() as i64)
+    }
+
+    async fn fresh_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+        max_age: Duration,
+    ) -> Result<Option<TokenStats>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .stats
+            .get(token_id)
+            .filter(|(stats, calculated_at)| {
+                stats.last_checked_block == *last_checked_block || calculated_at.elapsed() < max_age
+            })
+            .map(|(stats, _)| stats.clone()))
+    }
+
+    async fn calculate_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+    ) -> Result<TokenStats> {
+        let mut state = self.state.lock().unwrap();
+        let stats = token_stats(
+            token_id,
+            last_checked_block,
+            state.positive_amounts(token_id),
+        )?;
+
+        state
+            .stats
+            .insert(*token_id, (stats.clone(), Instant::now()));
+
+        Ok(stats)
+    }
+
+    async fn add_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        event: &str,
+        token_id: Option<i32>,
+        holder_addr: Option<&str>,
+        min_amount: Option<&str>,
+    ) -> Result<Webhook> {
+        let mut state = self.state.lock().unwrap();
+        let webhook = Webhook {
+            id: state.next_id("webhook"),
+            url: url.to_string(),
+            event: event.to_string(),
+            token_id,
+            holder_addr: holder_addr.map(normalize_addr),
+            min_amount: min_amount
+                .map(|amount| Ok::<_, anyhow::Error>(amount.parse::<Amount>()?.to_string()))
+                .transpose()?,
+        };
+
+        state
+            .webhooks
+            .insert(webhook.id, (webhook.clone(), secret.to_string()));
+
+        Ok(webhook)
+    }
+
+    async fn webhook_by_id(&self, webhook_id: &i32) -> Result<Option<Webhook>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .webhooks
+            .get(webhook_id)
+            .map(|(webhook, _)| webhook.clone()))
+    }
+
+    async fn all_webhook(&self, number: i64, size: i64) -> Result<Vec<Webhook>> {
+        let state = self.state.lock().unwrap();
+        let webhooks = state
+            .webhooks
+            .values()
+            .map(|(webhook, _)| webhook.clone())
+            .collect();
+
+        Ok(page(webhooks, number, size))
+    }
+
+    async fn all_webhook_count(&self) -> Result<i64> {
+        Ok(self.state.lock().unwrap().webhooks.len() as i64)
+    }
+
+    async fn delete_webhook(&self, webhook_id: &i32) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .deliveries
+            .retain(|_, (delivery, _)| delivery.webhook_id != *webhook_id);
+
+        Ok(state.webhooks.remove(webhook_id).is_some())
+    }
+
+    async fn matching_webhooks(
+        &self,
+        token_id: &i32,
+        from_holder_addr: &str,
+        to_holder_addr: &str,
+        amount: &str,
+    ) -> Result<Vec<Webhook>> {
+        let holder_addrs = [
+            normalize_addr(from_holder_addr),
+            normalize_addr(to_holder_addr),
+        ];
+        let amount = amount.parse::<Amount>()?;
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .webhooks
+            .values()
+            .map(|(webhook, _)| webhook)
+            .filter(|webhook| webhook.token_id.is_none_or(|id| id == *token_id))
+            .filter(|webhook| {
+                webhook
+                    .holder_addr
+                    .as_ref()
+                    .is_none_or(|addr| holder_addrs.contains(addr))
+            })
+            .filter(|webhook| {
+                webhook.event != "transfer"
+                    || webhook.min_amount.as_ref().is_none_or(|min_amount| {
+                        min_amount.parse::<Amount>().is_ok_and(|min| min <= amount)
+                    })
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn add_webhook_delivery(&self, webhook_id: &i32, payload: &str) -> Result<i32> {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.webhooks.contains_key(webhook_id) {
+            return Err(anyhow!("Webhook {webhook_id} not found"));
+        }
+
+        let delivery_id = state.next_id("webhook_delivery");
+        let delivery = WebhookDelivery {
+            id: delivery_id,
+            webhook_id: *webhook_id,
+            payload: payload.to_string(),
+            attempts: 0,
+            status_code: None,
+            delivered: false,
+            error: None,
+            created_at: now_text(),
+        };
+
+        state
+            .deliveries
+            .insert(delivery_id, (delivery, Instant::now()));
+
+        Ok(delivery_id)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        max_attempts: &i32,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueWebhookDelivery>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let State {
+            webhooks,
+            deliveries,
+            ..
+        } = &mut *state;
+
+        let mut due: Vec<(&mut WebhookDelivery, &mut Instant)> = deliveries
+            .values_mut()
+            .filter(|(delivery, next_attempt_at)| {
+                !delivery.delivered && delivery.attempts < *max_attempts && *next_attempt_at <= now
+            })
+            .map(|(delivery, next_attempt_at)| (delivery, next_attempt_at))
+            .collect();
+        due.sort_by_key(|(_, next_attempt_at)| **next_attempt_at);
+
+        Ok(due
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .filter_map(|(delivery, next_attempt_at)| {
+                let (webhook, secret) = webhooks.get(&delivery.webhook_id)?;
+                *next_attempt_at = now + lease;
+
+                Some(DueWebhookDelivery {
+                    delivery_id: delivery.id,
+                    webhook_id: delivery.webhook_id,
+                    url: webhook.url.clone(),
+                    secret: secret.clone(),
+                    payload: delivery.payload.clone(),
+                    attempts: delivery.attempts,
+                })
+            })
+            .collect())
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery_id: &i32,
+        attempts: &i32,
+        status_code: Option<i32>,
+        delivered: bool,
+        error: Option<&str>,
+        retry_in: Duration,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some((delivery, next_attempt_at)) = state.deliveries.get_mut(delivery_id) {
+            delivery.attempts = *attempts;
+            delivery.status_code = status_code;
+            delivery.delivered = delivered;
+            delivery.error = error.map(|error| error.to_string());
+            *next_attempt_at = Instant::now() + retry_in;
+        }
+
+        Ok(())
+    }
+
+    async fn all_webhook_delivery(
+        &self,
+        webhook_id: &i32,
+        number: i64,
+        size: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let state = self.state.lock().unwrap();
+        let deliveries = state
+            .deliveries
+            .values()
+            .rev()
+            .map(|(delivery, _)| delivery)
+            .filter(|delivery| delivery.webhook_id == *webhook_id)
+            .cloned()
+            .collect();
+
+        Ok(page(deliveries, number, size))
+    }
+
+    async fn all_webhook_delivery_count(&self, webhook_id: &i32) -> Result<i64> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .deliveries
+            .values()
+            .filter(|(delivery, _)| delivery.webhook_id == *webhook_id)
+            .count() as i64)
+    }
+
+    async fn add_api_key(
+        &self,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        rate_limit: &i32,
+    ) -> Result<Option<ApiKey>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.api_keys.values().any(|(_, hash)| hash == key_hash) {
+            return Ok(None);
+        }
+
+        let api_key = ApiKey {
+            id: state.next_id("api_key"),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            rate_limit: *rate_limit,
+            created_at: now_text(),
+            key: None,
+        };
+
+        state
+            .api_keys
+            .insert(api_key.id, (api_key.clone(), key_hash.to_string()));
+
+        Ok(Some(api_key))
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .api_keys
+            .values()
+            .find(|(_, hash)| hash == key_hash)
+            .map(|(api_key, _)| api_key.clone()))
+    }
+
+    async fn all_api_key(&self, number: i64, size: i64) -> Result<Vec<ApiKey>> {
+        let state = self.state.lock().unwrap();
+        let api_keys = state
+            .api_keys
+            .values()
+            .map(|(api_key, _)| api_key.clone())
+            .collect();
+
+        Ok(page(api_keys, number, size))
+    }
+
+    async fn all_api_key_count(&self) -> Result<i64> {
+        Ok(self.state.lock().unwrap().api_keys.len() as i64)
+    }
+
+    async fn delete_api_key(&self, api_key_id: &i32) -> Result<bool> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .api_keys
+            .remove(api_key_id)
+            .is_some())
+    }
+
+    async fn add_job(
+        &self,
+        contract_addr: &str,
+        start_block: Option<i64>,
+        idempotency_key: Option<&str>,
+    ) -> Result<NewJob> {
+        let contract_addr = normalize_addr(contract_addr);
+        let mut state = self.state.lock().unwrap();
+
+        if idempotency_key.is_some()
+            && state
+                .jobs
+                .values()
+                .any(|(_, key)| key.as_deref() == idempotency_key)
+        {
+            return Ok(NewJob::IdempotencyKeyUsed);
+        }
+
+        if state.jobs.values().any(|(job, _)| {
+            job.contract_addr == contract_addr
+                && (job.state == JobState::Pending.as_str()
+                    || job.state == JobState::Running.as_str())
+        }) {
+            return Ok(NewJob::ContractQueued);
+        }
+
+        let created_at = now_text();
+        let job = Job {
+            id: state.next_id("job"),
+            contract_addr,
+            start_block,
+            state: JobState::Pending.as_str().to_string(),
+            token_id: None,
+            error: None,
+            created_at: created_at.clone(),
+            updated_at: created_at,
+        };
+
+        state.jobs.insert(
+            job.id,
+            (job.clone(), idempotency_key.map(|key| key.to_string())),
+        );
+
+        Ok(NewJob::Added(job))
+    }
+
+    async fn job_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<Job>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .jobs
+            .values()
+            .find(|(_, key)| key.as_deref() == Some(idempotency_key))
+            .map(|(job, _)| job.clone()))
+    }
+
+    async fn open_job_by_contract(&self, contract_addr: &str) -> Result<Option<Job>> {
+        let contract_addr = normalize_addr(contract_addr);
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .jobs
+            .values()
+            .map(|(job, _)| job)
+            .find(|job| {
+                job.contract_addr == contract_addr
+                    && (job.state == JobState::Pending.as_str()
+                        || job.state == JobState::Running.as_str())
+            })
+            .cloned())
+    }
+
+    async fn job_by_id(&self, job_id: &i32) -> Result<Option<Job>> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state.jobs.get(job_id).map(|(job, _)| job.clone()))
+    }
+
+    async fn claim_job(&self, worker: &str, lease: Duration) -> Result<Option<Job>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let State {
+            jobs, job_leases, ..
+        } = &mut *state;
+
+        let job = jobs.values_mut().map(|(job, _)| job).find(|job| {
+            job.state == JobState::Pending.as_str()
+                || (job.state == JobState::Running.as_str()
+                    && job_leases
+                        .get(&job.id)
+                        .is_none_or(|(_, expires_at)| *expires_at <= now))
+        });
+
+        Ok(job.map(|job| {
+            job.state = JobState::Running.as_str().to_string();
+            job.updated_at = now_text();
+            job_leases.insert(job.id, (worker.to_string(), now + lease));
+            job.clone()
+        }))
+    }
+
+    async fn finish_job(
+        &self,
+        job_id: &i32,
+        worker: &str,
+        state: JobState,
+        token_id: Option<i32>,
+        error: Option<&str>,
+    ) -> Result<bool> {
+        let mut jobs = self.state.lock().unwrap();
+
+        if jobs
+            .job_leases
+            .get(job_id)
+            .is_none_or(|(claimed_by, _)| claimed_by != worker)
+        {
+            return Ok(false);
+        }
+
+        let job = jobs.job_mut(job_id)?;
+
+        job.state = state.as_str().to_string();
+        job.token_id = token_id;
+        job.error = error.map(|error| error.to_string());
+        job.updated_at = now_text();
+
+        Ok(true)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
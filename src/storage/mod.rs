@@ -0,0 +1,411 @@
+//! Storage of tokens, holders, balances, token stats and history,
+//! onboarding jobs, webhooks and api keys
+
+mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(test)]
+mod tests;
+mod timed;
+
+pub use memory::MemoryStorage;
+#[cfg(feature = "postgres")]
+pub use postgres::PgStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+use timed::TimedStorage;
+
+use crate::{
+    model::{
+        ApiKey, Balance, DueWebhookDelivery, Filter, Holder, Job, JobState, PageCursor, Sort,
+        SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+    utils,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::types::U256;
+use std::{env, sync::Arc, time::Duration};
+
+/// Storage shared by api handlers and indexer tasks
+pub type SharedStorage = Arc<dyn Storage>;
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn all_token(&self, number: i64, size: i64, sort: &[Sort]) -> Result<Vec<Token>>;
+
+    async fn all_token_count(&self) -> Result<i64>;
+
+    async fn token_by_id(&self, token_id: &i32) -> Result<Option<Token>>;
+
+    async fn token_by_contract(&self, contract_addr: &str) -> Result<Option<Token>>;
+
+    /// Add token, returns its id
+    async fn add_token(
+        &self,
+        contract_addr: &str,
+        last_checked_block: &i64,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<i32>;
+
+    async fn update_token_metadata(
+        &self,
+        token_id: &i32,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<()>;
+
+    async fn update_token_last_checked_block(
+        &self,
+        last_checked_block: &i64,
+        token_id: &i32,
+    ) -> Result<()>;
+
+    /// Set sync state and error, head block only moves forward
+    /// and backfill rate is kept if it is not set
+    async fn update_token_sync(
+        &self,
+        token_id: &i32,
+        sync_state: SyncState,
+        head_block: &i64,
+        backfill_rate: Option<f64>,
+        sync_error: Option<&str>,
+    ) -> Result<()>;
+
+    /// Ask indexer to index token again from `start_block`
+    async fn request_reindex(&self, token_id: &i32, start_block: &i64) -> Result<()>;
+
+    /// Ids and start blocks of tokens waiting for reindex
+    async fn all_reindex_request(&self) -> Result<Vec<(i32, i64)>>;
+
+    /// Take or renew lease of token indexing by worker for `lease`.
+    /// Returns false if another worker holds unexpired lease of token
+    async fn claim_token(&self, token_id: &i32, worker: &str, lease: Duration) -> Result<bool>;
+
+    /// Give up lease of token held by worker, so that other workers can take it at once
+    async fn release_token(&self, token_id: &i32, worker: &str) -> Result<()>;
+
+    /// Clear balances of token and move its checkpoint back
+    /// to `last_checked_block` so that it is indexed again
+    async fn reset_token(&self, token_id: &i32, last_checked_block: &i64) -> Result<()>;
+
+    async fn add_or_get_holder(&self, holder_addr: &str) -> Result<i32>;
+
+    async fn holder_by_addr(&self, holder_addr: &str) -> Result<Option<Holder>>;
+
+    /// Non-zero balances joined with token and holder
+    async fn all_balance_by_filter(
+        &self,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<Balance>>;
+
+    /// Page of balances next to cursor, ordered by amount, holder and token.
+    /// Returns balances in sort order and whether more balances follow in cursor direction
+    async fn all_balance_by_cursor(
+        &self,
+        filter: &[Filter],
+        cursor: &PageCursor,
+        size: i64,
+        descending: bool,
+    ) -> Result<(Vec<Balance>, bool)>;
+
+    async fn all_balance_by_filter_count(&self, filter: &[Filter]) -> Result<i64>;
+
+    /// Move amount between holders and checkpoint of token to `last_checked_block` at once,
+    /// returns updated balances as (holder_id, amount)
+    async fn upsert_balance(
+        &self,
+        from_holder_id: &i32,
+        to_holder_id: &i32,
+        token_id: &i32,
+        amount: &str,
+        last_checked_block: &i64,
+    ) -> Result<Vec<(i32, String)>>;
+
+    /// Add net balance changes of holders as (holder_addr, signed amount)
+    /// and move checkpoint of token to `last_checked_block` at once
+    async fn apply_balance_deltas(
+        &self,
+        token_id: &i32,
+        deltas: &[(String, String)],
+        last_checked_block: &i64,
+    ) -> Result<()>;
+
+    /// Save holder count and total supply of token at block
+    async fn add_token_history(&self, token_id: &i32, block_number: &i64) -> Result<()>;
+
+    async fn all_token_history(
+        &self,
+        token_id: &i32,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<TokenHistory>>;
+
+    async fn all_token_history_count(&self, token_id: &i32, filter: &[Filter]) -> Result<i64>;
+
+    /// Cached stats of token if calculated for the checkpoint or not older than `max_age`
+    async fn fresh_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+        max_age: Duration,
+    ) -> Result<Option<TokenStats>>;
+
+    /// Calculate holder concentration metrics from non-zero balances
+    /// and cache them for the given checkpoint
+    async fn calculate_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+    ) -> Result<TokenStats>;
+
+    async fn add_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        event: &str,
+        token_id: Option<i32>,
+        holder_addr: Option<&str>,
+        min_amount: Option<&str>,
+    ) -> Result<Webhook>;
+
+    async fn webhook_by_id(&self, webhook_id: &i32) -> Result<Option<Webhook>>;
+
+    async fn all_webhook(&self, number: i64, size: i64) -> Result<Vec<Webhook>>;
+
+    async fn all_webhook_count(&self) -> Result<i64>;
+
+    /// Delete webhook with its delivery log, returns false if webhook is missing
+    async fn delete_webhook(&self, webhook_id: &i32) -> Result<bool>;
+
+    /// Webhooks with rules matching transfer of token between holders
+    async fn matching_webhooks(
+        &self,
+        token_id: &i32,
+        from_holder_addr: &str,
+        to_holder_addr: &str,
+        amount: &str,
+    ) -> Result<Vec<Webhook>>;
+
+    async fn add_webhook_delivery(&self, webhook_id: &i32, payload: &str) -> Result<i32>;
+
+    /// Claim due undelivered payloads with attempts left,
+    /// claimed deliveries are skipped by other workers until `lease` expires
+    async fn claim_webhook_deliveries(
+        &self,
+        max_attempts: &i32,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueWebhookDelivery>>;
+
+    /// Log delivery attempt, failed delivery is retried after `retry_in`
+    async fn update_webhook_delivery(
+        &self,
+        delivery_id: &i32,
+        attempts: &i32,
+        status_code: Option<i32>,
+        delivered: bool,
+        error: Option<&str>,
+        retry_in: Duration,
+    ) -> Result<()>;
+
+    /// Delivery log of webhook, newest first
+    async fn all_webhook_delivery(
+        &self,
+        webhook_id: &i32,
+        number: i64,
+        size: i64,
+    ) -> Result<Vec<WebhookDelivery>>;
+
+    async fn all_webhook_delivery_count(&self, webhook_id: &i32) -> Result<i64>;
+
+    /// Add api key by hash of key, returns `None` if key already exists
+    async fn add_api_key(
+        &self,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        rate_limit: &i32,
+    ) -> Result<Option<ApiKey>>;
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>>;
+
+    async fn all_api_key(&self, number: i64, size: i64) -> Result<Vec<ApiKey>>;
+
+    async fn all_api_key_count(&self) -> Result<i64>;
+
+    /// Revoke api key, returns false if key is missing
+    async fn delete_api_key(&self, api_key_id: &i32) -> Result<bool>;
+
+    /// Add pending onboarding job of token, unless job with the same
+    /// idempotency key or open job of the same contract exists
+    async fn add_job(
+        &self,
+        contract_addr: &str,
+        start_block: Option<i64>,
+        idempotency_key: Option<&str>,
+    ) -> Result<NewJob>;
+
+    async fn job_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<Job>>;
+
+    /// Pending or running job of contract
+    async fn open_job_by_contract(&self, contract_addr: &str) -> Result<Option<Job>>;
+
+    async fn job_by_id(&self, job_id: &i32) -> Result<Option<Job>>;
+
+    /// Mark oldest pending job, or running job with expired lease, as running
+    /// by worker for `lease` and return it. Jobs of live workers are skipped
+    async fn claim_job(&self, worker: &str, lease: Duration) -> Result<Option<Job>>;
+
+    /// Set final state of job claimed by worker with its token or error.
+    /// Returns false if job was claimed by another worker after lease expired
+    async fn finish_job(
+        &self,
+        job_id: &i32,
+        worker: &str,
+        state: JobState,
+        token_id: Option<i32>,
+        error: Option<&str>,
+    ) -> Result<bool>;
+
+    /// Check that storage responds
+    async fn ping(&self) -> Result<()>;
+}
+
+/// Outcome of adding onboarding job
+#[derive(Debug)]
+pub enum NewJob {
+    Added(Job),
+    /// Job with the same idempotency key exists
+    IdempotencyKeyUsed,
+    /// Pending or running job of the same contract exists
+    ContractQueued,
+}
+
+/// Error of unique constraint, such as one open job per contract
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    matches!(err, sqlx::Error::Database(err) if err.is_unique_violation())
+}
+
+/// Storage by `STORAGE_URL` env: `memory://`, `sqlite://path` or `postgres://...`.
+/// Postgres database from `DATABASE_URL` env is used if it is not set
+pub async fn from_env() -> Result<SharedStorage> {
+    let url = match env::var("STORAGE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            env::var("DATABASE_URL").map_err(|_| anyhow!("Missing STORAGE_URL or DATABASE_URL"))?
+        }
+    };
+
+    let storage: SharedStorage = match url.split_once(':').map(|(scheme, _)| scheme) {
+        Some("memory") => Arc::new(MemoryStorage::default()),
+        #[cfg(feature = "sqlite")]
+        Some("sqlite") => Arc::new(SqliteStorage::connect(&url).await?),
+        #[cfg(feature = "postgres")]
+        Some("postgres" | "postgresql") => Arc::new(PgStorage::connect(&url).await?),
+        _ => return Err(anyhow!("Unsupported STORAGE_URL '{url}'")),
+    };
+
+    // Latency is recorded at storage boundary, so that every backend reports it
+    Ok(Arc::new(TimedStorage(storage)))
+}
+
+/// Token by id or by contract address
+pub async fn find_token(storage: &SharedStorage, id: &str) -> Result<Option<Token>> {
+    match id.parse::<i32>() {
+        Ok(token_id) => storage.token_by_id(&token_id).await,
+        Err(_) => storage.token_by_contract(utils::strip_hex_prefix(id)).await,
+    }
+}
+
+/// Sum of non-zero balances, error if it is over `U256::MAX`
+fn total_supply(amounts: &[U256]) -> Result<U256> {
+    amounts.iter().try_fold(U256::zero(), |total, amount| {
+        total
+            .checked_add(*amount)
+            .ok_or_else(|| anyhow!("Total supply overflow"))
+    })
+}
+
+/// Holder concentration metrics of token from non-zero balances
+fn token_stats(
+    token_id: &i32,
+    last_checked_block: &i64,
+    mut amounts: Vec<U256>,
+) -> Result<TokenStats> {
+    amounts.sort_unstable_by(|a, b| b.cmp(a));
+
+    let total_supply = total_supply(&amounts)?;
+    let holder_count = amounts.len() as i64;
+
+    let total = to_f64(&total_supply);
+    let share = |amount: &U256| {
+        if total > 0.0 {
+            to_f64(amount) / total
+        } else {
+            0.0
+        }
+    };
+    let top_share = |count: usize| amounts.iter().take(count).map(share).sum::<f64>();
+
+    // Ranks of amounts in ascending order
+    let gini = if holder_count > 0 && total > 0.0 {
+        let weighted: f64 = amounts
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(rank, amount)| (rank + 1) as f64 * to_f64(amount))
+            .sum();
+        let n = holder_count as f64;
+
+        2.0 * weighted / (n * total) - (n + 1.0) / n
+    } else {
+        0.0
+    };
+
+    // Holders are counted while balances before them are not more than half of supply
+    let mut cumulative = U256::zero();
+    let mut nakamoto = 0;
+    for amount in amounts.iter() {
+        if cumulative > total_supply - cumulative {
+            break;
+        }
+        nakamoto += 1;
+        cumulative += *amount;
+    }
+
+    Ok(TokenStats {
+        id: *token_id,
+        last_checked_block: *last_checked_block,
+        holder_count,
+        total_supply: total_supply.to_string(),
+        top10_share: top_share(10),
+        top100_share: top_share(100),
+        gini,
+        herfindahl: amounts.iter().map(|amount| share(amount).powi(2)).sum(),
+        nakamoto,
+    })
+}
+
+fn to_f64(amount: &U256) -> f64 {
+    amount.to_string().parse().unwrap_or_default()
+}
+
+/// Set lag and eta (in seconds) of token from its checkpoint and backfill rate
+fn with_progress(mut token: Token) -> Token {
+    token.lag = (token.head_block - token.last_checked_block).max(0);
+    token.eta = (token.sync_state == SyncState::Backfilling.as_str() && token.backfill_rate > 0.0)
+        .then(|| (token.lag as f64 / token.backfill_rate).ceil() as i64);
+
+    token
+}
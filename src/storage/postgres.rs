@@ -0,0 +1,422 @@
+use super::{is_unique_violation, NewJob, Storage};
+use crate::{
+    db,
+    model::{
+        ApiKey, Balance, DueWebhookDelivery, Filter, Holder, Job, JobState, PageCursor, Sort,
+        SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Storage in Postgres tables of service database
+pub struct PgStorage {
+    connection_pool: PgPool,
+}
+
+impl PgStorage {
+    /// Connect to database and apply migrations
+    pub async fn connect(url: &str) -> Result<Self> {
+        let connection_pool = db::init_db(url)
+            .await
+            .context("Error connecting to the database")?;
+
+        Ok(Self { connection_pool })
+    }
+}
+
+#[async_trait]
+impl Storage for PgStorage {
+    async fn all_token(&self, number: i64, size: i64, sort: &[Sort]) -> Result<Vec<Token>> {
+        Ok(db::all_token(&self.connection_pool, number, size, sort).await?)
+    }
+
+    async fn all_token_count(&self) -> Result<i64> {
+        Ok(db::all_token_count(&self.connection_pool).await?)
+    }
+
+    async fn token_by_id(&self, token_id: &i32) -> Result<Option<Token>> {
+        Ok(db::token_by_id(&self.connection_pool, token_id).await?)
+    }
+
+    async fn token_by_contract(&self, contract_addr: &str) -> Result<Option<Token>> {
+        Ok(db::token_by_contract(&self.connection_pool, contract_addr).await?)
+    }
+
+    async fn add_token(
+        &self,
+        contract_addr: &str,
+        last_checked_block: &i64,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<i32> {
+        Ok(db::add_token(
+            &self.connection_pool,
+            contract_addr,
+            last_checked_block,
+            symbol,
+            decimals,
+        )
+        .await?)
+    }
+
+    async fn update_token_metadata(
+        &self,
+        token_id: &i32,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<()> {
+        db::update_token_metadata(&self.connection_pool, token_id, symbol, decimals).await
+    }
+
+    async fn update_token_last_checked_block(
+        &self,
+        last_checked_block: &i64,
+        token_id: &i32,
+    ) -> Result<()> {
+        db::update_token_last_checked_block(&self.connection_pool, last_checked_block, token_id)
+            .await
+    }
+
+    async fn update_token_sync(
+        &self,
+        token_id: &i32,
+        sync_state: SyncState,
+        head_block: &i64,
+        backfill_rate: Option<f64>,
+        sync_error: Option<&str>,
+    ) -> Result<()> {
+        db::update_token_sync(
+            &self.connection_pool,
+            token_id,
+            sync_state,
+            head_block,
+            backfill_rate,
+            sync_error,
+        )
+        .await
+    }
+
+    async fn request_reindex(&self, token_id: &i32, start_block: &i64) -> Result<()> {
+        Ok(db::request_reindex(&self.connection_pool, token_id, start_block).await?)
+    }
+
+    async fn all_reindex_request(&self) -> Result<Vec<(i32, i64)>> {
+        Ok(db::all_reindex_request(&self.connection_pool).await?)
+    }
+
+    /// Stats and history of token are cleared too
+    async fn claim_token(&self, token_id: &i32, worker: &str, lease: Duration) -> Result<bool> {
+        Ok(db::claim_token(&self.connection_pool, token_id, worker, lease).await?)
+    }
+
+    async fn release_token(&self, token_id: &i32, worker: &str) -> Result<()> {
+        Ok(db::release_token(&self.connection_pool, token_id, worker).await?)
+    }
+
+    async fn reset_token(&self, token_id: &i32, last_checked_block: &i64) -> Result<()> {
+        db::reset_token(&self.connection_pool, token_id, last_checked_block).await
+    }
+
+    async fn add_or_get_holder(&self, holder_addr: &str) -> Result<i32> {
+        Ok(db::add_or_get_holder(&self.connection_pool, holder_addr).await?)
+    }
+
+    async fn holder_by_addr(&self, holder_addr: &str) -> Result<Option<Holder>> {
+        Ok(db::holder_by_addr(&self.connection_pool, holder_addr).await?)
+    }
+
+    async fn all_balance_by_filter(
+        &self,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<Balance>> {
+        Ok(db::all_balance_by_filter(&self.connection_pool, filter, number, size, sort).await?)
+    }
+
+    async fn all_balance_by_cursor(
+        &self,
+        filter: &[Filter],
+        cursor: &PageCursor,
+        size: i64,
+        descending: bool,
+    ) -> Result<(Vec<Balance>, bool)> {
+        Ok(
+            db::all_balance_by_cursor(&self.connection_pool, filter, cursor, size, descending)
+                .await?,
+        )
+    }
+
+    async fn all_balance_by_filter_count(&self, filter: &[Filter]) -> Result<i64> {
+        Ok(db::all_balance_by_filter_count(&self.connection_pool, filter).await?)
+    }
+
+    async fn upsert_balance(
+        &self,
+        from_holder_id: &i32,
+        to_holder_id: &i32,
+        token_id: &i32,
+        amount: &str,
+        last_checked_block: &i64,
+    ) -> Result<Vec<(i32, String)>> {
+        db::upsert_balance(
+            &self.connection_pool,
+            from_holder_id,
+            to_holder_id,
+            token_id,
+            amount,
+            last_checked_block,
+        )
+        .await
+    }
+
+    async fn apply_balance_deltas(
+        &self,
+        token_id: &i32,
+        deltas: &[(String, String)],
+        last_checked_block: &i64,
+    ) -> Result<()> {
+        Ok(
+            db::apply_balance_deltas(&self.connection_pool, token_id, deltas, last_checked_block)
+                .await?,
+        )
+    }
+
+    async fn add_token_history(&self, token_id: &i32, block_number: &i64) -> Result<()> {
+        db::add_token_history(&self.connection_pool, token_id, block_number).await
+    }
+
+    async fn all_token_history(
+        &self,
+        token_id: &i32,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<TokenHistory>> {
+        Ok(
+            db::all_token_history(&self.connection_pool, token_id, filter, number, size, sort)
+                .await?,
+        )
+    }
+
+    async fn all_token_history_count(&self, token_id: &i32, filter: &[Filter]) -> Result<i64> {
+        Ok(db::all_token_history_count(&self.connection_pool, token_id, filter).await?)
+    }
+
+    async fn fresh_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+        max_age: Duration,
+    ) -> Result<Option<TokenStats>> {
+        Ok(
+            db::fresh_token_stats(&self.connection_pool, token_id, last_checked_block, max_age)
+                .await?,
+        )
+    }
+
+    async fn calculate_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+    ) -> Result<TokenStats> {
+        Ok(db::calculate_token_stats(&self.connection_pool, token_id, last_checked_block).await?)
+    }
+
+    async fn add_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        event: &str,
+        token_id: Option<i32>,
+        holder_addr: Option<&str>,
+        min_amount: Option<&str>,
+    ) -> Result<Webhook> {
+        Ok(db::add_webhook(
+            &self.connection_pool,
+            url,
+            secret,
+            event,
+            token_id,
+            holder_addr,
+            min_amount,
+        )
+        .await?)
+    }
+
+    async fn webhook_by_id(&self, webhook_id: &i32) -> Result<Option<Webhook>> {
+        Ok(db::webhook_by_id(&self.connection_pool, webhook_id).await?)
+    }
+
+    async fn all_webhook(&self, number: i64, size: i64) -> Result<Vec<Webhook>> {
+        Ok(db::all_webhook(&self.connection_pool, number, size).await?)
+    }
+
+    async fn all_webhook_count(&self) -> Result<i64> {
+        Ok(db::all_webhook_count(&self.connection_pool).await?)
+    }
+
+    async fn delete_webhook(&self, webhook_id: &i32) -> Result<bool> {
+        Ok(db::delete_webhook(&self.connection_pool, webhook_id).await?)
+    }
+
+    async fn matching_webhooks(
+        &self,
+        token_id: &i32,
+        from_holder_addr: &str,
+        to_holder_addr: &str,
+        amount: &str,
+    ) -> Result<Vec<Webhook>> {
+        Ok(db::matching_webhooks(
+            &self.connection_pool,
+            token_id,
+            from_holder_addr,
+            to_holder_addr,
+            amount,
+        )
+        .await?)
+    }
+
+    async fn add_webhook_delivery(&self, webhook_id: &i32, payload: &str) -> Result<i32> {
+        Ok(db::add_webhook_delivery(&self.connection_pool, webhook_id, payload).await?)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        max_attempts: &i32,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueWebhookDelivery>> {
+        Ok(db::claim_webhook_deliveries(&self.connection_pool, max_attempts, limit, lease).await?)
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery_id: &i32,
+        attempts: &i32,
+        status_code: Option<i32>,
+        delivered: bool,
+        error: Option<&str>,
+        retry_in: Duration,
+    ) -> Result<()> {
+        db::update_webhook_delivery(
+            &self.connection_pool,
+            delivery_id,
+            attempts,
+            status_code,
+            delivered,
+            error,
+            retry_in,
+        )
+        .await
+    }
+
+    async fn all_webhook_delivery(
+        &self,
+        webhook_id: &i32,
+        number: i64,
+        size: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        Ok(db::all_webhook_delivery(&self.connection_pool, webhook_id, number, size).await?)
+    }
+
+    async fn all_webhook_delivery_count(&self, webhook_id: &i32) -> Result<i64> {
+        Ok(db::all_webhook_delivery_count(&self.connection_pool, webhook_id).await?)
+    }
+
+    async fn add_api_key(
+        &self,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        rate_limit: &i32,
+    ) -> Result<Option<ApiKey>> {
+        Ok(db::add_api_key(&self.connection_pool, key_hash, name, scopes, rate_limit).await?)
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        Ok(db::api_key_by_hash(&self.connection_pool, key_hash).await?)
+    }
+
+    async fn all_api_key(&self, number: i64, size: i64) -> Result<Vec<ApiKey>> {
+        Ok(db::all_api_key(&self.connection_pool, number, size).await?)
+    }
+
+    async fn all_api_key_count(&self) -> Result<i64> {
+        Ok(db::all_api_key_count(&self.connection_pool).await?)
+    }
+
+    async fn delete_api_key(&self, api_key_id: &i32) -> Result<bool> {
+        Ok(db::delete_api_key(&self.connection_pool, api_key_id).await?)
+    }
+
+    async fn add_job(
+        &self,
+        contract_addr: &str,
+        start_block: Option<i64>,
+        idempotency_key: Option<&str>,
+    ) -> Result<NewJob> {
+        let job = db::add_job(
+            &self.connection_pool,
+            contract_addr,
+            start_block,
+            idempotency_key,
+        )
+        .await;
+
+        match job {
+            Ok(Some(job)) => Ok(NewJob::Added(job)),
+            Ok(None) => Ok(NewJob::IdempotencyKeyUsed),
+            Err(err) if is_unique_violation(&err) => Ok(NewJob::ContractQueued),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn job_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<Job>> {
+        Ok(db::job_by_idempotency_key(&self.connection_pool, idempotency_key).await?)
+    }
+
+    async fn open_job_by_contract(&self, contract_addr: &str) -> Result<Option<Job>> {
+        Ok(db::open_job_by_contract(&self.connection_pool, contract_addr).await?)
+    }
+
+    async fn job_by_id(&self, job_id: &i32) -> Result<Option<Job>> {
+        Ok(db::job_by_id(&self.connection_pool, job_id).await?)
+    }
+
+    async fn claim_job(&self, worker: &str, lease: Duration) -> Result<Option<Job>> {
+        Ok(db::claim_job(&self.connection_pool, worker, lease).await?)
+    }
+
+    async fn finish_job(
+        &self,
+        job_id: &i32,
+        worker: &str,
+        state: JobState,
+        token_id: Option<i32>,
+        error: Option<&str>,
+    ) -> Result<bool> {
+        Ok(db::finish_job(
+            &self.connection_pool,
+            job_id,
+            worker,
+            state,
+            token_id,
+            error,
+        )
+        .await?)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1115 @@
+use super::{is_unique_violation, token_stats, total_supply, with_progress, NewJob, Storage};
+use crate::{
+    amount::Amount,
+    model::{
+        ApiKey, Balance, Column, DueWebhookDelivery, Filter, Holder, Job, JobState, Operator,
+        PageCursor, Sort, SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+    utils,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::types::U256;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    QueryBuilder, Sqlite, SqliteConnection, SqlitePool,
+};
+use std::{str::FromStr, time::Duration};
+
+/// Digits of zero padded amount, as many as `NUMERIC(78, 0)` of Postgres can hold
+const AMOUNT_DIGITS: usize = 78;
+
+/// Token columns, lag and eta are set after query
+const TOKEN_COLUMNS: &str = "token.token_id, token.contract_addr,
+    token.last_checked_block, token.symbol, token.decimals,
+    token.sync_state, token.head_block, 0 AS lag,
+    token.backfill_rate, NULL AS eta, token.sync_error";
+
+const BALANCE_COLUMNS: &str = "balance.holder_id || '_' || balance.token_id AS id,
+    balance.amount, holder.holder_id, holder.holder_addr";
+
+const TOKEN_STATS_COLUMNS: &str = "token_id, last_checked_block, holder_count, total_supply,
+    top10_share, top100_share, gini, herfindahl, nakamoto";
+
+const WEBHOOK_COLUMNS: &str = "webhook_id, url, event, token_id, holder_addr, min_amount";
+
+const WEBHOOK_DELIVERY_COLUMNS: &str = "delivery_id, webhook_id, payload, attempts,
+    status_code, delivered, error, created_at";
+
+const API_KEY_COLUMNS: &str = "api_key_id, name, scopes, rate_limit, created_at";
+
+const JOB_COLUMNS: &str = "job_id, contract_addr, start_block,
+    state, token_id, error, created_at, updated_at";
+
+/// Seconds in julian day of `julianday('now')`
+const DAY_SECS: f64 = 86400.0;
+
+/// Api key row with scopes joined by comma
+type ApiKeyRow = (i32, String, String, i32, String);
+
+/// Storage in SQLite file, writes are serialized by single connection
+pub struct SqliteStorage {
+    connection_pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Open or create database file and apply migrations
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+
+        let connection_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        sqlx::migrate!("./migrations_sqlite")
+            .run(&connection_pool)
+            .await?;
+
+        Ok(Self { connection_pool })
+    }
+}
+
+/// Lowercase address without `0x` prefix, as stored
+fn normalize_addr(addr: &str) -> String {
+    utils::strip_hex_prefix(addr).to_lowercase()
+}
+
+fn encode_amount(amount: &Amount) -> String {
+    let sign = if amount.is_negative() { "-" } else { "" };
+    format!("{sign}{:0>AMOUNT_DIGITS$}", amount.magnitude().to_string())
+}
+
+/// Zero padded digits of filter value, values are validated as digits
+fn encode_amount_param(value: &str) -> String {
+    format!("{:0>AMOUNT_DIGITS$}", value.trim_start_matches('0'))
+}
+
+fn decode_amount(amount: &str) -> String {
+    let (sign, digits) = match amount.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", amount),
+    };
+
+    match digits.trim_start_matches('0') {
+        "" => "0".to_string(),
+        digits => format!("{sign}{digits}"),
+    }
+}
+
+fn push_value(builder: &mut QueryBuilder<Sqlite>, column: Column, value: &str) {
+    match column {
+        Column::ContractAddr | Column::HolderAddr => {
+            builder.push_bind(normalize_addr(value));
+        }
+        Column::Amount => {
+            builder.push_bind(encode_amount_param(value));
+        }
+        Column::BlockNumber | Column::TokenId => {
+            builder.push_bind(value.parse::<i64>().unwrap_or_default());
+        }
+        Column::Symbol => {
+            builder.push_bind(value.to_string());
+        }
+    }
+}
+
+/// Append ` AND column operator values` for every filter
+fn push_filter(builder: &mut QueryBuilder<Sqlite>, filter: &[Filter]) {
+    for Filter {
+        column,
+        operator,
+        values,
+    } in filter.iter()
+    {
+        builder
+            .push(" AND ")
+            .push(column.sql())
+            .push(operator.sql());
+
+        if *operator == Operator::In {
+            builder.push("(");
+
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    builder.push(", ");
+                }
+                push_value(builder, *column, value);
+            }
+
+            builder.push(")");
+        } else {
+            push_value(builder, *column, &values[0]);
+        }
+    }
+}
+
+/// Append ` ORDER BY` clause
+fn push_sort(builder: &mut QueryBuilder<Sqlite>, sort: &[Sort]) {
+    for (i, Sort { column, descending }) in sort.iter().enumerate() {
+        builder
+            .push(if i == 0 { " ORDER BY " } else { ", " })
+            .push(column.sql())
+            .push(if *descending { " DESC" } else { " ASC" });
+    }
+}
+
+/// Append ` LIMIT OFFSET` clause for page
+fn push_page(builder: &mut QueryBuilder<Sqlite>, number: i64, size: i64) {
+    builder
+        .push(" LIMIT ")
+        .push_bind(size)
+        .push(" OFFSET ")
+        .push_bind(number * size);
+}
+
+/// Select of non-zero balances joined with token and holder
+fn balance_select(columns: &str) -> QueryBuilder<'static, Sqlite> {
+    QueryBuilder::new(format!(
+        "SELECT {columns}
+        FROM balance
+        INNER JOIN holder ON balance.holder_id = holder.holder_id
+        INNER JOIN token ON balance.token_id = token.token_id
+        WHERE balance.amount > '{}'",
+        encode_amount(&Amount::default())
+    ))
+}
+
+fn api_key_from_row((id, name, scopes, rate_limit, created_at): ApiKeyRow) -> ApiKey {
+    ApiKey {
+        id,
+        name,
+        scopes: scopes.split(',').map(|scope| scope.to_string()).collect(),
+        rate_limit,
+        created_at,
+        key: None,
+    }
+}
+
+fn decode_webhook(mut webhook: Webhook) -> Webhook {
+    webhook.min_amount = webhook.min_amount.as_deref().map(decode_amount);
+    webhook
+}
+
+fn decode_balance(mut balance: Balance) -> Balance {
+    balance.amount = decode_amount(&balance.amount);
+    balance.token = with_progress(balance.token);
+    balance
+}
+
+/// Add signed amount to balance, returns new balance
+async fn add_balance(
+    connection: &mut SqliteConnection,
+    holder_id: &i32,
+    token_id: &i32,
+    delta: Amount,
+) -> Result<Amount> {
+    let current: Option<String> =
+        sqlx::query_scalar("SELECT amount FROM balance WHERE holder_id = ? AND token_id = ?")
+            .bind(holder_id)
+            .bind(token_id)
+            .fetch_optional(&mut *connection)
+            .await?;
+
+    let current = match current {
+        Some(current) => decode_amount(&current).parse()?,
+        None => Amount::default(),
+    };
+    let balance = current
+        .checked_add(delta)
+        .ok_or_else(|| anyhow!("Balance overflow"))?;
+
+    sqlx::query(
+        "INSERT INTO balance (holder_id, token_id, amount) VALUES (?, ?, ?)
+        ON CONFLICT (holder_id, token_id) DO UPDATE SET amount = excluded.amount",
+    )
+    .bind(holder_id)
+    .bind(token_id)
+    .bind(encode_amount(&balance))
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(balance)
+}
+
+/// Non-zero balances of token
+async fn positive_amounts(connection_pool: &SqlitePool, token_id: &i32) -> Result<Vec<U256>> {
+    let amounts: Vec<String> =
+        sqlx::query_scalar("SELECT amount FROM balance WHERE token_id = ? AND amount > ?")
+            .bind(token_id)
+            .bind(encode_amount(&Amount::default()))
+            .fetch_all(connection_pool)
+            .await?;
+
+    amounts
+        .iter()
+        .map(|amount| Ok(U256::from_dec_str(&decode_amount(amount))?))
+        .collect()
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn all_token(&self, number: i64, size: i64, sort: &[Sort]) -> Result<Vec<Token>> {
+        let mut builder = QueryBuilder::new(format!("SELECT {TOKEN_COLUMNS} FROM token"));
+
+        push_sort(&mut builder, sort);
+        push_page(&mut builder, number, size);
+
+        let tokens = builder
+            .build_query_as::<Token>()
+            .fetch_all(&self.connection_pool)
+            .await?;
+
+        Ok(tokens.into_iter().map(with_progress).collect())
+    }
+
+    async fn all_token_count(&self) -> Result<i64> {
+        Ok(sqlx::query_scalar("SELECT COUNT(*) FROM token")
+            .fetch_one(&self.connection_pool)
+            .await?)
+    }
+
+    async fn token_by_id(&self, token_id: &i32) -> Result<Option<Token>> {
+        let token = sqlx::query_as::<_, Token>(&format!(
+            "SELECT {TOKEN_COLUMNS} FROM token WHERE token_id = ?"
+        ))
+        .bind(token_id)
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(token.map(with_progress))
+    }
+
+    async fn token_by_contract(&self, contract_addr: &str) -> Result<Option<Token>> {
+        let token = sqlx::query_as::<_, Token>(&format!(
+            "SELECT {TOKEN_COLUMNS} FROM token WHERE contract_addr = ?"
+        ))
+        .bind(normalize_addr(contract_addr))
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(token.map(with_progress))
+    }
+
+    async fn add_token(
+        &self,
+        contract_addr: &str,
+        last_checked_block: &i64,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<i32> {
+        Ok(sqlx::query_scalar(
+            "INSERT INTO token (contract_addr, last_checked_block, symbol, decimals)
+            VALUES (?, ?, ?, ?)
+            RETURNING token_id",
+        )
+        .bind(normalize_addr(contract_addr))
+        .bind(last_checked_block)
+        .bind(symbol)
+        .bind(decimals)
+        .fetch_one(&self.connection_pool)
+        .await?)
+    }
+
+    async fn update_token_metadata(
+        &self,
+        token_id: &i32,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<()> {
+        sqlx::query("UPDATE token SET symbol = ?, decimals = ? WHERE token_id = ?")
+            .bind(symbol)
+            .bind(decimals)
+            .bind(token_id)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_token_last_checked_block(
+        &self,
+        last_checked_block: &i64,
+        token_id: &i32,
+    ) -> Result<()> {
+        sqlx::query("UPDATE token SET last_checked_block = ? WHERE token_id = ?")
+            .bind(last_checked_block)
+            .bind(token_id)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_token_sync(
+        &self,
+        token_id: &i32,
+        sync_state: SyncState,
+        head_block: &i64,
+        backfill_rate: Option<f64>,
+        sync_error: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE token
+                SET sync_state = ?,
+                    head_block = MAX(head_block, ?),
+                    backfill_rate = COALESCE(?, backfill_rate),
+                    sync_error = ?
+                WHERE token_id = ?",
+        )
+        .bind(sync_state.as_str())
+        .bind(head_block)
+        .bind(backfill_rate)
+        .bind(sync_error)
+        .bind(token_id)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn request_reindex(&self, token_id: &i32, start_block: &i64) -> Result<()> {
+        sqlx::query("UPDATE token SET reindex_block = ? WHERE token_id = ?")
+            .bind(start_block)
+            .bind(token_id)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn all_reindex_request(&self) -> Result<Vec<(i32, i64)>> {
+        Ok(sqlx::query_as(
+            "SELECT token_id, reindex_block FROM token WHERE reindex_block IS NOT NULL",
+        )
+        .fetch_all(&self.connection_pool)
+        .await?)
+    }
+
+    async fn claim_token(&self, token_id: &i32, worker: &str, lease: Duration) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE token SET owner = ?2, lease_expires_at = julianday('now') + ?3
+            WHERE token_id = ?1
+                AND (owner IS NULL OR owner = ?2 OR lease_expires_at <= julianday('now'))",
+        )
+        .bind(token_id)
+        .bind(worker)
+        .bind(lease.as_secs_f64() / DAY_SECS)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn release_token(&self, token_id: &i32, worker: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE token SET owner = NULL, lease_expires_at = NULL
+            WHERE token_id = ? AND owner = ?",
+        )
+        .bind(token_id)
+        .bind(worker)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_token(&self, token_id: &i32, last_checked_block: &i64) -> Result<()> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        for table in ["balance", "token_stats", "token_history"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE token_id = ?"))
+                .bind(token_id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        sqlx::query(
+            "UPDATE token
+                SET last_checked_block = ?,
+                    sync_state = ?,
+                    backfill_rate = 0,
+                    sync_error = NULL,
+                    reindex_block = NULL
+                WHERE token_id = ?",
+        )
+        .bind(last_checked_block)
+        .bind(SyncState::Backfilling.as_str())
+        .bind(token_id)
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn add_or_get_holder(&self, holder_addr: &str) -> Result<i32> {
+        let holder_addr = normalize_addr(holder_addr);
+
+        sqlx::query("INSERT INTO holder (holder_addr) VALUES (?) ON CONFLICT DO NOTHING")
+            .bind(&holder_addr)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(
+            sqlx::query_scalar("SELECT holder_id FROM holder WHERE holder_addr = ?")
+                .bind(&holder_addr)
+                .fetch_one(&self.connection_pool)
+                .await?,
+        )
+    }
+
+    async fn holder_by_addr(&self, holder_addr: &str) -> Result<Option<Holder>> {
+        Ok(sqlx::query_as::<_, Holder>(
+            "SELECT holder_id, holder_addr FROM holder WHERE holder_addr = ?",
+        )
+        .bind(normalize_addr(holder_addr))
+        .fetch_optional(&self.connection_pool)
+        .await?)
+    }
+
+    async fn all_balance_by_filter(
+        &self,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<Balance>> {
+        let mut builder = balance_select(&format!("{BALANCE_COLUMNS}, {TOKEN_COLUMNS}"));
+
+        push_filter(&mut builder, filter);
+        push_sort(&mut builder, sort);
+        push_page(&mut builder, number, size);
+
+        let balances = builder
+            .build_query_as::<Balance>()
+            .fetch_all(&self.connection_pool)
+            .await?;
+
+        Ok(balances.into_iter().map(decode_balance).collect())
+    }
+
+    async fn all_balance_by_cursor(
+        &self,
+        filter: &[Filter],
+        cursor: &PageCursor,
+        size: i64,
+        descending: bool,
+    ) -> Result<(Vec<Balance>, bool)> {
+        let mut builder = balance_select(&format!("{BALANCE_COLUMNS}, {TOKEN_COLUMNS}"));
+
+        push_filter(&mut builder, filter);
+
+        let (position, backward) = match cursor {
+            PageCursor::After(position) => (position.as_ref(), false),
+            PageCursor::Before(position) => (Some(position), true),
+        };
+        let descending = descending != backward;
+
+        if let Some(position) = position {
+            builder
+                .push(" AND (balance.amount, balance.holder_id, balance.token_id)")
+                .push(if descending { " < (" } else { " > (" })
+                .push_bind(encode_amount_param(&position.amount))
+                .push(", ")
+                .push_bind(position.holder_id)
+                .push(", ")
+                .push_bind(position.token_id)
+                .push(")");
+        }
+
+        let order = if descending { " DESC" } else { " ASC" };
+        builder
+            .push(" ORDER BY balance.amount")
+            .push(order)
+            .push(", balance.holder_id")
+            .push(order)
+            .push(", balance.token_id")
+            .push(order)
+            .push(" LIMIT ")
+            .push_bind(size + 1);
+
+        let mut balances: Vec<Balance> = builder
+            .build_query_as::<Balance>()
+            .fetch_all(&self.connection_pool)
+            .await?
+            .into_iter()
+            .map(decode_balance)
+            .collect();
+
+        let has_more = balances.len() as i64 > size;
+        balances.truncate(size as usize);
+
+        if backward {
+            balances.reverse();
+        }
+
+        Ok((balances, has_more))
+    }
+
+    async fn all_balance_by_filter_count(&self, filter: &[Filter]) -> Result<i64> {
+        let mut builder = balance_select("COUNT(*)");
+
+        push_filter(&mut builder, filter);
+
+        Ok(builder
+            .build_query_scalar()
+            .fetch_one(&self.connection_pool)
+            .await?)
+    }
+
+    /// Amounts are added in Rust, SQLite numbers cannot hold 256 bit integers
+    async fn upsert_balance(
+        &self,
+        from_holder_id: &i32,
+        to_holder_id: &i32,
+        token_id: &i32,
+        amount: &str,
+        last_checked_block: &i64,
+    ) -> Result<Vec<(i32, String)>> {
+        let amount = amount.parse::<Amount>()?;
+        let mut transaction = self.connection_pool.begin().await?;
+        let mut balances = Vec::new();
+
+        for (holder_id, delta) in [(from_holder_id, -amount), (to_holder_id, amount)] {
+            let balance = add_balance(&mut transaction, holder_id, token_id, delta).await?;
+            balances.push((*holder_id, balance.to_string()));
+        }
+
+        sqlx::query("UPDATE token SET last_checked_block = ? WHERE token_id = ?")
+            .bind(last_checked_block)
+            .bind(token_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(balances)
+    }
+
+    async fn apply_balance_deltas(
+        &self,
+        token_id: &i32,
+        deltas: &[(String, String)],
+        last_checked_block: &i64,
+    ) -> Result<()> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        for (holder_addr, delta) in deltas {
+            let holder_addr = normalize_addr(holder_addr);
+
+            let holder_id: i32 = sqlx::query_scalar(
+                "INSERT INTO holder (holder_addr) VALUES (?)
+                ON CONFLICT (holder_addr) DO UPDATE SET holder_addr = excluded.holder_addr
+                RETURNING holder_id",
+            )
+            .bind(&holder_addr)
+            .fetch_one(&mut *transaction)
+            .await?;
+
+            add_balance(&mut transaction, &holder_id, token_id, delta.parse()?).await?;
+        }
+
+        sqlx::query("UPDATE token SET last_checked_block = ? WHERE token_id = ?")
+            .bind(last_checked_block)
+            .bind(token_id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn add_token_history(&self, token_id: &i32, block_number: &i64) -> Result<()> {
+        let amounts = positive_amounts(&self.connection_pool, token_id).await?;
+        let total_supply = total_supply(&amounts)?;
+
+        sqlx::query(
+            "INSERT INTO token_history (token_id, block_number, holder_count, total_supply)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (token_id, block_number) DO NOTHING",
+        )
+        .bind(token_id)
+        .bind(block_number)
+        .bind(amounts.len() as i64)
+        .bind(encode_amount_param(&total_supply.to_string()))
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn all_token_history(
+        &self,
+        token_id: &i32,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<TokenHistory>> {
+        let mut builder = QueryBuilder::new(
+            "SELECT token_id || '_' || block_number AS id,
+                block_number, holder_count, total_supply
+            FROM token_history
+            WHERE token_id = ",
+        );
+        builder.push_bind(*token_id);
+
+        push_filter(&mut builder, filter);
+        push_sort(&mut builder, sort);
+        push_page(&mut builder, number, size);
+
+        let history = builder
+            .build_query_as::<TokenHistory>()
+            .fetch_all(&self.connection_pool)
+            .await?;
+
+        Ok(history
+            .into_iter()
+            .map(|mut history| {
+                history.total_supply = decode_amount(&history.total_supply);
+                history
+            })
+            .collect())
+    }
+
+    async fn all_token_history_count(&self, token_id: &i32, filter: &[Filter]) -> Result<i64> {
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM token_history WHERE token_id = ");
+        builder.push_bind(*token_id);
+
+        push_filter(&mut builder, filter);
+
+        Ok(builder
+            .build_query_scalar()
+            .fetch_one(&self.connection_pool)
+            .await?)
+    }
+
+    async fn fresh_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+        max_age: Duration,
+    ) -> Result<Option<TokenStats>> {
+        let stats = sqlx::query_as::<_, TokenStats>(&format!(
+            "SELECT {TOKEN_STATS_COLUMNS} FROM token_stats
+            WHERE token_id = ?
+                AND (last_checked_block = ? OR calculated_at > julianday('now') - ?)"
+        ))
+        .bind(token_id)
+        .bind(last_checked_block)
+        .bind(max_age.as_secs_f64() / DAY_SECS)
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(stats.map(|mut stats| {
+            stats.total_supply = decode_amount(&stats.total_supply);
+            stats
+        }))
+    }
+
+    /// Metrics are calculated in Rust, SQLite numbers cannot hold 256 bit integers
+    async fn calculate_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+    ) -> Result<TokenStats> {
+        let amounts = positive_amounts(&self.connection_pool, token_id).await?;
+        let stats = token_stats(token_id, last_checked_block, amounts)?;
+
+        sqlx::query(&format!(
+            "INSERT INTO token_stats ({TOKEN_STATS_COLUMNS}, calculated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, julianday('now'))
+            ON CONFLICT (token_id) DO UPDATE SET
+                last_checked_block = excluded.last_checked_block,
+                holder_count = excluded.holder_count,
+                total_supply = excluded.total_supply,
+                top10_share = excluded.top10_share,
+                top100_share = excluded.top100_share,
+                gini = excluded.gini,
+                herfindahl = excluded.herfindahl,
+                nakamoto = excluded.nakamoto,
+                calculated_at = excluded.calculated_at"
+        ))
+        .bind(stats.id)
+        .bind(stats.last_checked_block)
+        .bind(stats.holder_count)
+        .bind(encode_amount_param(&stats.total_supply))
+        .bind(stats.top10_share)
+        .bind(stats.top100_share)
+        .bind(stats.gini)
+        .bind(stats.herfindahl)
+        .bind(stats.nakamoto)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    async fn add_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        event: &str,
+        token_id: Option<i32>,
+        holder_addr: Option<&str>,
+        min_amount: Option<&str>,
+    ) -> Result<Webhook> {
+        let webhook = sqlx::query_as::<_, Webhook>(&format!(
+            "INSERT INTO webhook (url, secret, event, token_id, holder_addr, min_amount)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING {WEBHOOK_COLUMNS}"
+        ))
+        .bind(url)
+        .bind(secret)
+        .bind(event)
+        .bind(token_id)
+        .bind(holder_addr.map(normalize_addr))
+        .bind(min_amount.map(encode_amount_param))
+        .fetch_one(&self.connection_pool)
+        .await?;
+
+        Ok(decode_webhook(webhook))
+    }
+
+    async fn webhook_by_id(&self, webhook_id: &i32) -> Result<Option<Webhook>> {
+        let webhook = sqlx::query_as::<_, Webhook>(&format!(
+            "SELECT {WEBHOOK_COLUMNS} FROM webhook WHERE webhook_id = ?"
+        ))
+        .bind(webhook_id)
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(webhook.map(decode_webhook))
+    }
+
+    async fn all_webhook(&self, number: i64, size: i64) -> Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as::<_, Webhook>(&format!(
+            "SELECT {WEBHOOK_COLUMNS} FROM webhook ORDER BY webhook_id LIMIT ? OFFSET ?"
+        ))
+        .bind(size)
+        .bind(number * size)
+        .fetch_all(&self.connection_pool)
+        .await?;
+
+        Ok(webhooks.into_iter().map(decode_webhook).collect())
+    }
+
+    async fn all_webhook_count(&self) -> Result<i64> {
+        Ok(sqlx::query_scalar("SELECT COUNT(*) FROM webhook")
+            .fetch_one(&self.connection_pool)
+            .await?)
+    }
+
+    async fn delete_webhook(&self, webhook_id: &i32) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM webhook WHERE webhook_id = ?")
+            .bind(webhook_id)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn matching_webhooks(
+        &self,
+        token_id: &i32,
+        from_holder_addr: &str,
+        to_holder_addr: &str,
+        amount: &str,
+    ) -> Result<Vec<Webhook>> {
+        let webhooks = sqlx::query_as::<_, Webhook>(&format!(
+            "SELECT {WEBHOOK_COLUMNS} FROM webhook
+            WHERE (token_id IS NULL OR token_id = ?)
+                AND (holder_addr IS NULL OR holder_addr IN (?, ?))
+                AND (event <> 'transfer' OR min_amount IS NULL OR min_amount <= ?)"
+        ))
+        .bind(token_id)
+        .bind(normalize_addr(from_holder_addr))
+        .bind(normalize_addr(to_holder_addr))
+        .bind(encode_amount_param(amount))
+        .fetch_all(&self.connection_pool)
+        .await?;
+
+        Ok(webhooks.into_iter().map(decode_webhook).collect())
+    }
+
+    async fn add_webhook_delivery(&self, webhook_id: &i32, payload: &str) -> Result<i32> {
+        Ok(sqlx::query_scalar(
+            "INSERT INTO webhook_delivery (webhook_id, payload)
+            VALUES (?, ?)
+            RETURNING delivery_id",
+        )
+        .bind(webhook_id)
+        .bind(payload)
+        .fetch_one(&self.connection_pool)
+        .await?)
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        max_attempts: &i32,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueWebhookDelivery>> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        let deliveries = sqlx::query_as::<_, DueWebhookDelivery>(
+            "SELECT webhook_delivery.delivery_id, webhook_delivery.webhook_id,
+                webhook.url, webhook.secret, webhook_delivery.payload, webhook_delivery.attempts
+            FROM webhook_delivery
+            INNER JOIN webhook ON webhook.webhook_id = webhook_delivery.webhook_id
+            WHERE NOT webhook_delivery.delivered
+                AND webhook_delivery.attempts < ?
+                AND webhook_delivery.next_attempt_at <= julianday('now')
+            ORDER BY webhook_delivery.next_attempt_at
+            LIMIT ?",
+        )
+        .bind(max_attempts)
+        .bind(limit)
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        for delivery in deliveries.iter() {
+            sqlx::query(
+                "UPDATE webhook_delivery SET next_attempt_at = julianday('now') + ?
+                WHERE delivery_id = ?",
+            )
+            .bind(lease.as_secs_f64() / DAY_SECS)
+            .bind(delivery.delivery_id)
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(deliveries)
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery_id: &i32,
+        attempts: &i32,
+        status_code: Option<i32>,
+        delivered: bool,
+        error: Option<&str>,
+        retry_in: Duration,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE webhook_delivery
+                SET attempts = ?, status_code = ?, delivered = ?, error = ?,
+                    next_attempt_at = julianday('now') + ?
+                WHERE delivery_id = ?",
+        )
+        .bind(attempts)
+        .bind(status_code)
+        .bind(delivered)
+        .bind(error)
+        .bind(retry_in.as_secs_f64() / DAY_SECS)
+        .bind(delivery_id)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn all_webhook_delivery(
+        &self,
+        webhook_id: &i32,
+        number: i64,
+        size: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        Ok(sqlx::query_as::<_, WebhookDelivery>(&format!(
+            "SELECT {WEBHOOK_DELIVERY_COLUMNS} FROM webhook_delivery
+            WHERE webhook_id = ?
+            ORDER BY delivery_id DESC LIMIT ? OFFSET ?"
+        ))
+        .bind(webhook_id)
+        .bind(size)
+        .bind(number * size)
+        .fetch_all(&self.connection_pool)
+        .await?)
+    }
+
+    async fn all_webhook_delivery_count(&self, webhook_id: &i32) -> Result<i64> {
+        Ok(
+            sqlx::query_scalar("SELECT COUNT(*) FROM webhook_delivery WHERE webhook_id = ?")
+                .bind(webhook_id)
+                .fetch_one(&self.connection_pool)
+                .await?,
+        )
+    }
+
+    async fn add_api_key(
+        &self,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        rate_limit: &i32,
+    ) -> Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            "INSERT INTO api_key (key_hash, name, scopes, rate_limit)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (key_hash) DO NOTHING
+            RETURNING {API_KEY_COLUMNS}"
+        ))
+        .bind(key_hash)
+        .bind(name)
+        .bind(scopes.join(","))
+        .bind(rate_limit)
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(api_key.map(api_key_from_row))
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            "SELECT {API_KEY_COLUMNS} FROM api_key WHERE key_hash = ?"
+        ))
+        .bind(key_hash)
+        .fetch_optional(&self.connection_pool)
+        .await?;
+
+        Ok(api_key.map(api_key_from_row))
+    }
+
+    async fn all_api_key(&self, number: i64, size: i64) -> Result<Vec<ApiKey>> {
+        let api_keys = sqlx::query_as::<_, ApiKeyRow>(&format!(
+            "SELECT {API_KEY_COLUMNS} FROM api_key ORDER BY api_key_id LIMIT ? OFFSET ?"
+        ))
+        .bind(size)
+        .bind(number * size)
+        .fetch_all(&self.connection_pool)
+        .await?;
+
+        Ok(api_keys.into_iter().map(api_key_from_row).collect())
+    }
+
+    async fn all_api_key_count(&self) -> Result<i64> {
+        Ok(sqlx::query_scalar("SELECT COUNT(*) FROM api_key")
+            .fetch_one(&self.connection_pool)
+            .await?)
+    }
+
+    async fn delete_api_key(&self, api_key_id: &i32) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_key WHERE api_key_id = ?")
+            .bind(api_key_id)
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn add_job(
+        &self,
+        contract_addr: &str,
+        start_block: Option<i64>,
+        idempotency_key: Option<&str>,
+    ) -> Result<NewJob> {
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO job (contract_addr, start_block, idempotency_key)
+            VALUES (?, ?, ?)
+            ON CONFLICT (idempotency_key) DO NOTHING
+            RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(normalize_addr(contract_addr))
+        .bind(start_block)
+        .bind(idempotency_key)
+        .fetch_optional(&self.connection_pool)
+        .await;
+
+        match job {
+            Ok(Some(job)) => Ok(NewJob::Added(job)),
+            Ok(None) => Ok(NewJob::IdempotencyKeyUsed),
+            Err(err) if is_unique_violation(&err) => Ok(NewJob::ContractQueued),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn job_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<Job>> {
+        Ok(sqlx::query_as::<_, Job>(&format!(
+            "SELECT {JOB_COLUMNS} FROM job WHERE idempotency_key = ?"
+        ))
+        .bind(idempotency_key)
+        .fetch_optional(&self.connection_pool)
+        .await?)
+    }
+
+    async fn open_job_by_contract(&self, contract_addr: &str) -> Result<Option<Job>> {
+        Ok(sqlx::query_as::<_, Job>(&format!(
+            "SELECT {JOB_COLUMNS} FROM job
+            WHERE contract_addr = ? AND state IN (?, ?)
+            ORDER BY job_id
+            LIMIT 1"
+        ))
+        .bind(normalize_addr(contract_addr))
+        .bind(JobState::Pending.as_str())
+        .bind(JobState::Running.as_str())
+        .fetch_optional(&self.connection_pool)
+        .await?)
+    }
+
+    async fn job_by_id(&self, job_id: &i32) -> Result<Option<Job>> {
+        Ok(
+            sqlx::query_as::<_, Job>(&format!("SELECT {JOB_COLUMNS} FROM job WHERE job_id = ?"))
+                .bind(job_id)
+                .fetch_optional(&self.connection_pool)
+                .await?,
+        )
+    }
+
+    async fn claim_job(&self, worker: &str, lease: Duration) -> Result<Option<Job>> {
+        Ok(sqlx::query_as::<_, Job>(&format!(
+            "UPDATE job SET state = ?1, claimed_by = ?3,
+                lease_expires_at = julianday('now') + ?4, updated_at = datetime('now')
+            WHERE job_id = (
+                SELECT job_id FROM job
+                WHERE state = ?2 OR (state = ?1 AND lease_expires_at <= julianday('now'))
+                ORDER BY job_id
+                LIMIT 1
+            )
+            RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(JobState::Running.as_str())
+        .bind(JobState::Pending.as_str())
+        .bind(worker)
+        .bind(lease.as_secs_f64() / DAY_SECS)
+        .fetch_optional(&self.connection_pool)
+        .await?)
+    }
+
+    async fn finish_job(
+        &self,
+        job_id: &i32,
+        worker: &str,
+        state: JobState,
+        token_id: Option<i32>,
+        error: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE job SET state = ?, token_id = ?, error = ?, updated_at = datetime('now')
+            WHERE job_id = ? AND claimed_by = ?",
+        )
+        .bind(state.as_str())
+        .bind(token_id)
+        .bind(error)
+        .bind(job_id)
+        .bind(worker)
+        .execute(&self.connection_pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.connection_pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,668 @@
+use super::{find_token, MemoryStorage, NewJob, SharedStorage};
+use crate::model::{Balance, Column, Cursor, Filter, JobState, Operator, PageCursor, Sort};
+use ethers::types::U256;
+use std::{sync::Arc, time::Duration};
+
+/// Zero address, mints are transfers from it
+const MINT: &str = "0000000000000000000000000000000000000000";
+
+fn holder_addr(n: u8) -> String {
+    format!("{:040x}", n)
+}
+
+/// Storages of every enabled backend
+async fn storages() -> Vec<(&'static str, SharedStorage)> {
+    vec![
+        (
+            "memory",
+            Arc::new(MemoryStorage::default()) as SharedStorage,
+        ),
+        #[cfg(feature = "sqlite")]
+        (
+            "sqlite",
+            Arc::new(
+                super::SqliteStorage::connect("sqlite::memory:")
+                    .await
+                    .unwrap(),
+            ),
+        ),
+    ]
+}
+
+/// Two tokens, first one has holders 1..=5 with amounts 10, 20, 20, 40, 50,
+/// second one has holders 1 and 2 with amounts 30 and 5
+async fn seed(storage: &SharedStorage) -> (i32, i32) {
+    let first = storage
+        .add_token(&"aa".repeat(20), &0, "AAA", &18)
+        .await
+        .unwrap();
+    let second = storage
+        .add_token(&"bb".repeat(20), &0, "BBB", &6)
+        .await
+        .unwrap();
+
+    let mint = |amounts: &[(u8, i64)]| {
+        let total: i64 = amounts.iter().map(|(_, amount)| amount).sum();
+        let mut deltas: Vec<(String, String)> = amounts
+            .iter()
+            .map(|(n, amount)| (holder_addr(*n), amount.to_string()))
+            .collect();
+        deltas.push((MINT.to_string(), (-total).to_string()));
+        deltas
+    };
+
+    storage
+        .apply_balance_deltas(
+            &first,
+            &mint(&[(1, 10), (2, 20), (3, 20), (4, 40), (5, 50)]),
+            &100,
+        )
+        .await
+        .unwrap();
+    storage
+        .apply_balance_deltas(&second, &mint(&[(1, 30), (2, 5)]), &100)
+        .await
+        .unwrap();
+
+    (first, second)
+}
+
+fn amounts(balances: &[Balance]) -> Vec<&str> {
+    balances
+        .iter()
+        .map(|balance| balance.amount.as_str())
+        .collect()
+}
+
+fn filter(column: Column, operator: Operator, values: &[&str]) -> Filter {
+    Filter {
+        column,
+        operator,
+        values: values.iter().map(|value| value.to_string()).collect(),
+    }
+}
+
+#[tokio::test]
+async fn cursor_pages_follow_each_other_in_both_directions() {
+    for (name, storage) in storages().await {
+        let (first, _) = seed(&storage).await;
+        let token = [filter(
+            Column::ContractAddr,
+            Operator::In,
+            &[&"aa".repeat(20)],
+        )];
+
+        let (page1, more) = storage
+            .all_balance_by_cursor(&token, &PageCursor::After(None), 2, true)
+            .await
+            .unwrap();
+        assert_eq!(amounts(&page1), ["50", "40"], "{name}");
+        assert!(more, "{name}");
+
+        let after = PageCursor::After(Some(Cursor::of(page1.last().unwrap())));
+        let (page2, more) = storage
+            .all_balance_by_cursor(&token, &after, 2, true)
+            .await
+            .unwrap();
+        assert_eq!(amounts(&page2), ["20", "20"], "{name}");
+        assert!(more, "{name}");
+        // Equal amounts are ordered by holder
+        assert!(page2[0].holder.id > page2[1].holder.id, "{name}");
+
+        let after = PageCursor::After(Some(Cursor::of(page2.last().unwrap())));
+        let (page3, more) = storage
+            .all_balance_by_cursor(&token, &after, 2, true)
+            .await
+            .unwrap();
+        assert_eq!(amounts(&page3), ["10"], "{name}");
+        assert!(!more, "{name}");
+        assert!(page3.iter().all(|balance| balance.token.id == first));
+
+        // Previous page keeps sort order
+        let before = PageCursor::Before(Cursor::of(&page3[0]));
+        let (previous, more) = storage
+            .all_balance_by_cursor(&token, &before, 2, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            previous
+                .iter()
+                .map(|balance| &balance.id)
+                .collect::<Vec<_>>(),
+            page2.iter().map(|balance| &balance.id).collect::<Vec<_>>(),
+            "{name}"
+        );
+        assert!(more, "{name}");
+
+        let (ascending, more) = storage
+            .all_balance_by_cursor(&token, &PageCursor::After(None), 3, false)
+            .await
+            .unwrap();
+        assert_eq!(amounts(&ascending), ["10", "20", "20"], "{name}");
+        assert!(more, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn cursor_ties_of_holder_are_broken_by_token() {
+    for (name, storage) in storages().await {
+        let (first, second) = seed(&storage).await;
+        let holder = [filter(Column::HolderAddr, Operator::In, &[&holder_addr(1)])];
+
+        storage
+            .apply_balance_deltas(
+                &second,
+                &[
+                    (holder_addr(1), "-20".to_string()),
+                    (holder_addr(2), "20".to_string()),
+                ],
+                &101,
+            )
+            .await
+            .unwrap();
+
+        // Holder 1 has 10 of both tokens now
+        let (page, more) = storage
+            .all_balance_by_cursor(&holder, &PageCursor::After(None), 1, true)
+            .await
+            .unwrap();
+        assert_eq!(page[0].token.id, second, "{name}");
+        assert!(more, "{name}");
+
+        let after = PageCursor::After(Some(Cursor::of(&page[0])));
+        let (page, more) = storage
+            .all_balance_by_cursor(&holder, &after, 1, true)
+            .await
+            .unwrap();
+        assert_eq!(page[0].token.id, first, "{name}");
+        assert!(!more, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn filters_select_matching_non_zero_balances() {
+    for (name, storage) in storages().await {
+        seed(&storage).await;
+
+        let holders = [filter(
+            Column::HolderAddr,
+            Operator::In,
+            &[
+                &format!("0x{}", holder_addr(1).to_uppercase()),
+                &holder_addr(2),
+            ],
+        )];
+        assert_eq!(
+            storage.all_balance_by_filter_count(&holders).await.unwrap(),
+            4,
+            "{name}"
+        );
+
+        let compared = [
+            filter(Column::Amount, Operator::Gte, &["20"]),
+            filter(Column::Amount, Operator::Lt, &["50"]),
+        ];
+        let balances = storage
+            .all_balance_by_filter(
+                &compared,
+                0,
+                10,
+                &[Sort {
+                    column: Column::Amount,
+                    descending: false,
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(amounts(&balances), ["20", "20", "30", "40"], "{name}");
+
+        let token = [
+            filter(Column::ContractAddr, Operator::In, &[&"bb".repeat(20)]),
+            filter(Column::Amount, Operator::Gt, &["5"]),
+        ];
+        let balances = storage
+            .all_balance_by_filter(&token, 0, 10, &[])
+            .await
+            .unwrap();
+        assert_eq!(amounts(&balances), ["30"], "{name}");
+        assert_eq!(balances[0].holder.holder_addr, holder_addr(1), "{name}");
+
+        // Negative balance of mint address is not listed
+        let mint = [filter(Column::HolderAddr, Operator::In, &[MINT])];
+        assert_eq!(
+            storage.all_balance_by_filter_count(&mint).await.unwrap(),
+            0,
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn sort_keys_are_applied_in_order_with_pages() {
+    for (name, storage) in storages().await {
+        seed(&storage).await;
+
+        let sort = [
+            Sort {
+                column: Column::Amount,
+                descending: true,
+            },
+            Sort {
+                column: Column::HolderAddr,
+                descending: false,
+            },
+        ];
+
+        let page = storage
+            .all_balance_by_filter(&[], 1, 3, &sort)
+            .await
+            .unwrap();
+        assert_eq!(amounts(&page), ["20", "20", "10"], "{name}");
+        assert!(
+            page[0].holder.holder_addr < page[1].holder.holder_addr,
+            "{name}"
+        );
+
+        let tokens = storage
+            .all_token(
+                0,
+                10,
+                &[Sort {
+                    column: Column::Symbol,
+                    descending: true,
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.symbol.as_str())
+                .collect::<Vec<_>>(),
+            ["BBB", "AAA"],
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn large_amounts_round_trip() {
+    for (name, storage) in storages().await {
+        let token_id = storage
+            .add_token(&"cc".repeat(20), &0, "CCC", &18)
+            .await
+            .unwrap();
+        let max = U256::MAX.to_string();
+
+        // Mint of whole uint256 range as backfill delta
+        storage
+            .apply_balance_deltas(
+                &token_id,
+                &[
+                    (holder_addr(1), max.clone()),
+                    (MINT.to_string(), format!("-{max}")),
+                ],
+                &1,
+            )
+            .await
+            .unwrap();
+
+        let balances = storage
+            .all_balance_by_filter(&[], 0, 10, &[])
+            .await
+            .unwrap();
+        assert_eq!(amounts(&balances), [max.as_str()], "{name}");
+
+        let compared = [filter(Column::Amount, Operator::Gte, &[&max])];
+        assert_eq!(
+            storage
+                .all_balance_by_filter_count(&compared)
+                .await
+                .unwrap(),
+            1,
+            "{name}"
+        );
+
+        let after = PageCursor::After(Some(Cursor::of(&balances[0])));
+        let (page, more) = storage
+            .all_balance_by_cursor(&[], &after, 10, false)
+            .await
+            .unwrap();
+        assert!(page.is_empty() && !more, "{name}");
+
+        // Transfer back to mint address leaves its balance at -1
+        let holder_id = storage.add_or_get_holder(&holder_addr(1)).await.unwrap();
+        let mint_id = storage.add_or_get_holder(MINT).await.unwrap();
+        let updated = storage
+            .upsert_balance(
+                &holder_id,
+                &mint_id,
+                &token_id,
+                &(U256::MAX - 1).to_string(),
+                &1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            updated,
+            [(holder_id, "1".to_string()), (mint_id, "-1".to_string())],
+            "{name}"
+        );
+
+        // Overflow is rejected and balances are kept
+        assert!(
+            storage
+                .apply_balance_deltas(&token_id, &[(holder_addr(1), max.clone())], &2)
+                .await
+                .is_err(),
+            "{name}"
+        );
+        let stats = storage.calculate_token_stats(&token_id, &2).await.unwrap();
+        assert_eq!(stats.total_supply, "1", "{name}");
+        assert_eq!(stats.holder_count, 1, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn transfers_over_i256_are_applied_and_totalled() {
+    for (name, storage) in storages().await {
+        let token_id = storage
+            .add_token(&"cc".repeat(20), &0, "CCC", &18)
+            .await
+            .unwrap();
+        let mint_id = storage.add_or_get_holder(MINT).await.unwrap();
+        let first = storage.add_or_get_holder(&holder_addr(1)).await.unwrap();
+        let second = storage.add_or_get_holder(&holder_addr(2)).await.unwrap();
+        let large = (U256::MAX - 5).to_string();
+
+        let webhook = storage
+            .add_webhook(
+                "http://localhost/hook",
+                "secret",
+                "transfer",
+                Some(token_id),
+                None,
+                Some(&(U256::one() << 255).to_string()),
+            )
+            .await
+            .unwrap();
+        let matching = storage
+            .matching_webhooks(&token_id, MINT, &holder_addr(1), &large)
+            .await
+            .unwrap();
+        assert_eq!(matching.len(), 1, "{name}");
+        assert_eq!(matching[0].id, webhook.id, "{name}");
+
+        storage
+            .upsert_balance(&mint_id, &first, &token_id, &large, &1)
+            .await
+            .unwrap();
+        storage
+            .upsert_balance(&mint_id, &second, &token_id, "5", &2)
+            .await
+            .unwrap();
+
+        storage.add_token_history(&token_id, &1).await.unwrap();
+        let history = storage
+            .all_token_history(&token_id, &[], 0, 10, &[])
+            .await
+            .unwrap();
+        assert_eq!(history[0].total_supply, U256::MAX.to_string(), "{name}");
+
+        let stats = storage.calculate_token_stats(&token_id, &1).await.unwrap();
+        assert_eq!(stats.total_supply, U256::MAX.to_string(), "{name}");
+        assert_eq!((stats.holder_count, stats.nakamoto), (2, 1), "{name}");
+
+        // Supply over uint256 is rejected instead of clamped
+        assert!(
+            storage
+                .upsert_balance(&mint_id, &second, &token_id, "1", &3)
+                .await
+                .is_err(),
+            "{name}"
+        );
+
+        // Checkpoint moves only with applied transfer
+        let token = storage.token_by_id(&token_id).await.unwrap().unwrap();
+        assert_eq!(token.last_checked_block, 2, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn failed_batch_of_deltas_changes_nothing() {
+    for (name, storage) in storages().await {
+        let (first, _) = seed(&storage).await;
+
+        // Holder 5 overflows after holder 1 and a new holder are changed
+        assert!(
+            storage
+                .apply_balance_deltas(
+                    &first,
+                    &[
+                        (holder_addr(1), "5".to_string()),
+                        (holder_addr(9), "5".to_string()),
+                        (holder_addr(5), U256::MAX.to_string()),
+                    ],
+                    &200,
+                )
+                .await
+                .is_err(),
+            "{name}"
+        );
+
+        let holders = [filter(
+            Column::HolderAddr,
+            Operator::In,
+            &[&holder_addr(1), &holder_addr(9)],
+        )];
+        let balances = storage
+            .all_balance_by_filter(
+                &holders,
+                0,
+                10,
+                &[Sort {
+                    column: Column::Amount,
+                    descending: false,
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(amounts(&balances), ["10", "30"], "{name}");
+        assert_eq!(
+            storage
+                .token_by_id(&first)
+                .await
+                .unwrap()
+                .unwrap()
+                .last_checked_block,
+            100,
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn running_job_is_claimed_again_only_after_lease_expires() {
+    for (name, storage) in storages().await {
+        let NewJob::Added(job) = storage.add_job(&"dd".repeat(20), None, None).await.unwrap()
+        else {
+            panic!("Job is not added to {name}");
+        };
+        let lease = Duration::from_secs(60);
+
+        let claimed = storage.claim_job("first", Duration::ZERO).await.unwrap();
+        assert_eq!(claimed.map(|job| job.id), Some(job.id), "{name}");
+
+        // Expired lease is taken over and the first worker can't finish the job
+        let claimed = storage.claim_job("second", lease).await.unwrap();
+        assert_eq!(claimed.map(|job| job.id), Some(job.id), "{name}");
+        assert!(
+            storage.claim_job("third", lease).await.unwrap().is_none(),
+            "{name}"
+        );
+        assert!(
+            !storage
+                .finish_job(&job.id, "first", JobState::Failed, None, Some("late"))
+                .await
+                .unwrap(),
+            "{name}"
+        );
+
+        assert!(
+            storage
+                .finish_job(&job.id, "second", JobState::Done, Some(1), None)
+                .await
+                .unwrap(),
+            "{name}"
+        );
+        let job = storage.job_by_id(&job.id).await.unwrap().unwrap();
+        assert_eq!(
+            (job.state.as_str(), job.token_id, job.error),
+            ("done", Some(1), None),
+            "{name}"
+        );
+        assert!(
+            storage.claim_job("third", lease).await.unwrap().is_none(),
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn contract_has_one_open_job() {
+    for (name, storage) in storages().await {
+        let contract_addr = "ee".repeat(20);
+
+        let NewJob::Added(job) = storage
+            .add_job(&contract_addr, None, Some("key"))
+            .await
+            .unwrap()
+        else {
+            panic!("Job is not added to {name}");
+        };
+
+        assert!(
+            matches!(
+                storage
+                    .add_job(&contract_addr, None, Some("key"))
+                    .await
+                    .unwrap(),
+                NewJob::IdempotencyKeyUsed
+            ),
+            "{name}"
+        );
+        assert!(
+            matches!(
+                storage
+                    .add_job(&format!("0x{contract_addr}"), Some(1), None)
+                    .await
+                    .unwrap(),
+                NewJob::ContractQueued
+            ),
+            "{name}"
+        );
+
+        // Running job is still open
+        storage
+            .claim_job("worker", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(
+            matches!(
+                storage.add_job(&contract_addr, None, None).await.unwrap(),
+                NewJob::ContractQueued
+            ),
+            "{name}"
+        );
+
+        storage
+            .finish_job(&job.id, "worker", JobState::Failed, None, Some("error"))
+            .await
+            .unwrap();
+        assert!(
+            matches!(
+                storage.add_job(&contract_addr, None, None).await.unwrap(),
+                NewJob::Added(_)
+            ),
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn token_is_leased_to_one_worker_at_a_time() {
+    for (name, storage) in storages().await {
+        let (first, _) = seed(&storage).await;
+        let lease = Duration::from_secs(60);
+
+        assert!(
+            storage.claim_token(&first, "a", lease).await.unwrap(),
+            "{name}"
+        );
+        assert!(
+            !storage.claim_token(&first, "b", lease).await.unwrap(),
+            "{name}"
+        );
+        // Owner renews its lease
+        assert!(
+            storage.claim_token(&first, "a", lease).await.unwrap(),
+            "{name}"
+        );
+
+        // Lease of other worker is not released
+        storage.release_token(&first, "b").await.unwrap();
+        assert!(
+            !storage.claim_token(&first, "b", lease).await.unwrap(),
+            "{name}"
+        );
+
+        storage.release_token(&first, "a").await.unwrap();
+        assert!(
+            storage
+                .claim_token(&first, "b", Duration::ZERO)
+                .await
+                .unwrap(),
+            "{name}"
+        );
+
+        // Expired lease is taken over
+        assert!(
+            storage.claim_token(&first, "a", lease).await.unwrap(),
+            "{name}"
+        );
+        assert!(
+            !storage.claim_token(&404, "a", lease).await.unwrap(),
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn token_is_found_by_id_or_contract() {
+    for (name, storage) in storages().await {
+        let (first, second) = seed(&storage).await;
+
+        let by_id = find_token(&storage, &second.to_string()).await.unwrap();
+        assert_eq!(
+            by_id.map(|token| token.symbol).as_deref(),
+            Some("BBB"),
+            "{name}"
+        );
+
+        let contract_addr = format!("0x{}", "aa".repeat(20));
+        let by_contract = find_token(&storage, &contract_addr).await.unwrap();
+        assert_eq!(by_contract.map(|token| token.id), Some(first), "{name}");
+
+        assert!(
+            find_token(&storage, "404").await.unwrap().is_none(),
+            "{name}"
+        );
+        assert!(
+            find_token(&storage, &"cc".repeat(20))
+                .await
+                .unwrap()
+                .is_none(),
+            "{name}"
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Storage wrapper recording latency of every call, the same for all backends
+
+use super::{NewJob, SharedStorage, Storage};
+use crate::{
+    model::{
+        ApiKey, Balance, DueWebhookDelivery, Filter, Holder, Job, JobState, PageCursor, Sort,
+        SyncState, Token, TokenHistory, TokenStats, Webhook, WebhookDelivery,
+    },
+    telemetry,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Records `db_query_duration_seconds` of calls to wrapped storage by method
+pub struct TimedStorage(pub SharedStorage);
+
+#[async_trait]
+impl Storage for TimedStorage {
+    async fn all_token(&self, number: i64, size: i64, sort: &[Sort]) -> Result<Vec<Token>> {
+        telemetry::db_query("all_token", self.0.all_token(number, size, sort)).await
+    }
+
+    async fn all_token_count(&self) -> Result<i64> {
+        telemetry::db_query("all_token_count", self.0.all_token_count()).await
+    }
+
+    async fn token_by_id(&self, token_id: &i32) -> Result<Option<Token>> {
+        telemetry::db_query("token_by_id", self.0.token_by_id(token_id)).await
+    }
+
+    async fn token_by_contract(&self, contract_addr: &str) -> Result<Option<Token>> {
+        telemetry::db_query("token_by_contract", self.0.token_by_contract(contract_addr)).await
+    }
+
+    async fn add_token(
+        &self,
+        contract_addr: &str,
+        last_checked_block: &i64,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<i32> {
+        telemetry::db_query(
+            "add_token",
+            self.0
+                .add_token(contract_addr, last_checked_block, symbol, decimals),
+        )
+        .await
+    }
+
+    async fn update_token_metadata(
+        &self,
+        token_id: &i32,
+        symbol: &str,
+        decimals: &i16,
+    ) -> Result<()> {
+        telemetry::db_query(
+            "update_token_metadata",
+            self.0.update_token_metadata(token_id, symbol, decimals),
+        )
+        .await
+    }
+
+    async fn update_token_last_checked_block(
+        &self,
+        last_checked_block: &i64,
+        token_id: &i32,
+    ) -> Result<()> {
+        telemetry::db_query(
+            "update_token_last_checked_block",
+            self.0
+                .update_token_last_checked_block(last_checked_block, token_id),
+        )
+        .await
+    }
+
+    async fn update_token_sync(
+        &self,
+        token_id: &i32,
+        sync_state: SyncState,
+        head_block: &i64,
+        backfill_rate: Option<f64>,
+        sync_error: Option<&str>,
+    ) -> Result<()> {
+        telemetry::db_query(
+            "update_token_sync",
+            self.0
+                .update_token_sync(token_id, sync_state, head_block, backfill_rate, sync_error),
+        )
+        .await
+    }
+
+    async fn request_reindex(&self, token_id: &i32, start_block: &i64) -> Result<()> {
+        telemetry::db_query(
+            "request_reindex",
+            self.0.request_reindex(token_id, start_block),
+        )
+        .await
+    }
+
+    async fn all_reindex_request(&self) -> Result<Vec<(i32, i64)>> {
+        telemetry::db_query("all_reindex_request", self.0.all_reindex_request()).await
+    }
+
+    async fn claim_token(&self, token_id: &i32, worker: &str, lease: Duration) -> Result<bool> {
+        telemetry::db_query("claim_token", self.0.claim_token(token_id, worker, lease)).await
+    }
+
+    async fn release_token(&self, token_id: &i32, worker: &str) -> Result<()> {
+        telemetry::db_query("release_token", self.0.release_token(token_id, worker)).await
+    }
+
+    async fn reset_token(&self, token_id: &i32, last_checked_block: &i64) -> Result<()> {
+        telemetry::db_query(
+            "reset_token",
+            self.0.reset_token(token_id, last_checked_block),
+        )
+        .await
+    }
+
+    async fn add_or_get_holder(&self, holder_addr: &str) -> Result<i32> {
+        telemetry::db_query("add_or_get_holder", self.0.add_or_get_holder(holder_addr)).await
+    }
+
+    async fn holder_by_addr(&self, holder_addr: &str) -> Result<Option<Holder>> {
+        telemetry::db_query("holder_by_addr", self.0.holder_by_addr(holder_addr)).await
+    }
+
+    async fn all_balance_by_filter(
+        &self,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<Balance>> {
+        telemetry::db_query(
+            "all_balance_by_filter",
+            self.0.all_balance_by_filter(filter, number, size, sort),
+        )
+        .await
+    }
+
+    async fn all_balance_by_cursor(
+        &self,
+        filter: &[Filter],
+        cursor: &PageCursor,
+        size: i64,
+        descending: bool,
+    ) -> Result<(Vec<Balance>, bool)> {
+        telemetry::db_query(
+            "all_balance_by_cursor",
+            self.0
+                .all_balance_by_cursor(filter, cursor, size, descending),
+        )
+        .await
+    }
+
+    async fn all_balance_by_filter_count(&self, filter: &[Filter]) -> Result<i64> {
+        telemetry::db_query(
+            "all_balance_by_filter_count",
+            self.0.all_balance_by_filter_count(filter),
+        )
+        .await
+    }
+
+    async fn upsert_balance(
+        &self,
+        from_holder_id: &i32,
+        to_holder_id: &i32,
+        token_id: &i32,
+        amount: &str,
+        last_checked_block: &i64,
+    ) -> Result<Vec<(i32, String)>> {
+        telemetry::db_query(
+            "upsert_balance",
+            self.0.upsert_balance(
+                from_holder_id,
+                to_holder_id,
+                token_id,
+                amount,
+                last_checked_block,
+            ),
+        )
+        .await
+    }
+
+    async fn apply_balance_deltas(
+        &self,
+        token_id: &i32,
+        deltas: &[(String, String)],
+        last_checked_block: &i64,
+    ) -> Result<()> {
+        telemetry::db_query(
+            "apply_balance_deltas",
+            self.0
+                .apply_balance_deltas(token_id, deltas, last_checked_block),
+        )
+        .await
+    }
+
+    async fn add_token_history(&self, token_id: &i32, block_number: &i64) -> Result<()> {
+        telemetry::db_query(
+            "add_token_history",
+            self.0.add_token_history(token_id, block_number),
+        )
+        .await
+    }
+
+    async fn all_token_history(
+        &self,
+        token_id: &i32,
+        filter: &[Filter],
+        number: i64,
+        size: i64,
+        sort: &[Sort],
+    ) -> Result<Vec<TokenHistory>> {
+        telemetry::db_query(
+            "all_token_history",
+            self.0
+                .all_token_history(token_id, filter, number, size, sort),
+        )
+        .await
+    }
+
+    async fn all_token_history_count(&self, token_id: &i32, filter: &[Filter]) -> Result<i64> {
+        telemetry::db_query(
+            "all_token_history_count",
+            self.0.all_token_history_count(token_id, filter),
+        )
+        .await
+    }
+
+    async fn fresh_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+        max_age: Duration,
+    ) -> Result<Option<TokenStats>> {
+        telemetry::db_query(
+            "fresh_token_stats",
+            self.0
+                .fresh_token_stats(token_id, last_checked_block, max_age),
+        )
+        .await
+    }
+
+    async fn calculate_token_stats(
+        &self,
+        token_id: &i32,
+        last_checked_block: &i64,
+    ) -> Result<TokenStats> {
+        telemetry::db_query(
+            "calculate_token_stats",
+            self.0.calculate_token_stats(token_id, last_checked_block),
+        )
+        .await
+    }
+
+    async fn add_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        event: &str,
+        token_id: Option<i32>,
+        holder_addr: Option<&str>,
+        min_amount: Option<&str>,
+    ) -> Result<Webhook> {
+        telemetry::db_query(
+            "add_webhook",
+            self.0
+                .add_webhook(url, secret, event, token_id, holder_addr, min_amount),
+        )
+        .await
+    }
+
+    async fn webhook_by_id(&self, webhook_id: &i32) -> Result<Option<Webhook>> {
+        telemetry::db_query("webhook_by_id", self.0.webhook_by_id(webhook_id)).await
+    }
+
+    async fn all_webhook(&self, number: i64, size: i64) -> Result<Vec<Webhook>> {
+        telemetry::db_query("all_webhook", self.0.all_webhook(number, size)).await
+    }
+
+    async fn all_webhook_count(&self) -> Result<i64> {
+        telemetry::db_query("all_webhook_count", self.0.all_webhook_count()).await
+    }
+
+    async fn delete_webhook(&self, webhook_id: &i32) -> Result<bool> {
+        telemetry::db_query("delete_webhook", self.0.delete_webhook(webhook_id)).await
+    }
+
+    async fn matching_webhooks(
+        &self,
+        token_id: &i32,
+        from_holder_addr: &str,
+        to_holder_addr: &str,
+        amount: &str,
+    ) -> Result<Vec<Webhook>> {
+        telemetry::db_query(
+            "matching_webhooks",
+            self.0
+                .matching_webhooks(token_id, from_holder_addr, to_holder_addr, amount),
+        )
+        .await
+    }
+
+    async fn add_webhook_delivery(&self, webhook_id: &i32, payload: &str) -> Result<i32> {
+        telemetry::db_query(
+            "add_webhook_delivery",
+            self.0.add_webhook_delivery(webhook_id, payload),
+        )
+        .await
+    }
+
+    async fn claim_webhook_deliveries(
+        &self,
+        max_attempts: &i32,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<DueWebhookDelivery>> {
+        telemetry::db_query(
+            "claim_webhook_deliveries",
+            self.0.claim_webhook_deliveries(max_attempts, limit, lease),
+        )
+        .await
+    }
+
+    async fn update_webhook_delivery(
+        &self,
+        delivery_id: &i32,
+        attempts: &i32,
+        status_code: Option<i32>,
+        delivered: bool,
+        error: Option<&str>,
+        retry_in: Duration,
+    ) -> Result<()> {
+        telemetry::db_query(
+            "update_webhook_delivery",
+            self.0.update_webhook_delivery(
+                delivery_id,
+                attempts,
+                status_code,
+                delivered,
+                error,
+                retry_in,
+            ),
+        )
+        .await
+    }
+
+    async fn all_webhook_delivery(
+        &self,
+        webhook_id: &i32,
+        number: i64,
+        size: i64,
+    ) -> Result<Vec<WebhookDelivery>> {
+        telemetry::db_query(
+            "all_webhook_delivery",
+            self.0.all_webhook_delivery(webhook_id, number, size),
+        )
+        .await
+    }
+
+    async fn all_webhook_delivery_count(&self, webhook_id: &i32) -> Result<i64> {
+        telemetry::db_query(
+            "all_webhook_delivery_count",
+            self.0.all_webhook_delivery_count(webhook_id),
+        )
+        .await
+    }
+
+    async fn add_api_key(
+        &self,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        rate_limit: &i32,
+    ) -> Result<Option<ApiKey>> {
+        telemetry::db_query(
+            "add_api_key",
+            self.0.add_api_key(key_hash, name, scopes, rate_limit),
+        )
+        .await
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        telemetry::db_query("api_key_by_hash", self.0.api_key_by_hash(key_hash)).await
+    }
+
+    async fn all_api_key(&self, number: i64, size: i64) -> Result<Vec<ApiKey>> {
+        telemetry::db_query("all_api_key", self.0.all_api_key(number, size)).await
+    }
+
+    async fn all_api_key_count(&self) -> Result<i64> {
+        telemetry::db_query("all_api_key_count", self.0.all_api_key_count()).await
+    }
+
+    async fn delete_api_key(&self, api_key_id: &i32) -> Result<bool> {
+        telemetry::db_query("delete_api_key", self.0.delete_api_key(api_key_id)).await
+    }
+
+    async fn add_job(
+        &self,
+        contract_addr: &str,
+        start_block: Option<i64>,
+        idempotency_key: Option<&str>,
+    ) -> Result<NewJob> {
+        telemetry::db_query(
+            "add_job",
+            self.0.add_job(contract_addr, start_block, idempotency_key),
+        )
+        .await
+    }
+
+    async fn job_by_idempotency_key(&self, idempotency_key: &str) -> Result<Option<Job>> {
+        telemetry::db_query(
+            "job_by_idempotency_key",
+            self.0.job_by_idempotency_key(idempotency_key),
+        )
+        .await
+    }
+
+    async fn open_job_by_contract(&self, contract_addr: &str) -> Result<Option<Job>> {
+        telemetry::db_query(
+            "open_job_by_contract",
+            self.0.open_job_by_contract(contract_addr),
+        )
+        .await
+    }
+
+    async fn job_by_id(&self, job_id: &i32) -> Result<Option<Job>> {
+        telemetry::db_query("job_by_id", self.0.job_by_id(job_id)).await
+    }
+
+    async fn claim_job(&self, worker: &str, lease: Duration) -> Result<Option<Job>> {
+        telemetry::db_query("claim_job", self.0.claim_job(worker, lease)).await
+    }
+
+    async fn finish_job(
+        &self,
+        job_id: &i32,
+        worker: &str,
+        state: JobState,
+        token_id: Option<i32>,
+        error: Option<&str>,
+    ) -> Result<bool> {
+        telemetry::db_query(
+            "finish_job",
+            self.0.finish_job(job_id, worker, state, token_id, error),
+        )
+        .await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        telemetry::db_query("ping", self.0.ping()).await
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::{future::Future, sync::OnceLock, time::Instant};
+
+/// Buckets of latency histograms in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install global Prometheus recorder
+pub fn install() -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    let _ = HANDLE.set(handle);
+
+    Ok(())
+}
+
+/// Render metrics in Prometheus text format
+pub async fn metrics() -> impl IntoResponse {
+    HANDLE
+        .get()
+        .map(|handle| handle.render())
+        .unwrap_or_default()
+}
+
+/// Count and time RPC call by method
+pub async fn rpc<T, E>(
+    method: &'static str,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let result = call.await;
+    let status = if result.is_ok() { "ok" } else { "error" };
+
+    counter!("rpc_requests_total", "method" => method, "status" => status).increment(1);
+    histogram!("rpc_request_duration_seconds", "method" => method)
+        .record(start.elapsed().as_secs_f64());
+
+    result
+}
+
+/// Count `-32005` limit exceeded response of `eth_getLogs`, retried with smaller step
+pub fn rpc_limit_retry(token: &str) {
+    counter!("rpc_limit_retries_total", "token" => token.to_string()).increment(1);
+}
+
+pub fn logs_processed(token: &str, count: usize) {
+    counter!("logs_processed_total", "token" => token.to_string()).increment(count as u64);
+}
+
+pub fn backfill_step(token: &str, step: i64) {
+    gauge!("backfill_step_blocks", "token" => token.to_string()).set(step as f64);
+}
+
+/// Blocks between head and checkpoint of token
+pub fn token_lag(token: &str, head_block: i64, last_checked_block: i64) {
+    gauge!("token_lag_blocks", "token" => token.to_string())
+        .set((head_block - last_checked_block).max(0) as f64);
+}
+
+/// Time storage call by query
+pub async fn db_query<T>(query: &'static str, call: impl Future<Output = Result<T>>) -> Result<T> {
+    let start = Instant::now();
+    let result = call.await;
+
+    histogram!("db_query_duration_seconds", "query" => query).record(start.elapsed().as_secs_f64());
+
+    result
+}
+
+/// Count and time HTTP requests by method, route and status
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(start.elapsed().as_secs_f64());
+
+    response
+}
@@ -4,9 +4,13 @@ use crate::{
 };
 use anyhow::Result;
 use axum::http::StatusCode;
-use jsonapi::{model::*, query::PageParams};
+use ethers::{types::Address, utils::to_checksum};
+use jsonapi::{
+    model::*,
+    query::{PageParams, Query},
+};
 use serde_json::json;
-use std::collections::HashSet;
+use std::{collections::HashSet, str::FromStr};
 
 fn create_link(url: &str, page_number: i64, page_size: i64) -> String {
     format!(
@@ -15,6 +19,59 @@ fn create_link(url: &str, page_number: i64, page_size: i64) -> String {
     )
 }
 
+/// Keyset page with cursors of neighbour pages and other query params kept in links
+pub struct CursorPage {
+    pub size: i64,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+    pub query: String,
+}
+
+/// Raw query without `page[...]` params
+pub fn query_without_page(raw_query: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(
+            form_urlencoded::parse(raw_query.as_bytes())
+                .filter(|(key, _)| !key.starts_with("page[")),
+        )
+        .finish()
+}
+
+fn create_cursor_links_hashmap(
+    page: &CursorPage,
+    url: &str,
+) -> Option<HashMap<String, JsonApiValue>> {
+    let mut links: HashMap<String, JsonApiValue> = HashMap::new();
+
+    let query = if page.query.is_empty() {
+        String::new()
+    } else {
+        format!("{}&", page.query)
+    };
+
+    let create_link = |direction: &str, cursor: &str| {
+        json!(format!(
+            "{}?{}page[{}]={}&page[size]={}",
+            url, query, direction, cursor, page.size
+        ))
+    };
+
+    if let Some(prev) = &page.prev {
+        links.insert("first".to_string(), create_link("after", ""));
+        links.insert("prev".to_string(), create_link("before", prev));
+    }
+
+    if let Some(next) = &page.next {
+        links.insert("next".to_string(), create_link("after", next));
+    }
+
+    if links.is_empty() {
+        None
+    } else {
+        Some(links)
+    }
+}
+
 fn create_links_hashmap(
     total_count: i64,
     page: PageParams,
@@ -47,6 +104,107 @@ fn create_links_hashmap(
     }
 }
 
+/// Remove optional `0x` prefix from hex address
+pub fn strip_hex_prefix(addr: &str) -> &str {
+    addr.strip_prefix("0x")
+        .or_else(|| addr.strip_prefix("0X"))
+        .unwrap_or(addr)
+}
+
+/// Check that value is hex address without `0x` prefix
+pub fn is_hex_address(addr: &str) -> bool {
+    addr.len() == 40 && addr.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Lowercase hex address without `0x` prefix, as stored.
+/// Mixed case address has to match its EIP-55 checksum
+pub fn normalize_addr(addr: &str) -> Result<String, String> {
+    let hex = strip_hex_prefix(addr.trim());
+
+    if !is_hex_address(hex) {
+        return Err(format!("'{addr}' is not a valid address"));
+    }
+
+    let lowercase = hex.to_lowercase();
+
+    if hex != lowercase && hex != hex.to_uppercase() {
+        let address = Address::from_str(&lowercase).map_err(|err| err.to_string())?;
+
+        if to_checksum(&address, None)[2..] != *hex {
+            return Err(format!("'{addr}' does not match its checksum"));
+        }
+    }
+
+    Ok(lowercase)
+}
+
+/// Max fraction digits of formatted amount, as many as `NUMERIC(78, 0)` can hold
+pub const MAX_PRECISION: usize = 78;
+
+/// Format base unit integer amount as decimal with token decimals without precision loss.
+/// Fraction is truncated to precision digits, or stripped of trailing zeros without precision
+pub fn format_amount(amount: &str, decimals: i16, precision: Option<usize>) -> String {
+    let decimals = decimals.max(0) as usize;
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+
+    let fraction = match precision {
+        Some(precision) => format!(
+            "{:0<precision$}",
+            &fraction[..fraction.len().min(precision)]
+        ),
+        None => fraction.trim_end_matches('0').to_string(),
+    };
+
+    if fraction.is_empty() {
+        integer.to_string()
+    } else {
+        format!("{integer}.{fraction}")
+    }
+}
+
+/// Parse `precision` param of raw query, `None` if not set
+pub fn parse_precision(raw_query: &str) -> Option<Result<usize, String>> {
+    form_urlencoded::parse(raw_query.as_bytes())
+        .find(|(key, _)| key == "precision")
+        .map(|(_, value)| {
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|precision| *precision <= MAX_PRECISION)
+                .ok_or(format!("expected number from 0 to {MAX_PRECISION}"))
+        })
+}
+
+/// Url of route on this service
+pub fn service_url(route: &str) -> Result<String, AppErrorResponse> {
+    Ok(format!(
+        "{}:{}/{}",
+        std::env::var("SERVICE_IP")
+            .map_err(|err| app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, err))?,
+        std::env::var("SERVICE_PORT")
+            .map_err(|err| app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, err))?,
+        route
+    ))
+}
+
+/// Create jsonapi document of single object with `self` link to its route
+pub fn resource_document<T: JsonApiModel>(
+    object: &T,
+    route: &str,
+) -> Result<JsonApiDocument, AppErrorResponse> {
+    let mut links: HashMap<String, JsonApiValue> = HashMap::new();
+    links.insert("self".to_string(), json!(service_url(route)?));
+
+    Ok(match object.to_jsonapi_document() {
+        JsonApiDocument::Data(document) => JsonApiDocument::Data(DocumentData {
+            links: Some(links),
+            ..document
+        }),
+        document => document,
+    })
+}
+
 /// Create jsonapi document from vector with meta, links and included
 pub fn vec_to_jsonapi_document<T: JsonApiModel>(
     objects: Vec<T>,
@@ -54,6 +212,44 @@ pub fn vec_to_jsonapi_document<T: JsonApiModel>(
     page: PageParams,
     route: &str,
 ) -> Result<JsonApiDocument, AppErrorResponse> {
+    let url = service_url(route)?;
+
+    let mut meta: HashMap<String, JsonApiValue> = HashMap::new();
+    meta.insert("total_count".to_string(), json!(total_count));
+    meta.insert("page_number".to_string(), json!(page.number));
+    meta.insert("page_size".to_string(), json!(page.size));
+
+    Ok(list_document(
+        objects,
+        meta,
+        create_links_hashmap(total_count, page, &url),
+    ))
+}
+
+/// Create jsonapi document from keyset page of vector, without total count
+/// so deep pages don't have to count all rows
+pub fn vec_to_cursor_document<T: JsonApiModel>(
+    objects: Vec<T>,
+    page: CursorPage,
+    route: &str,
+) -> Result<JsonApiDocument, AppErrorResponse> {
+    let url = service_url(route)?;
+
+    let mut meta: HashMap<String, JsonApiValue> = HashMap::new();
+    meta.insert("page_size".to_string(), json!(page.size));
+
+    Ok(list_document(
+        objects,
+        meta,
+        create_cursor_links_hashmap(&page, &url),
+    ))
+}
+
+fn list_document<T: JsonApiModel>(
+    objects: Vec<T>,
+    meta: HashMap<String, JsonApiValue>,
+    links: Option<HashMap<String, JsonApiValue>>,
+) -> JsonApiDocument {
     let (resources, mut included) = vec_to_jsonapi_resources(objects);
 
     if let Some(mut vector) = included {
@@ -68,27 +264,56 @@ pub fn vec_to_jsonapi_document<T: JsonApiModel>(
         included = Some(vector);
     }
 
-    let url = format!(
-        "{}:{}/{}",
-        std::env::var("SERVICE_IP")
-            .or_else(|err| Err(app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, err)))?,
-        std::env::var("SERVICE_PORT")
-            .or_else(|err| Err(app_err_response!(StatusCode::INTERNAL_SERVER_ERROR, err)))?,
-        route
-    );
-
-    let mut meta: HashMap<String, JsonApiValue> = HashMap::new();
-    meta.insert("total_count".to_string(), json!(total_count));
-    meta.insert("page_number".to_string(), json!(page.number));
-    meta.insert("page_size".to_string(), json!(page.size));
-
-    Ok(JsonApiDocument::Data(DocumentData {
+    JsonApiDocument::Data(DocumentData {
         data: Some(PrimaryData::Multiple(resources)),
         included,
         meta: Some(meta),
-        links: create_links_hashmap(total_count, page, &url),
+        links,
         ..Default::default()
-    }))
+    })
+}
+
+/// Keep included resources of requested relationships only
+/// and trim attributes and relationships to requested fields of their type
+pub fn sparse_document(document: JsonApiDocument, query: &Query) -> JsonApiDocument {
+    let JsonApiDocument::Data(mut document) = document else {
+        return document;
+    };
+
+    let include = query.include.clone().unwrap_or_default();
+    let fields = query.fields.clone().unwrap_or_default();
+
+    let trim = |resource: &mut Resource| {
+        if let Some(fields) = fields.get(&resource._type) {
+            resource.attributes.retain(|name, _| fields.contains(name));
+
+            if let Some(relationships) = resource.relationships.as_mut() {
+                relationships.retain(|name, _| fields.contains(name));
+            }
+        }
+    };
+
+    match document.data.as_mut() {
+        Some(PrimaryData::Single(resource)) => trim(resource),
+        Some(PrimaryData::Multiple(resources)) => resources.iter_mut().for_each(trim),
+        _ => {}
+    }
+
+    document.included = document
+        .included
+        .map(|included| {
+            included
+                .into_iter()
+                .filter(|resource| include.contains(&resource._type))
+                .collect::<Resources>()
+        })
+        .filter(|included| !included.is_empty());
+
+    if let Some(included) = document.included.as_mut() {
+        included.iter_mut().for_each(trim);
+    }
+
+    JsonApiDocument::Data(document)
 }
 
 pub fn get_data_from_doc(doc: JsonApiDocument) -> Result<Resource, AppErrorResponse> {
@@ -122,3 +347,64 @@ pub fn get_data_from_doc(doc: JsonApiDocument) -> Result<Resource, AppErrorRespo
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Address of EIP-55 examples
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn address_is_lowercased_and_checked_against_checksum() {
+        let lowercase = "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+        assert_eq!(normalize_addr(CHECKSUMMED).as_deref(), Ok(lowercase));
+        assert_eq!(normalize_addr(lowercase).as_deref(), Ok(lowercase));
+        assert_eq!(
+            normalize_addr(&format!("0X{}", lowercase.to_uppercase())).as_deref(),
+            Ok(lowercase)
+        );
+
+        // Mixed case with one letter of checksum flipped
+        let bad_checksum = CHECKSUMMED.replacen("aA", "aa", 1);
+        assert!(normalize_addr(&bad_checksum)
+            .unwrap_err()
+            .contains("does not match its checksum"));
+        assert!(normalize_addr("0x5aaeb6053f").is_err());
+        assert!(normalize_addr(&"zz".repeat(20)).is_err());
+    }
+
+    #[test]
+    fn amount_is_formatted_with_decimals() {
+        assert_eq!(format_amount("1500000", 6, None), "1.5");
+        assert_eq!(format_amount("1000000", 6, None), "1");
+        assert_eq!(format_amount("5", 6, None), "0.000005");
+        assert_eq!(format_amount("0", 18, None), "0");
+        assert_eq!(format_amount("42", 0, None), "42");
+
+        // Precision truncates or pads fraction
+        assert_eq!(format_amount("1234567", 6, Some(2)), "1.23");
+        assert_eq!(format_amount("1234567", 6, Some(0)), "1");
+        assert_eq!(format_amount("15", 1, Some(3)), "1.500");
+    }
+
+    #[test]
+    fn precision_is_limited_to_numeric_digits() {
+        assert_eq!(parse_precision("page[size]=10"), None);
+        assert_eq!(parse_precision("precision=0"), Some(Ok(0)));
+        assert_eq!(parse_precision("precision=78"), Some(Ok(MAX_PRECISION)));
+
+        for invalid in [
+            "precision=79",
+            "precision=-1",
+            "precision=two",
+            "precision=",
+        ] {
+            assert!(
+                matches!(parse_precision(invalid), Some(Err(_))),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -1,7 +1,17 @@
-use crate::error::{AppError, AppErrorResponse};
+use crate::{
+    error::{AppError, AppErrorResponse},
+    model::{Column, Filter, PageCursor},
+    utils,
+};
 use axum::http::StatusCode;
 use jsonapi::query::Query;
 
+/// Max resources per page of list endpoints
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Max page number, so that offset of page fits in i64
+pub const MAX_PAGE_NUMBER: i64 = i64::MAX / MAX_PAGE_SIZE;
+
 pub struct QueryParamsValidator {
     query_params: Query,
     errors_vec: Vec<AppError>,
@@ -40,32 +50,29 @@ impl QueryParamsValidator {
     pub fn valid_pagination(mut self) -> Self {
         let page = self.query_params.page.unwrap();
 
-        let mut validate = |attribute, name| {
-            if attribute < 0 {
-                let message = format!("pagination attribute {name} less then 0");
+        let mut validate = |attribute, name, max| {
+            let message = if attribute < 0 {
+                format!("pagination attribute {name} less then 0")
+            } else if attribute > max {
+                format!("pagination attribute {name} greater then {max}")
+            } else {
+                return;
+            };
 
-                self.errors_vec.push(AppError::new(
-                    StatusCode::BAD_REQUEST,
-                    &message,
-                    Some(&message),
-                    Some(&format!("page/{name}")),
-                ));
-            }
+            self.errors_vec.push(AppError::new(
+                StatusCode::BAD_REQUEST,
+                &message,
+                Some(&message),
+                Some(&format!("page/{name}")),
+            ));
         };
 
-        validate(page.size, "size");
-        validate(page.number, "number");
+        validate(page.size, "size", MAX_PAGE_SIZE);
+        validate(page.number, "number", MAX_PAGE_NUMBER);
 
         self
     }
 
-    fn valid_vector(vector: Vec<String>, valid_vector: &Vec<&str>) -> Vec<String> {
-        vector
-            .into_iter()
-            .filter(|value| valid_vector.contains(&value.as_str()))
-            .collect()
-    }
-
     fn unwrap<T: Default>(&mut self, collection: Option<T>) -> T {
         match collection {
             Some(collection) => collection,
@@ -88,81 +95,325 @@ impl QueryParamsValidator {
         }
     }
 
-    fn only(&mut self, vector_len: usize, valid_vector_len: usize, required: &Vec<&str>) {
-        if vector_len != valid_vector_len {
+    pub fn no_filter(mut self) -> Self {
+        self.cur_param_name = "filter".to_string();
+        self.no(self.query_params.filter.clone());
+
+        self
+    }
+
+    pub fn no_include(mut self) -> Self {
+        self.cur_param_name = "include".to_string();
+        self.no(self.query_params.include.clone());
+
+        self
+    }
+
+    pub fn no_fields(mut self) -> Self {
+        self.cur_param_name = "fields".to_string();
+        if !self.query_params.fields.clone().unwrap().is_empty() {
+            self.no(Some(()));
+        }
+
+        self
+    }
+
+    pub fn no_sort(mut self) -> Self {
+        self.cur_param_name = "sort".to_string();
+        self.no(self.query_params.sort.clone());
+
+        self
+    }
+
+    /// Sort by one or more of valid columns, `-` prefix for descending order
+    pub fn valid_sort(mut self, columns: &[(&str, Column)]) -> Self {
+        self.cur_param_name = "sort".to_string();
+
+        let valid_sort: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+        let sort_params = self.unwrap(self.query_params.sort.clone());
+        let mut seen = Vec::new();
+
+        for sort_param in sort_params.iter() {
+            let column = sort_param.strip_prefix('-').unwrap_or(sort_param);
+
+            if !valid_sort.contains(&column) {
+                let message = format!(
+                    "'{}' attribute has invalid value '{}'. Valid values: {}",
+                    self.cur_param_name,
+                    sort_param,
+                    valid_sort.join(", ")
+                );
+                self.add_error(&message);
+            } else if seen.contains(&column) {
+                let message = format!(
+                    "'{}' attribute has duplicated value '{}'",
+                    self.cur_param_name, column
+                );
+                self.add_error(&message);
+            }
+
+            seen.push(column);
+        }
+
+        self
+    }
+
+    /// Filter by valid columns, combined with AND.
+    /// Reports every invalid `filter[column]` or `filter[column][operator]` param
+    pub fn valid_filter(
+        mut self,
+        raw_query: &str,
+        columns: &[(&str, Column)],
+        required: bool,
+    ) -> Self {
+        let valid_filter: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+        let filter_params = Filter::parse_params(raw_query, columns);
+
+        if required && filter_params.is_empty() {
+            self.cur_param_name = "filter".to_string();
             let message = format!(
-                "'{}' attribute cannot have optional values. Required values: {}",
+                "'{}' attribute is missing. Valid values: {}",
                 self.cur_param_name,
-                required.join(", ")
+                valid_filter.join(", ")
             );
             self.add_error(&message);
         }
+
+        for (param_name, column, filter) in filter_params {
+            self.cur_param_name = param_name;
+
+            let message = if !valid_filter.contains(&column.as_str()) {
+                format!(
+                    "'{}' attribute is not allowed. Valid columns: {}",
+                    self.cur_param_name,
+                    valid_filter.join(", ")
+                )
+            } else if let Err(err) = filter {
+                format!("'{}' attribute is invalid: {}", self.cur_param_name, err)
+            } else {
+                continue;
+            };
+
+            self.add_error(&message);
+        }
+
+        self
     }
 
-    fn one(&mut self, vector_len: usize, valid_vector_len: usize, required: &Vec<&str>) {
-        if vector_len != valid_vector_len {
+    /// Keyset pagination with `page[after]` or `page[before]` cursor,
+    /// allowed only with sort by amount and without page number
+    pub fn valid_cursor(mut self, raw_query: &str) -> Self {
+        let Some((param_name, cursor)) = PageCursor::parse_params(raw_query) else {
+            return self;
+        };
+
+        self.cur_param_name = param_name;
+
+        if let Err(err) = cursor {
+            let message = format!("'{}' attribute is invalid: {}", self.cur_param_name, err);
+            self.add_error(&message);
+        }
+
+        if self.query_params.page.unwrap().number != 0 {
             let message = format!(
-                "'{}' attribute cannot have more then one of required values. Required values: {}",
-                self.cur_param_name,
-                required.join(", ")
+                "'{}' attribute cannot be combined with page[number]",
+                self.cur_param_name
             );
             self.add_error(&message);
         }
-    }
 
-    fn only_one(&mut self, vector: Vec<String>, required_vector: Vec<&str>) {
-        let vector_len = vector.len();
-        let valid_vector = Self::valid_vector(vector, &required_vector);
-        let valid_vector_len = valid_vector.len();
+        let sort = self.query_params.sort.clone().unwrap_or_default();
 
-        self.only(vector_len, valid_vector_len, &required_vector);
-        self.one(vector_len, valid_vector_len, &required_vector);
+        if sort != ["amount"] && sort != ["-amount"] {
+            let message = format!(
+                "'{}' attribute requires sort by 'amount' or '-amount'",
+                self.cur_param_name
+            );
+            self.add_error(&message);
+        }
+
+        self
     }
 
-    pub fn no_filter(mut self) -> Self {
-        self.cur_param_name = "filter".to_string();
-        self.no(self.query_params.filter.clone());
+    /// Include related resources of valid relationships
+    pub fn valid_include(mut self, valid_include: Vec<&str>) -> Self {
+        self.cur_param_name = "include".to_string();
+
+        for include in self.query_params.include.clone().unwrap_or_default() {
+            if !valid_include.contains(&include.as_str()) {
+                let message = format!(
+                    "'{}' attribute has invalid value '{}'. Valid values: {}",
+                    self.cur_param_name,
+                    include,
+                    valid_include.join(", ")
+                );
+                self.add_error(&message);
+            }
+        }
 
         self
     }
 
-    pub fn no_include(mut self) -> Self {
-        self.cur_param_name = "include".to_string();
-        self.no(self.query_params.include.clone());
+    /// Sparse fieldsets `fields[type]=a,b` of valid types and their fields
+    pub fn valid_fields(mut self, valid_fields: Vec<(&str, &[&str])>) -> Self {
+        for (resource_type, fields) in self.query_params.fields.clone().unwrap_or_default() {
+            self.cur_param_name = format!("fields[{resource_type}]");
+
+            let Some((_, valid)) = valid_fields.iter().find(|(t, _)| *t == resource_type) else {
+                let message = format!(
+                    "'{}' attribute is not allowed. Valid types: {}",
+                    self.cur_param_name,
+                    valid_fields
+                        .iter()
+                        .map(|(t, _)| *t)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                self.add_error(&message);
+                continue;
+            };
+
+            for field in fields.iter() {
+                if !valid.contains(&field.as_str()) {
+                    let message = format!(
+                        "'{}' attribute has invalid value '{}'. Valid values: {}",
+                        self.cur_param_name,
+                        field,
+                        valid.join(", ")
+                    );
+                    self.add_error(&message);
+                }
+            }
+        }
 
         self
     }
 
-    pub fn no_fields(mut self) -> Self {
-        self.cur_param_name = "fields".to_string();
-        if !self.query_params.fields.clone().unwrap().is_empty() {
-            self.no(Some(()));
+    /// Fraction digits of formatted amounts
+    pub fn valid_precision(mut self, raw_query: &str) -> Self {
+        self.cur_param_name = "precision".to_string();
+
+        if let Some(Err(err)) = utils::parse_precision(raw_query) {
+            let message = format!("'{}' attribute is invalid: {}", self.cur_param_name, err);
+            self.add_error(&message);
         }
 
         self
     }
+}
 
-    pub fn only_one_sort(mut self, required_sort: Vec<&str>) -> Self {
-        self.cur_param_name = "sort".to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
 
-        let sort_params = self.unwrap(self.query_params.sort.clone());
+    const COLUMNS: &[(&str, Column)] = &[
+        ("holder.holder_addr", Column::HolderAddr),
+        ("amount", Column::Amount),
+    ];
 
-        self.only_one(sort_params, required_sort);
+    fn validate(raw_query: &str) -> QueryParamsValidator {
+        QueryParamsValidator::new(Query::from_params(raw_query))
+    }
 
-        self
+    /// Parameters and details of validation errors in response
+    async fn errors_of(validator: QueryParamsValidator) -> Vec<(String, String)> {
+        let Err(response) = validator.collect_query() else {
+            return Vec::new();
+        };
+
+        let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        document["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| {
+                (
+                    error["source"]["parameter"].as_str().unwrap().to_string(),
+                    error["detail"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
     }
 
-    pub fn only_one_filter(mut self, required_filter: Vec<&str>) -> Self {
-        self.cur_param_name = "filter".to_string();
+    #[tokio::test]
+    async fn cursor_needs_amount_sort_and_no_page_number() {
+        for raw_query in [
+            "sort=-amount&page[after]=",
+            "sort=amount&page[before]=10_1_2",
+        ] {
+            let validator = validate(raw_query).valid_cursor(raw_query);
+            assert!(errors_of(validator).await.is_empty(), "{raw_query}");
+        }
+
+        let raw_query = "sort=-amount&page[after]=10_1_2&page[number]=2";
+        let errors = errors_of(validate(raw_query).valid_cursor(raw_query)).await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "page[after]");
+        assert!(errors[0].1.contains("cannot be combined with page[number]"));
 
-        let filter_keys: Vec<_> = self
-            .unwrap(self.query_params.filter.clone())
-            .keys()
-            .cloned()
-            .collect();
+        let raw_query = "sort=holder.holder_addr&page[before]=ten";
+        let errors = errors_of(validate(raw_query).valid_cursor(raw_query)).await;
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].1.contains("invalid cursor"));
+        assert!(errors[1].1.contains("requires sort by 'amount'"));
+    }
 
-        self.only_one(filter_keys, required_filter);
+    #[tokio::test]
+    async fn filter_reports_every_invalid_param() {
+        let raw_query = format!(
+            "filter[amount][gte]=10&filter[holder.holder_addr]={},0x{}",
+            "00".repeat(20),
+            "01".repeat(20)
+        );
+        let validator = validate(&raw_query).valid_filter(&raw_query, COLUMNS, true);
+        assert!(errors_of(validator).await.is_empty());
 
-        self
+        let raw_query =
+            "filter[amount][between]=1&filter[holder.holder_addr][gt]=00&filter[symbol]=A";
+        let errors = errors_of(validate(raw_query).valid_filter(raw_query, COLUMNS, false)).await;
+        let params: Vec<&str> = errors.iter().map(|(param, _)| param.as_str()).collect();
+        assert_eq!(
+            params,
+            [
+                "filter[amount][between]",
+                "filter[holder.holder_addr][gt]",
+                "filter[symbol]"
+            ]
+        );
+        assert!(errors[0].1.contains("unknown filter operator"));
+        assert!(errors[1].1.contains("cannot be compared"));
+        assert!(errors[2].1.contains("is not allowed"));
+    }
+
+    #[tokio::test]
+    async fn required_filter_is_reported_missing() {
+        let errors = errors_of(validate("").valid_filter("", COLUMNS, true)).await;
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "filter");
+        assert!(errors[0].1.contains("is missing"));
+    }
+
+    #[tokio::test]
+    async fn fields_are_checked_by_resource_type() {
+        let valid_fields: Vec<(&str, &[&str])> = vec![("token", &["symbol", "decimals"])];
+
+        let validator_of = |raw_query| validate(raw_query).valid_fields(valid_fields.clone());
+        assert!(errors_of(validator_of("fields[token]=symbol,decimals"))
+            .await
+            .is_empty());
+
+        let errors = errors_of(validator_of(
+            "fields[token]=name&fields[holder]=holder_addr",
+        ))
+        .await;
+        let mut errors: Vec<_> = errors.iter().map(|(param, _)| param.as_str()).collect();
+        errors.sort();
+        assert_eq!(errors, ["fields[holder]", "fields[token]"]);
     }
 }
@@ -0,0 +1,172 @@
+use crate::{
+    evm::TransferEvent,
+    model::{DueWebhookDelivery, Webhook},
+    storage::SharedStorage,
+};
+use anyhow::Result;
+use ethers::utils::hex;
+use futures::stream::{self, StreamExt};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::{sync::OnceLock, time::Duration};
+
+/// Delivery attempts before giving up
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Delay before second attempt, doubled for every next one
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Interval of checking storage for due deliveries
+const DELIVERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Due deliveries claimed at once
+const DELIVERY_BATCH: i64 = 100;
+
+/// Deliveries sent concurrently
+const DELIVERY_CONCURRENCY: usize = 16;
+
+/// Time claimed delivery is hidden from other workers, longer than request timeout
+const DELIVERY_LEASE: Duration = Duration::from_secs(30);
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Error creating webhook http client")
+    })
+}
+
+/// Hex encoded HMAC-SHA256 of payload
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(payload.as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Payloads of webhook for transfer, one per matched holder for balance event
+fn payloads(webhook: &Webhook, event: &TransferEvent) -> Vec<String> {
+    match webhook.event.as_str() {
+        "transfer" => vec![json!({
+            "webhook_id": webhook.id,
+            "event": "transfer",
+            "data": event,
+        })
+        .to_string()],
+        _ => [
+            (&event.from, &event.from_balance),
+            (&event.to, &event.to_balance),
+        ]
+        .into_iter()
+        .filter(|(holder, _)| match &webhook.holder_addr {
+            Some(holder_addr) => holder.eq_ignore_ascii_case(holder_addr),
+            None => true,
+        })
+        .map(|(holder, amount)| {
+            json!({
+                "webhook_id": webhook.id,
+                "event": "balance",
+                "data": {
+                    "token": event.token,
+                    "holder": holder,
+                    "amount": amount,
+                    "block_number": event.block_number,
+                    "transaction_hash": event.transaction_hash,
+                },
+            })
+            .to_string()
+        })
+        .collect(),
+    }
+}
+
+/// Queue payloads of webhooks matching transfer, they are sent by `deliver_pending`
+pub async fn notify(storage: &SharedStorage, token_id: &i32, event: &TransferEvent) -> Result<()> {
+    let webhooks = storage
+        .matching_webhooks(token_id, &event.from, &event.to, &event.amount)
+        .await?;
+
+    for webhook in webhooks.iter() {
+        for payload in payloads(webhook, event) {
+            storage.add_webhook_delivery(&webhook.id, &payload).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Send due deliveries from storage forever, so retries survive restarts
+/// and are shared between indexer processes
+pub async fn deliver_pending(storage: SharedStorage) {
+    let mut poll = tokio::time::interval(DELIVERY_POLL_INTERVAL);
+
+    loop {
+        poll.tick().await;
+
+        let deliveries = match storage
+            .claim_webhook_deliveries(&MAX_ATTEMPTS, DELIVERY_BATCH, DELIVERY_LEASE)
+            .await
+        {
+            Ok(deliveries) => deliveries,
+            Err(err) => {
+                tracing::error!("Webhook delivery claim error: {}", err);
+                continue;
+            }
+        };
+
+        stream::iter(deliveries)
+            .for_each_concurrent(DELIVERY_CONCURRENCY, |delivery| deliver(&storage, delivery))
+            .await;
+    }
+}
+
+/// Send signed payload once and log the attempt with time of the next one
+async fn deliver(storage: &SharedStorage, delivery: DueWebhookDelivery) {
+    let signature = sign(&delivery.secret, &delivery.payload);
+    let attempt = delivery.attempts + 1;
+
+    let response = client()
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Id", delivery.webhook_id.to_string())
+        .header("X-Webhook-Signature", format!("sha256={signature}"))
+        .body(delivery.payload)
+        .send()
+        .await;
+
+    let (status_code, delivered, error) = match response {
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            response.status().is_success(),
+            None,
+        ),
+        Err(err) => (None, false, Some(err.to_string())),
+    };
+
+    tracing::debug!(
+        "Webhook: Id: {}; Delivery: {}; Attempt: {}; Status: {:?};",
+        delivery.webhook_id,
+        delivery.delivery_id,
+        attempt,
+        status_code
+    );
+
+    if let Err(err) = storage
+        .update_webhook_delivery(
+            &delivery.delivery_id,
+            &attempt,
+            status_code,
+            delivered,
+            error.as_deref(),
+            FIRST_RETRY_DELAY * 2u32.pow(attempt as u32 - 1),
+        )
+        .await
+    {
+        tracing::error!("Webhook delivery log error: {}", err);
+    }
+}